@@ -0,0 +1,34 @@
+use std::{
+  path::Path,
+  process::Command,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+  // Docker builds copy the sources without `.git`, so allow passing the hash in.
+  let git_hash = std::env::var("GIT_HASH").ok().filter(|hash| !hash.is_empty()).or_else(|| git(&["rev-parse", "HEAD"]));
+  let build_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+
+  println!("cargo:rustc-env=OCV_GIT_HASH={}", git_hash.unwrap_or_else(|| "unknown".to_string()));
+  println!("cargo:rustc-env=OCV_BUILD_TIMESTAMP={build_timestamp}");
+  println!("cargo:rerun-if-env-changed=GIT_HASH");
+  // A commit moves the branch HEAD points at, not HEAD itself, and `git gc`
+  // moves that ref into packed-refs.
+  let head_ref = git(&["symbolic-ref", "-q", "HEAD"]);
+  for name in ["HEAD", "packed-refs"].into_iter().chain(head_ref.as_deref()) {
+    if let Some(path) = git(&["rev-parse", "--git-path", name]).filter(|path| Path::new(path).exists()) {
+      println!("cargo:rerun-if-changed={path}");
+    }
+  }
+
+  // Use the vendored protoc so builds don't need one installed.
+  std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+  prost_build::compile_protos(&["proto/tally_result.proto"], &["proto/"]).expect("failed to compile protos");
+  println!("cargo:rerun-if-changed=proto/tally_result.proto");
+}
+
+/// Runs git with `args`, returning its trimmed output if it succeeded.
+fn git(args: &[&str]) -> Option<String> {
+  let output = Command::new("git").args(args).output().ok()?;
+  output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -1,43 +1,184 @@
-use anyhow::{Context, Result};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
 use diesel::{
-  PgConnection, QueryableByName, RunQueryDsl,
+  OptionalExtension, PgConnection, QueryableByName, RunQueryDsl,
   r2d2::ConnectionManager,
   sql_query,
   sql_types::{BigInt, Text},
 };
 use r2d2::Pool;
 
-use crate::{BlockStatus, ChainStatusType};
+use crate::{BlockStatus, ChainStatusType, LedgerKind, SLOTS_PER_EPOCH, WindowBound};
+
+/// How long `current_epoch_and_slot` reuses its last answer.
+const EPOCH_AND_SLOT_TTL: Duration = Duration::from_secs(30);
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// The last epoch and slot read, and when.
+type CachedEpochAndSlot = Option<(Instant, (u32, u64))>;
 
 #[derive(Clone)]
-pub struct Archive(Pool<ConnectionManager<PgConnection>>);
+pub struct Archive {
+  /// Pool of the primary database. Opens no connections until used when a
+  /// replica takes the reads.
+  pool: PgPool,
+  /// Pool of a read replica, which takes every read query when set.
+  replica: Option<PgPool>,
+  epoch_and_slot: Arc<Mutex<CachedEpochAndSlot>>,
+  ledger_hashes: Arc<Mutex<HashMap<(u32, LedgerKind), String>>>,
+  allow_non_self_send_votes: bool,
+}
 
 impl Archive {
-  pub fn new(archive_database_url: &String) -> Self {
-    let archive_manager = ConnectionManager::<PgConnection>::new(archive_database_url);
-    let pool = Pool::builder()
-      .test_on_check_out(true)
-      .build(archive_manager)
-      .unwrap_or_else(|_| panic!("Error: failed to build `archive` connection pool"));
-    Self(pool)
+  /// Connects to the archive database at `archive_database_url`, sending
+  /// read queries to the read replica at `replica_database_url` when set,
+  /// keeping load off the primary.
+  pub fn new(archive_database_url: &str, replica_database_url: Option<&str>) -> Self {
+    let (pool, replica) = match replica_database_url {
+      None => (build_pool(archive_database_url, "archive"), None),
+      Some(replica_database_url) => {
+        (build_lazy_pool(archive_database_url), Some(build_pool(replica_database_url, "archive replica")))
+      }
+    };
+    Self {
+      pool,
+      replica,
+      epoch_and_slot: Arc::default(),
+      ledger_hashes: Arc::default(),
+      allow_non_self_send_votes: false,
+    }
+  }
+
+  /// The pool read queries use: the replica's if there is one.
+  fn read_pool(&self) -> &PgPool {
+    self.replica.as_ref().unwrap_or(&self.pool)
+  }
+
+  /// Also treats payments to another account as vote candidates, for
+  /// experimental voting schemes. Votes are self-sends by default.
+  pub fn with_allow_non_self_send_votes(mut self, allow_non_self_send_votes: bool) -> Self {
+    self.allow_non_self_send_votes = allow_non_self_send_votes;
+    self
   }
 
   pub fn fetch_chain_tip(&self) -> Result<i64> {
-    let connection = &mut self.0.get().context("failed to get archive db connection")?;
+    let connection = &mut self.read_pool().get().context("failed to get archive db connection")?;
     let result = sql_query("SELECT MAX(height) FROM blocks").get_result::<FetchChainTipResult>(connection)?;
     Ok(result.max)
   }
 
   pub fn fetch_latest_slot(&self) -> Result<i64> {
-    let connection = &mut self.0.get().context("failed to get archive db connection")?;
+    let connection = &mut self.read_pool().get().context("failed to get archive db connection")?;
     let result = sql_query("SELECT MAX(global_slot) FROM blocks").get_result::<FetchLatestSlotResult>(connection)?;
     Ok(result.max)
   }
 
+  /// Epoch and global slot of the latest canonical block, cached for
+  /// `EPOCH_AND_SLOT_TTL`.
+  pub fn current_epoch_and_slot(&self) -> Result<(u32, u64)> {
+    let mut cached = self.epoch_and_slot.lock().expect("epoch and slot lock poisoned");
+    if let Some((fetched_at, epoch_and_slot)) = *cached {
+      if fetched_at.elapsed() < EPOCH_AND_SLOT_TTL {
+        return Ok(epoch_and_slot);
+      }
+    }
+    let connection = &mut self.read_pool().get().context("failed to get archive db connection")?;
+    let block = sql_query(LATEST_CANONICAL_BLOCK_QUERY).get_result::<LatestCanonicalBlockResult>(connection)?;
+    let epoch_and_slot = block.epoch_and_slot();
+    *cached = Some((Instant::now(), epoch_and_slot));
+    Ok(epoch_and_slot)
+  }
+
+  /// Hash of the staking ledger of `epoch`, read from the epoch data of a
+  /// block in that epoch.
+  pub fn staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.epoch_ledger_hash(epoch, LedgerKind::Staking)
+  }
+
+  /// Hash of the ledger blocks of `epoch` name as the next epoch's staking
+  /// ledger.
+  pub fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.epoch_ledger_hash(epoch, LedgerKind::NextStaking)
+  }
+
+  /// Looks up a ledger hash of `epoch`, cached per epoch. The next staking
+  /// ledger is only cached once the epoch is over, as it may change until
+  /// then.
+  fn epoch_ledger_hash(&self, epoch: u32, kind: LedgerKind) -> Result<String> {
+    if let Some(hash) = self.ledger_hashes.lock().expect("ledger hashes lock poisoned").get(&(epoch, kind)) {
+      return Ok(hash.clone());
+    }
+    let connection = &mut self.read_pool().get().context("failed to get archive db connection")?;
+    let start = i64::from(epoch) * SLOTS_PER_EPOCH;
+    let hash = sql_query(epoch_ledger_hash_query(kind))
+      .bind::<BigInt, _>(start)
+      .bind::<BigInt, _>(start + SLOTS_PER_EPOCH)
+      .get_result::<EpochLedgerHashResult>(connection)
+      .optional()?
+      .ok_or_else(|| anyhow!("No block of epoch {} in the archive db", epoch))?
+      .ledger_hash;
+    if kind == LedgerKind::Staking || epoch < self.current_epoch_and_slot()?.0 {
+      self.ledger_hashes.lock().expect("ledger hashes lock poisoned").insert((epoch, kind), hash.clone());
+    }
+    Ok(hash)
+  }
+
   pub fn fetch_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>> {
-    let connection = &mut self.0.get().context("failed to get archive db connection")?;
-    let results = sql_query(
-      "SELECT DISTINCT pk.value as account, uc.memo as memo, uc.nonce as nonce, uc.hash as hash, b.height as height, b.chain_status as status, b.timestamp::bigint as timestamp
+    self.fetch_transactions_in_window(WindowBound::Timestamp(start_time), WindowBound::Timestamp(end_time))
+  }
+
+  /// Fetches candidate vote transactions between two window bounds, each of
+  /// which may be a timestamp or a global slot.
+  pub fn fetch_transactions_in_window(
+    &self,
+    start: WindowBound,
+    end: WindowBound,
+  ) -> Result<Vec<FetchTransactionResult>> {
+    let connection = &mut self.read_pool().get().context("failed to get archive db connection")?;
+    let query = format!(
+      "{FETCH_TRANSACTIONS_QUERY}
+      {}
+      AND {} >= $1
+      AND {} <= $2",
+      vote_candidate_filter(self.allow_non_self_send_votes),
+      window_column(start),
+      window_column(end)
+    );
+    let results = sql_query(query)
+      .bind::<BigInt, _>(window_value(start))
+      .bind::<BigInt, _>(window_value(end))
+      .get_results(connection)?;
+    tracing::info!("Fetched {} transactions from archive db between {:?} and {:?}", results.len(), start, end);
+    Ok(results)
+  }
+}
+
+fn build_pool(database_url: &str, name: &str) -> PgPool {
+  Pool::builder()
+    .test_on_check_out(true)
+    .build(ConnectionManager::<PgConnection>::new(database_url))
+    .unwrap_or_else(|_| panic!("Error: failed to build `{name}` connection pool"))
+}
+
+/// A pool that connects on first use rather than up front, and keeps no idle
+/// connections.
+fn build_lazy_pool(database_url: &str) -> PgPool {
+  Pool::builder()
+    .test_on_check_out(true)
+    .min_idle(Some(0))
+    .build_unchecked(ConnectionManager::<PgConnection>::new(database_url))
+}
+
+const LATEST_CANONICAL_BLOCK_QUERY: &str =
+  "SELECT global_slot FROM blocks WHERE chain_status = 'canonical' ORDER BY height DESC LIMIT 1";
+
+const FETCH_TRANSACTIONS_QUERY: &str = "SELECT DISTINCT pk.value as account, pkr.value as receiver, uc.memo as memo, uc.nonce as nonce, uc.hash as hash, b.height as height, b.chain_status as status, b.timestamp::bigint as timestamp, b.global_slot as global_slot, buc.sequence_no::bigint as sequence_no
       FROM user_commands AS uc
       JOIN blocks_user_commands AS buc
       ON uc.id = buc.user_command_id
@@ -45,15 +186,68 @@ impl Archive {
       ON buc.block_id = b.id
       JOIN public_keys AS pk
       ON uc.source_id = pk.id
+      JOIN public_keys AS pkr
+      ON uc.receiver_id = pkr.id
       WHERE uc.command_type = 'payment'
-      AND uc.source_id = uc.receiver_id
       AND NOT b.chain_status = 'orphaned'
-      AND buc.status = 'applied'
-      AND b.timestamp::bigint BETWEEN $1 AND $2"
-    );
-    let results = results.bind::<BigInt, _>(start_time).bind::<BigInt, _>(end_time).get_results(connection)?;
-    tracing::info!("Fetched {} transactions from archive db between {} and {}", results.len(), start_time, end_time);
-    Ok(results)
+      AND buc.status = 'applied'";
+
+/// Selects a ledger hash from the epoch data of the latest block in a global
+/// slot range, preferring canonical blocks.
+fn epoch_ledger_hash_query(kind: LedgerKind) -> String {
+  let epoch_data_column = match kind {
+    LedgerKind::Staking => "staking_epoch_data_id",
+    LedgerKind::NextStaking => "next_epoch_data_id",
+  };
+  format!(
+    "SELECT slh.value as ledger_hash
+      FROM blocks AS b
+      JOIN epoch_data AS ed
+      ON ed.id = b.{epoch_data_column}
+      JOIN snarked_ledger_hashes AS slh
+      ON slh.id = ed.ledger_hash_id
+      WHERE NOT b.chain_status = 'orphaned'
+      AND b.global_slot >= $1
+      AND b.global_slot < $2
+      ORDER BY b.chain_status = 'canonical' DESC, b.height DESC
+      LIMIT 1"
+  )
+}
+
+/// Base58 encoding of the empty memo; transactions carrying it can't vote.
+pub const EMPTY_MEMO: &str = "E4YM2vTHhWEg66xpj52JErHUBU4pZ1yageL4TVDDpTTSsv8mK6YaH";
+
+/// Conditions a transaction must meet to be read as a vote: a non-empty memo
+/// and, unless relaxed, a payment to the sender itself.
+fn vote_candidate_filter(allow_non_self_send_votes: bool) -> String {
+  match allow_non_self_send_votes {
+    true => format!("AND uc.memo <> '{EMPTY_MEMO}'"),
+    false => format!("AND uc.source_id = uc.receiver_id\n      AND uc.memo <> '{EMPTY_MEMO}'"),
+  }
+}
+
+fn window_column(bound: WindowBound) -> &'static str {
+  match bound {
+    WindowBound::Timestamp(_) => "b.timestamp::bigint",
+    WindowBound::Slot(_) => "b.global_slot",
+  }
+}
+
+fn window_value(bound: WindowBound) -> i64 {
+  match bound {
+    WindowBound::Timestamp(value) | WindowBound::Slot(value) => value,
+  }
+}
+
+impl FetchTransactionResult {
+  /// Epoch of the block the transaction was included in.
+  pub fn epoch(&self) -> i64 {
+    i64::from(epoch_and_slot(self.global_slot).0)
+  }
+
+  /// Mirrors `vote_candidate_filter` for archives that don't run the SQL.
+  pub fn is_vote_candidate(&self, allow_non_self_send_votes: bool) -> bool {
+    self.memo != EMPTY_MEMO && (allow_non_self_send_votes || self.account == self.receiver)
   }
 }
 
@@ -70,10 +264,34 @@ pub struct FetchLatestSlotResult {
 }
 
 #[derive(QueryableByName)]
+pub struct LatestCanonicalBlockResult {
+  #[diesel(sql_type = BigInt)]
+  pub global_slot: i64,
+}
+
+#[derive(QueryableByName)]
+pub struct EpochLedgerHashResult {
+  #[diesel(sql_type = Text)]
+  pub ledger_hash: String,
+}
+
+impl LatestCanonicalBlockResult {
+  pub fn epoch_and_slot(&self) -> (u32, u64) {
+    epoch_and_slot(self.global_slot)
+  }
+}
+
+fn epoch_and_slot(global_slot: i64) -> (u32, u64) {
+  ((global_slot / SLOTS_PER_EPOCH) as u32, global_slot as u64)
+}
+
+#[derive(QueryableByName, Clone)]
 pub struct FetchTransactionResult {
   #[diesel(sql_type = Text)]
   pub account: String,
   #[diesel(sql_type = Text)]
+  pub receiver: String,
+  #[diesel(sql_type = Text)]
   pub hash: String,
   #[diesel(sql_type = Text)]
   pub memo: String,
@@ -85,12 +303,21 @@ pub struct FetchTransactionResult {
   pub timestamp: i64,
   #[diesel(sql_type = BigInt)]
   pub nonce: i64,
+  #[diesel(sql_type = BigInt)]
+  pub global_slot: i64,
+  /// Position of the transaction within its block.
+  #[diesel(sql_type = BigInt)]
+  pub sequence_no: i64,
 }
 
 pub trait ArchiveInterface {
   fn fetch_chain_tip(&self) -> Result<i64>;
   fn fetch_latest_slot(&self) -> Result<i64>;
+  fn current_epoch_and_slot(&self) -> Result<(u32, u64)>;
+  fn staking_ledger_hash(&self, epoch: u32) -> Result<String>;
+  fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String>;
   fn fetch_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>>;
+  fn fetch_transactions_in_window(&self, start: WindowBound, end: WindowBound) -> Result<Vec<FetchTransactionResult>>;
 }
 
 impl ArchiveInterface for Archive {
@@ -102,9 +329,25 @@ impl ArchiveInterface for Archive {
     self.fetch_latest_slot()
   }
 
+  fn current_epoch_and_slot(&self) -> Result<(u32, u64)> {
+    self.current_epoch_and_slot()
+  }
+
+  fn staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.staking_ledger_hash(epoch)
+  }
+
+  fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.next_staking_ledger_hash(epoch)
+  }
+
   fn fetch_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>> {
     self.fetch_transactions(start_time, end_time)
   }
+
+  fn fetch_transactions_in_window(&self, start: WindowBound, end: WindowBound) -> Result<Vec<FetchTransactionResult>> {
+    self.fetch_transactions_in_window(start, end)
+  }
 }
 
 pub struct MockArchive;
@@ -118,23 +361,197 @@ impl ArchiveInterface for MockArchive {
     Ok(200) // Return a mock value for the latest slot
   }
 
+  fn current_epoch_and_slot(&self) -> Result<(u32, u64)> {
+    Ok(epoch_and_slot(self.fetch_latest_slot()?))
+  }
+
+  fn staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    Ok(format!("jxMockStaking{epoch}"))
+  }
+
+  fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    Ok(format!("jxMockNextStaking{epoch}"))
+  }
+
   fn fetch_transactions(&self, start_time: i64, _end_time: i64) -> Result<Vec<FetchTransactionResult>> {
     Ok(vec![FetchTransactionResult {
       account: "mock_account".to_string(),
+      receiver: "mock_account".to_string(),
       hash: "mock_hash".to_string(),
       memo: "mock_memo".to_string(),
       height: 1,
       status: BlockStatus::Pending, // Use a mock value
       timestamp: start_time + 1000,
       nonce: 42,
+      global_slot: 200,
+      sequence_no: 0,
     }]) // Return a mock list of transactions
   }
+
+  fn fetch_transactions_in_window(&self, start: WindowBound, end: WindowBound) -> Result<Vec<FetchTransactionResult>> {
+    self.fetch_transactions(window_value(start), window_value(end))
+  }
+}
+
+/// Archive returning a canned chain state and set of votes, for tests that
+/// can't reach a database.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone, Default)]
+pub struct StubArchive {
+  pub chain_tip: i64,
+  pub latest_slot: i64,
+  pub transactions: Vec<FetchTransactionResult>,
+  pub ledger_hashes: HashMap<(u32, LedgerKind), String>,
+  pub allow_non_self_send_votes: bool,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl StubArchive {
+  pub fn new(chain_tip: i64, latest_slot: i64) -> Self {
+    StubArchive {
+      chain_tip,
+      latest_slot,
+      transactions: Vec::new(),
+      ledger_hashes: HashMap::new(),
+      allow_non_self_send_votes: false,
+    }
+  }
+
+  /// Records `hash` as the `kind` ledger of `epoch`.
+  pub fn with_ledger_hash(mut self, epoch: u32, kind: LedgerKind, hash: &str) -> Self {
+    self.ledger_hashes.insert((epoch, kind), hash.to_string());
+    self
+  }
+
+  fn ledger_hash(&self, epoch: u32, kind: LedgerKind) -> Result<String> {
+    let hash = self.ledger_hashes.get(&(epoch, kind)).cloned();
+    hash.ok_or_else(|| anyhow!("No block of epoch {} in the stub archive", epoch))
+  }
+
+  /// Adds a vote transaction, encoding `memo` the way it is stored on chain.
+  pub fn with_vote(self, account: &str, memo: &str, height: i64, status: BlockStatus, timestamp: i64) -> Self {
+    self.with_payment(account, account, memo, height, status, timestamp)
+  }
+
+  /// Adds a payment from `account` to `receiver`.
+  pub fn with_payment(
+    mut self,
+    account: &str,
+    receiver: &str,
+    memo: &str,
+    height: i64,
+    status: BlockStatus,
+    timestamp: i64,
+  ) -> Self {
+    let mut payload = vec![0x01, memo.len() as u8];
+    payload.extend_from_slice(memo.as_bytes());
+    payload.resize(34, 0);
+    self.transactions.push(FetchTransactionResult {
+      account: account.to_string(),
+      receiver: receiver.to_string(),
+      hash: format!("{}-{}", account, self.transactions.len()),
+      memo: bs58::encode(payload).with_check_version(0x14).into_string(),
+      height,
+      status,
+      timestamp,
+      nonce: self.transactions.len() as i64,
+      global_slot: 0,
+      sequence_no: 0,
+    });
+    self
+  }
+
+  /// Like `with_vote`, for a vote included at `global_slot`.
+  pub fn with_vote_at_slot(
+    self,
+    account: &str,
+    memo: &str,
+    height: i64,
+    status: BlockStatus,
+    timestamp: i64,
+    global_slot: i64,
+  ) -> Self {
+    let mut archive = self.with_vote(account, memo, height, status, timestamp);
+    if let Some(transaction) = archive.transactions.last_mut() {
+      transaction.global_slot = global_slot;
+    }
+    archive
+  }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ArchiveInterface for StubArchive {
+  fn fetch_chain_tip(&self) -> Result<i64> {
+    Ok(self.chain_tip)
+  }
+
+  fn fetch_latest_slot(&self) -> Result<i64> {
+    Ok(self.latest_slot)
+  }
+
+  fn current_epoch_and_slot(&self) -> Result<(u32, u64)> {
+    Ok(epoch_and_slot(self.latest_slot))
+  }
+
+  fn staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.ledger_hash(epoch, LedgerKind::Staking)
+  }
+
+  fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+    self.ledger_hash(epoch, LedgerKind::NextStaking)
+  }
+
+  fn fetch_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>> {
+    Ok(
+      self
+        .transactions
+        .iter()
+        .filter(|tx| tx.timestamp >= start_time && tx.timestamp <= end_time)
+        .filter(|tx| tx.is_vote_candidate(self.allow_non_self_send_votes))
+        .cloned()
+        .collect(),
+    )
+  }
+
+  /// Slot windows return every candidate, as canned votes carry no slot.
+  fn fetch_transactions_in_window(&self, start: WindowBound, end: WindowBound) -> Result<Vec<FetchTransactionResult>> {
+    match (start, end) {
+      (WindowBound::Timestamp(start), WindowBound::Timestamp(end)) => self.fetch_transactions(start, end),
+      _ => self.fetch_transactions(i64::MIN, i64::MAX),
+    }
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  #[test]
+  fn test_reads_use_replica() {
+    // Pools are told apart by size, as unchecked pools don't connect.
+    let pool = |max_size| {
+      Pool::builder().max_size(max_size).build_unchecked(ConnectionManager::new("postgres://localhost/archive"))
+    };
+    let mut archive = Archive {
+      pool: pool(1),
+      replica: None,
+      epoch_and_slot: Arc::default(),
+      ledger_hashes: Arc::default(),
+      allow_non_self_send_votes: false,
+    };
+    assert_eq!(archive.read_pool().max_size(), 1);
+
+    archive.replica = Some(pool(2));
+    assert_eq!(archive.read_pool().max_size(), 2);
+  }
+
+  #[test]
+  fn test_lazy_primary_with_replica() {
+    // Nothing listens on the port, and building the pool doesn't notice.
+    let pool = build_lazy_pool("postgres://localhost:1/archive");
+    assert_eq!(pool.state().connections, 0);
+  }
+
   #[test]
   fn test_fetch_chain_tip() {
     let archive = MockArchive;
@@ -156,4 +573,62 @@ mod tests {
     assert_eq!(transactions.len(), 1);
     assert_eq!(transactions[0].account, "mock_account");
   }
+
+  #[test]
+  fn test_current_epoch_and_slot() {
+    // Latest canonical block row of mainnet at slot 378_520 (epoch 53).
+    let block = LatestCanonicalBlockResult { global_slot: 378_520 };
+    assert_eq!(block.epoch_and_slot(), (53, 378_520));
+    assert_eq!(LatestCanonicalBlockResult { global_slot: 7_139 }.epoch_and_slot(), (0, 7_139));
+    assert_eq!(LatestCanonicalBlockResult { global_slot: 7_140 }.epoch_and_slot(), (1, 7_140));
+
+    assert_eq!(MockArchive.current_epoch_and_slot().unwrap(), (0, 200));
+  }
+
+  #[test]
+  fn test_stub_archive() {
+    let archive = StubArchive::new(100, 200)
+      .with_vote("A", "cftest-2", 10, BlockStatus::Canonical, 1000)
+      .with_vote("B", "no cftest-2", 11, BlockStatus::Pending, 5000);
+
+    let transactions = archive.fetch_transactions(0, 2000).unwrap();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].memo, "E4YdLeukpqzqyBAxujeELx9SZWoUW9MhcUfnGHF9PhQmxTJcpmj7j");
+    assert_eq!(archive.fetch_transactions_in_window(WindowBound::Slot(0), WindowBound::Slot(1)).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_epoch_ledger_hash() {
+    let staking = epoch_ledger_hash_query(LedgerKind::Staking);
+    assert!(staking.contains("ON ed.id = b.staking_epoch_data_id"));
+    assert!(epoch_ledger_hash_query(LedgerKind::NextStaking).contains("ON ed.id = b.next_epoch_data_id"));
+
+    let hash = "jxQXzUkst2L9Ma9g9YQ3kfpgB5v5Znr1vrYb1mupakc5y7T89H8";
+    let archive = StubArchive::new(100, 378_520).with_ledger_hash(53, LedgerKind::Staking, hash);
+    assert_eq!(archive.staking_ledger_hash(53).unwrap(), hash);
+    assert!(archive.next_staking_ledger_hash(53).is_err());
+    assert!(archive.staking_ledger_hash(54).is_err());
+  }
+
+  #[test]
+  fn test_vote_candidate_filter() {
+    let strict = vote_candidate_filter(false);
+    assert!(strict.contains("uc.source_id = uc.receiver_id"));
+    assert!(strict.contains(EMPTY_MEMO));
+    let relaxed = vote_candidate_filter(true);
+    assert!(!relaxed.contains("receiver_id"));
+    assert!(relaxed.contains(EMPTY_MEMO));
+
+    // A payment to someone else carrying a keyword memo, and an empty self-send.
+    let archive = StubArchive::new(100, 200)
+      .with_vote("A", "cftest-2", 10, BlockStatus::Canonical, 1000)
+      .with_payment("B", "C", "cftest-2", 11, BlockStatus::Canonical, 1000)
+      .with_vote("D", "", 12, BlockStatus::Canonical, 1000);
+    assert_eq!(archive.transactions[2].memo, EMPTY_MEMO);
+    let accounts = |archive: &StubArchive| {
+      archive.fetch_transactions(0, 2000).unwrap().into_iter().map(|tx| tx.account).collect::<Vec<_>>()
+    };
+    assert_eq!(accounts(&archive), ["A"]);
+    assert_eq!(accounts(&StubArchive { allow_non_self_send_votes: true, ..archive }), ["A", "B"]);
+  }
 }
@@ -0,0 +1,274 @@
+use std::{
+  collections::HashMap,
+  fmt::Write as _,
+  fs::{self, OpenOptions},
+  io::Write,
+  path::PathBuf,
+  sync::Mutex,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{BlockStatus, VoteWithWeight, storage::StorageProvider};
+
+/// Tracing target audit records are emitted under.
+pub const AUDIT_TARGET: &str = "audit";
+
+/// How a final tally was produced, kept as an append-only trail.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TallyAuditRecord {
+  /// Increases by one with each record. Continues from the audit file, if
+  /// any, across restarts.
+  pub sequence: u64,
+  pub recorded_at: DateTime<Utc>,
+  pub proposal_id: usize,
+  /// The keyword voters put in their memo.
+  pub proposal_key: String,
+  pub ledger_hashes: Vec<String>,
+  pub chain_tip: i64,
+  #[serde(flatten)]
+  pub inputs: AuditInputs,
+  pub positive_votes: usize,
+  pub negative_votes: usize,
+  pub positive_stake_weight: Decimal,
+  pub negative_stake_weight: Decimal,
+  pub total_stake_weight: Decimal,
+  /// `Insufficient voters`, `Approved` or `Rejected`.
+  pub outcome: String,
+}
+
+/// What a final tally counted: the block height range of its votes, and a
+/// digest of its ledgers and votes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditInputs {
+  pub min_block_height: Option<i64>,
+  pub max_block_height: Option<i64>,
+  /// Hex SHA-256 over the ledger hashes and each counted vote's transaction
+  /// hash, account, memo, height and weight, with votes in transaction hash
+  /// order.
+  pub input_digest: String,
+}
+
+impl AuditInputs {
+  /// Describes the votes of a final tally; pending votes aren't counted.
+  pub fn of(ledger_hashes: &[String], votes: &[VoteWithWeight]) -> Self {
+    let mut counted: Vec<&VoteWithWeight> = votes.iter().filter(|vote| vote.status != BlockStatus::Pending).collect();
+    counted.sort_by(|a, b| a.hash.cmp(&b.hash));
+    let mut hasher = Sha256::new();
+    for hash in ledger_hashes {
+      hasher.update(format!("ledger:{hash}\n"));
+    }
+    for vote in &counted {
+      let (hash, account, memo, height, weight) = (&vote.hash, &vote.account, &vote.memo, vote.height, vote.weight);
+      hasher.update(format!("vote:{hash}:{account}:{memo}:{height}:{weight}\n"));
+    }
+    AuditInputs {
+      min_block_height: counted.iter().map(|vote| vote.height).min(),
+      max_block_height: counted.iter().map(|vote| vote.height).max(),
+      input_digest: hasher.finalize().iter().fold(String::new(), |mut digest, byte| {
+        let _ = write!(digest, "{byte:02x}");
+        digest
+      }),
+    }
+  }
+}
+
+/// Numbers audit records and writes them to the `audit` tracing target.
+///
+/// Records also go as JSON lines to `path`, and as one object per record
+/// under `key_prefix`, when set. A record with the same inputs as the last
+/// one of its proposal is skipped.
+#[derive(Default)]
+pub struct AuditLog {
+  path: Option<PathBuf>,
+  key_prefix: Option<String>,
+  state: Mutex<AuditState>,
+}
+
+#[derive(Default)]
+struct AuditState {
+  next_sequence: u64,
+  /// Input digest of the last record of each proposal.
+  last_digests: HashMap<usize, String>,
+}
+
+impl AuditLog {
+  /// Opens the audit log, continuing the sequence of the records already in
+  /// `path`. Lines that don't parse, such as one cut short by a crash, are
+  /// logged and skipped; a missing final newline is restored so the next
+  /// record starts on its own line.
+  pub fn open(path: Option<PathBuf>, key_prefix: Option<String>) -> Result<Self> {
+    let mut state = AuditState::default();
+    if let Some(path) = path.as_ref().filter(|path| path.exists()) {
+      let contents = fs::read_to_string(path)?;
+      for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str::<TallyAuditRecord>(line) {
+          Ok(record) => {
+            state.next_sequence = state.next_sequence.max(record.sequence + 1);
+            state.last_digests.insert(record.proposal_id, record.inputs.input_digest);
+          }
+          Err(err) => tracing::warn!("Skipping line {} of audit log {}: {}", number + 1, path.display(), err),
+        }
+      }
+      if !contents.is_empty() && !contents.ends_with('\n') {
+        writeln!(OpenOptions::new().append(true).open(path)?)?;
+      }
+    }
+    Ok(AuditLog { path, key_prefix, state: Mutex::new(state) })
+  }
+
+  /// Numbers `record` and writes it to every sink, returning it as written,
+  /// or `None` if its inputs are those of the last record of its proposal.
+  pub async fn append(
+    &self,
+    mut record: TallyAuditRecord,
+    storage: &(dyn StorageProvider + Send + Sync),
+    bucket: &str,
+  ) -> Result<Option<TallyAuditRecord>> {
+    let line = {
+      // Held while appending, so the file stays in sequence order.
+      let mut state = self.state.lock().expect("audit log lock poisoned");
+      if state.last_digests.get(&record.proposal_id) == Some(&record.inputs.input_digest) {
+        return Ok(None);
+      }
+      record.sequence = state.next_sequence;
+      let line = serde_json::to_string(&record)?;
+      if let Some(path) = &self.path {
+        writeln!(OpenOptions::new().create(true).append(true).open(path)?, "{line}")?;
+      }
+      state.next_sequence += 1;
+      state.last_digests.insert(record.proposal_id, record.inputs.input_digest.clone());
+      line
+    };
+    tracing::info!(
+      target: AUDIT_TARGET,
+      sequence = record.sequence,
+      proposal_id = record.proposal_id,
+      input_digest = %record.inputs.input_digest,
+      "{}",
+      line
+    );
+    match &self.key_prefix {
+      Some(_) if !storage.capabilities().can_write => {
+        tracing::info!(
+          "{} is read-only, not uploading audit record {} of proposal {}",
+          storage.provider_name(),
+          record.sequence,
+          record.proposal_id
+        );
+      }
+      Some(prefix) => {
+        let key = format!("{}{:012}-{}.json", prefix, record.sequence, record.proposal_id);
+        storage.put_object(bucket, &key, line.into()).await?;
+      }
+      None => {}
+    }
+    Ok(Some(record))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{MemoryProvider, Vote};
+
+  fn vote(hash: &str, height: i64, status: BlockStatus) -> VoteWithWeight {
+    Vote::new("B62qA", hash, "cftest-2", height, status, 1000, 0).to_weighted(Decimal::from(10))
+  }
+
+  /// A record of proposal 1 counting the vote `hash`.
+  fn record(hash: &str) -> TallyAuditRecord {
+    TallyAuditRecord {
+      sequence: 0,
+      recorded_at: DateTime::from_timestamp_millis(9001).unwrap(),
+      proposal_id: 1,
+      proposal_key: "cftest-2".to_string(),
+      ledger_hashes: vec!["jxTest".to_string()],
+      chain_tip: 200,
+      inputs: AuditInputs::of(&["jxTest".to_string()], &[vote(hash, 100, BlockStatus::Canonical)]),
+      positive_votes: 1,
+      negative_votes: 0,
+      positive_stake_weight: Decimal::from(10),
+      negative_stake_weight: Decimal::ZERO,
+      total_stake_weight: Decimal::from(10),
+      outcome: "Insufficient voters".to_string(),
+    }
+  }
+
+  #[test]
+  fn test_audit_inputs() {
+    let ledgers = ["jxTest".to_string()];
+    let votes = [vote("b", 120, BlockStatus::Canonical), vote("a", 100, BlockStatus::Canonical)];
+    let inputs = AuditInputs::of(&ledgers, &votes);
+    assert_eq!((inputs.min_block_height, inputs.max_block_height), (Some(100), Some(120)));
+    assert_eq!(inputs.input_digest.len(), 64);
+
+    // Vote order and pending votes don't change the digest; ledgers do.
+    let reordered = [votes[1].clone(), vote("c", 150, BlockStatus::Pending), votes[0].clone()];
+    assert_eq!(AuditInputs::of(&ledgers, &reordered), inputs);
+    assert_ne!(AuditInputs::of(&["jxOther".to_string()], &votes).input_digest, inputs.input_digest);
+    assert_eq!(AuditInputs::of(&ledgers, &[]).min_block_height, None);
+  }
+
+  #[tokio::test]
+  async fn test_audit_log_append() {
+    let path = std::env::temp_dir().join(format!("ocv-audit-{}.jsonl", std::process::id()));
+    let storage = MemoryProvider::new();
+    let log = AuditLog::open(Some(path.clone()), Some("audit/".to_string())).unwrap();
+    let sequence = |record: Option<TallyAuditRecord>| record.map(|record| record.sequence);
+    assert_eq!(sequence(log.append(record("a"), &storage, "results").await.unwrap()), Some(0));
+    assert_eq!(sequence(log.append(record("b"), &storage, "results").await.unwrap()), Some(1));
+    // Recomputing a tally with the same inputs doesn't add a record.
+    assert_eq!(sequence(log.append(record("b"), &storage, "results").await.unwrap()), None);
+
+    // A reopened log continues the sequence and remembers the last inputs.
+    let reopened = AuditLog::open(Some(path.clone()), None).unwrap();
+    assert_eq!(sequence(reopened.append(record("b"), &storage, "results").await.unwrap()), None);
+    assert_eq!(sequence(reopened.append(record("a"), &storage, "results").await.unwrap()), Some(2));
+    let lines = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let sequences: Vec<u64> =
+      lines.lines().map(|line| serde_json::from_str::<TallyAuditRecord>(line).unwrap().sequence).collect();
+    assert_eq!(sequences, [0, 1, 2]);
+    assert_eq!(storage.list_objects("results", Some("audit/")).await.unwrap(), [
+      "audit/000000000000-1.json",
+      "audit/000000000001-1.json"
+    ]);
+  }
+
+  #[tokio::test]
+  async fn test_audit_log_read_only_storage() {
+    // Uploads to read-only storage are skipped rather than failing the append.
+    let storage = crate::HttpProvider::new(reqwest::Client::new(), "http://127.0.0.1:9/", None);
+    let log = AuditLog::open(None, Some("audit/".to_string())).unwrap();
+    let record = log.append(record("a"), &storage, "results").await.unwrap();
+    assert_eq!(record.map(|record| record.sequence), Some(0));
+  }
+
+  #[tokio::test]
+  async fn test_audit_log_corrupt_line() {
+    let path = std::env::temp_dir().join(format!("ocv-audit-corrupt-{}.jsonl", std::process::id()));
+    let storage = MemoryProvider::new();
+    let log = AuditLog::open(Some(path.clone()), None).unwrap();
+    log.append(record("a"), &storage, "results").await.unwrap();
+    // A record cut short by a crash.
+    let line = serde_json::to_string(&record("b")).unwrap();
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(&line.as_bytes()[.. 40]).unwrap();
+
+    let reopened = AuditLog::open(Some(path.clone()), None).unwrap();
+    let appended = reopened.append(record("c"), &storage, "results").await.unwrap();
+    let lines = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(appended.map(|record| record.sequence), Some(1));
+    let records: Vec<Result<TallyAuditRecord, _>> = lines.lines().map(serde_json::from_str).collect();
+    assert_eq!(records.len(), 3);
+    assert!(records[1].is_err());
+    assert_eq!(records[2].as_ref().unwrap().sequence, 1);
+  }
+}
@@ -1,8 +1,35 @@
 use anyhow::Result;
-use clap::Parser;
-use mina_ocv::ServeArgs;
+use clap::{CommandFactory, FromArgMatches, Subcommand};
+use mina_ocv::{CacheArgs, ExportArgs, ProposalsArgs, ServeArgs};
+
+/// Serves the API, or runs a maintenance command.
+#[derive(Subcommand)]
+enum Command {
+  /// Manage the on-disk ledger cache.
+  Cache(CacheArgs),
+  /// Export proposal data.
+  Export(Box<ExportArgs>),
+  /// Inspect the proposals manifest.
+  Proposals(ProposalsArgs),
+}
+
+fn cli() -> clap::Command {
+  let serve = ServeArgs::command()
+    .name("mina_ocv")
+    .args_conflicts_with_subcommands(true)
+    .subcommand_negates_reqs(true);
+  Command::augment_subcommands(serve)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  ServeArgs::parse().serve().await
+  let matches = cli().get_matches();
+  match matches.subcommand() {
+    Some(_) => match Command::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()) {
+      Command::Cache(args) => args.run(),
+      Command::Export(args) => args.run().await,
+      Command::Proposals(args) => args.run(),
+    },
+    None => ServeArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()).serve().await,
+  }
 }
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use crate::{clear_ledger_cache, ledger_storage_dir};
+
+#[derive(Clone, Parser)]
+#[command(name = "cache")]
+pub struct CacheArgs {
+  #[command(subcommand)]
+  pub command: CacheCommand,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum CacheCommand {
+  /// Remove cached ledgers from disk.
+  Clear {
+    /// Only remove the ledger with this hash.
+    #[clap(long)]
+    hash: Option<String>,
+    /// List the files that would be removed without removing them.
+    #[clap(long)]
+    dry_run: bool,
+    /// Path to store the ledgers
+    #[clap(long, env, default_value = "/tmp/ledgers")]
+    ledger_storage_path: String,
+  },
+}
+
+impl CacheArgs {
+  pub fn run(&self) -> Result<()> {
+    match &self.command {
+      CacheCommand::Clear { hash, dry_run, ledger_storage_path } => {
+        let response = clear_ledger_cache(&ledger_storage_dir(ledger_storage_path)?, hash.as_deref(), *dry_run)?;
+        println!("{}", serde_json::to_string_pretty(&response)?);
+      }
+    }
+    Ok(())
+  }
+}
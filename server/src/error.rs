@@ -0,0 +1,115 @@
+use axum::http::StatusCode;
+use thiserror::Error;
+
+/// Typed failures of a tally that the HTTP layer maps to specific status
+/// codes. Anything else surfaces as a 500.
+#[derive(Debug, Error)]
+pub enum TallyError {
+  #[error(
+    "Staking ledger {hash} for epoch {} is not available yet",
+    .epoch.map_or_else(|| "unknown".to_string(), |epoch| epoch.to_string())
+  )]
+  LedgerUnavailable { epoch: Option<i64>, hash: String },
+  #[error("Invalid ledger hash '{0}'")]
+  InvalidLedgerHash(String),
+  #[error("No ledger object matches hash {0}")]
+  LedgerNotFound(String),
+  #[error("Proposal {0} not found")]
+  ProposalNotFound(usize),
+  #[error("Proposal {id} is for {proposal_network}, but this server reads votes from a {archive_network} archive")]
+  NetworkMismatch { id: usize, proposal_network: String, archive_network: String },
+  #[error("Archive query {query} timed out after {elapsed_secs:.1}s")]
+  ArchiveTimeout { query: &'static str, elapsed_secs: f64 },
+  #[error("Archive query {query} failed: {message}")]
+  ArchiveUnavailable { query: &'static str, message: String },
+  #[error("{provider} is unavailable after repeated failures; retry in {retry_after_secs}s")]
+  StorageUnavailable { provider: &'static str, retry_after_secs: u64 },
+  #[error(transparent)]
+  LedgerParse(#[from] LedgerParseError),
+  #[error("Request cancelled")]
+  RequestCancelled,
+  #[error("Request deadline exceeded")]
+  DeadlineExceeded,
+}
+
+/// A ledger that downloaded but isn't a valid JSON array or NDJSON ledger.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Malformed ledger{}{}: {message}", describe_key(key), describe_location(*line, *column))]
+pub struct LedgerParseError {
+  /// Bucket object the ledger was downloaded from; empty when unknown.
+  pub key: String,
+  pub line: Option<usize>,
+  pub column: Option<usize>,
+  pub message: String,
+}
+
+impl LedgerParseError {
+  pub fn new(message: impl Into<String>) -> Self {
+    LedgerParseError { key: String::new(), line: None, column: None, message: message.into() }
+  }
+
+  /// Keeps the position serde reports apart from its message.
+  pub fn from_json(err: serde_json::Error) -> Self {
+    if err.line() == 0 {
+      return Self::new(err.to_string());
+    }
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    let message = err.to_string().trim_end_matches(&position).to_string();
+    LedgerParseError { key: String::new(), line: Some(err.line()), column: Some(err.column()), message }
+  }
+
+  pub fn with_key(self, key: impl Into<String>) -> Self {
+    LedgerParseError { key: key.into(), ..self }
+  }
+}
+
+fn describe_key(key: &str) -> String {
+  match key.is_empty() {
+    true => String::new(),
+    false => format!(" object {key}"),
+  }
+}
+
+fn describe_location(line: Option<usize>, column: Option<usize>) -> String {
+  match (line, column) {
+    (Some(line), Some(column)) => format!(" at line {line} column {column}"),
+    (Some(line), None) => format!(" at line {line}"),
+    _ => String::new(),
+  }
+}
+
+impl TallyError {
+  pub fn status_code(&self) -> StatusCode {
+    match self {
+      TallyError::LedgerUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+      TallyError::InvalidLedgerHash(_) => StatusCode::BAD_REQUEST,
+      TallyError::LedgerNotFound(_) => StatusCode::NOT_FOUND,
+      TallyError::ProposalNotFound(_) => StatusCode::NOT_FOUND,
+      TallyError::NetworkMismatch { .. } => StatusCode::CONFLICT,
+      TallyError::ArchiveTimeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+      TallyError::ArchiveUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+      TallyError::StorageUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+      TallyError::LedgerParse(_) => StatusCode::BAD_GATEWAY,
+      // Only seen by clients still listening, e.g. if cancelled by the server.
+      TallyError::RequestCancelled => StatusCode::SERVICE_UNAVAILABLE,
+      TallyError::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+    }
+  }
+
+  /// Seconds after which the client may retry, if the error is transient.
+  pub fn retry_after_secs(&self) -> Option<u64> {
+    match self {
+      TallyError::LedgerUnavailable { .. } => Some(300),
+      TallyError::StorageUnavailable { retry_after_secs, .. } => Some(*retry_after_secs),
+      TallyError::InvalidLedgerHash(_)
+      | TallyError::LedgerNotFound(_)
+      | TallyError::ProposalNotFound(_)
+      | TallyError::NetworkMismatch { .. }
+      | TallyError::ArchiveTimeout { .. }
+      | TallyError::ArchiveUnavailable { .. }
+      | TallyError::LedgerParse(_)
+      | TallyError::RequestCancelled
+      | TallyError::DeadlineExceeded => None,
+    }
+  }
+}
@@ -0,0 +1,209 @@
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::PathBuf,
+  sync::Arc,
+};
+
+use anyhow::Result;
+use arrow::{
+  array::{ArrayRef, Decimal128Array, Int64Array, StringArray},
+  datatypes::{DataType, Field, Schema, SchemaRef},
+  record_batch::RecordBatch,
+};
+use clap::{Parser, Subcommand};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use rust_decimal::Decimal;
+
+use crate::{BlockStatus, OcvConfig, VoteChoice};
+
+/// Rows converted to Arrow and handed to the Parquet writer at a time.
+const BATCH_ROWS: usize = 8192;
+
+/// Decimal places of MINA amounts.
+const STAKE_SCALE: i8 = 9;
+
+/// A counted vote and the stake behind it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoteDetail {
+  pub account: String,
+  pub choice: VoteChoice,
+  /// The voter's own balance.
+  pub stake: Decimal,
+  /// Balances delegated to the voter and counted with its vote.
+  pub delegated_stake: Decimal,
+  /// Stake after the proposal's weighting scheme.
+  pub weight: Decimal,
+  pub block_height: i64,
+  pub status: BlockStatus,
+}
+
+#[derive(Clone, Parser)]
+#[command(name = "export")]
+pub struct ExportArgs {
+  #[command(subcommand)]
+  pub command: ExportCommand,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum ExportCommand {
+  /// Write the per-account votes of a proposal to a Parquet file.
+  Parquet {
+    /// Id of the proposal to export.
+    #[clap(long)]
+    proposal: usize,
+    /// File to write. Defaults to `proposal-<id>-votes.parquet`.
+    #[clap(long, conflicts_with = "object_key")]
+    output: Option<PathBuf>,
+    /// Upload to this key of the ledger bucket instead of writing a file. The
+    /// file is built in memory first.
+    #[clap(long)]
+    object_key: Option<String>,
+    #[command(flatten)]
+    config: OcvConfig,
+  },
+}
+
+impl ExportArgs {
+  pub async fn run(&self) -> Result<()> {
+    match &self.command {
+      ExportCommand::Parquet { proposal, output, object_key, config } => {
+        let ocv = config.to_ocv().await?;
+        let rows = ocv.vote_details(*proposal).await?;
+        match object_key {
+          Some(key) => {
+            let mut bytes = Vec::new();
+            let count = write_vote_details_parquet(rows, &mut bytes)?;
+            ocv.storage_provider.put_object(&ocv.bucket_name, key, bytes.into()).await?;
+            println!("Wrote {} votes to {} in bucket {}", count, key, ocv.bucket_name);
+          }
+          None => {
+            let path = output.clone().unwrap_or_else(|| PathBuf::from(format!("proposal-{proposal}-votes.parquet")));
+            let count = write_vote_details_parquet(rows, BufWriter::new(File::create(&path)?))?;
+            println!("Wrote {} votes to {}", count, path.display());
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn vote_details_schema() -> SchemaRef {
+  let stake = DataType::Decimal128(38, STAKE_SCALE);
+  Arc::new(Schema::new(vec![
+    Field::new("account", DataType::Utf8, false),
+    Field::new("choice", DataType::Utf8, false),
+    Field::new("stake", stake.clone(), false),
+    Field::new("delegated_stake", stake.clone(), false),
+    Field::new("weight", stake, false),
+    Field::new("block_height", DataType::Int64, false),
+    Field::new("status", DataType::Utf8, false),
+  ]))
+}
+
+/// Writes `rows` as Parquet, one row group per `BATCH_ROWS` rows. Returns the
+/// number of rows written.
+///
+/// Only one batch is converted to Arrow at a time. This doesn't stream the
+/// export: callers hold every row, and an in-memory `writer` the whole file.
+pub fn write_vote_details_parquet<W: Write + Send>(
+  rows: impl IntoIterator<Item = VoteDetail>,
+  writer: W,
+) -> Result<usize> {
+  let schema = vote_details_schema();
+  let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+  let mut writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))?;
+  let mut rows = rows.into_iter().peekable();
+  let mut count = 0;
+  while rows.peek().is_some() {
+    let batch: Vec<VoteDetail> = rows.by_ref().take(BATCH_ROWS).collect();
+    count += batch.len();
+    writer.write(&vote_details_batch(&schema, &batch)?)?;
+    writer.flush()?;
+  }
+  writer.close()?;
+  Ok(count)
+}
+
+fn vote_details_batch(schema: &SchemaRef, rows: &[VoteDetail]) -> Result<RecordBatch> {
+  let amounts = |amount: fn(&VoteDetail) -> Decimal| -> Result<ArrayRef> {
+    let array = Decimal128Array::from_iter_values(rows.iter().map(|row| decimal128(amount(row))))
+      .with_precision_and_scale(38, STAKE_SCALE)?;
+    Ok(Arc::new(array))
+  };
+  let columns: Vec<ArrayRef> = vec![
+    Arc::new(StringArray::from_iter_values(rows.iter().map(|row| row.account.as_str()))),
+    Arc::new(StringArray::from_iter_values(rows.iter().map(|row| choice_label(row.choice)))),
+    amounts(|row| row.stake)?,
+    amounts(|row| row.delegated_stake)?,
+    amounts(|row| row.weight)?,
+    Arc::new(Int64Array::from_iter_values(rows.iter().map(|row| row.block_height))),
+    Arc::new(StringArray::from_iter_values(rows.iter().map(|row| status_label(row.status)))),
+  ];
+  Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Unscaled value of `amount` at `STAKE_SCALE` decimal places.
+fn decimal128(amount: Decimal) -> i128 {
+  let mut amount = amount.round_dp(STAKE_SCALE as u32);
+  amount.rescale(STAKE_SCALE as u32);
+  amount.mantissa()
+}
+
+fn choice_label(choice: VoteChoice) -> &'static str {
+  match choice {
+    VoteChoice::Yes => "yes",
+    VoteChoice::No => "no",
+  }
+}
+
+fn status_label(status: BlockStatus) -> &'static str {
+  match status {
+    BlockStatus::Pending => "pending",
+    BlockStatus::Canonical => "canonical",
+    BlockStatus::Orphaned => "orphaned",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use arrow::{array::AsArray, datatypes::Decimal128Type};
+  use bytes::Bytes;
+  use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+  use super::*;
+
+  #[test]
+  fn test_write_vote_details_parquet() {
+    let row = |block_height: i64| VoteDetail {
+      account: format!("B62q{block_height}"),
+      choice: VoteChoice::Yes,
+      stake: Decimal::new(1_500_000_001, 9),
+      delegated_stake: Decimal::from(10),
+      weight: Decimal::new(11_500_000_001, 9),
+      block_height,
+      status: BlockStatus::Canonical,
+    };
+    let rows: Vec<VoteDetail> = (0 .. BATCH_ROWS as i64 + 1).map(row).collect();
+
+    let mut bytes = Vec::new();
+    assert_eq!(write_vote_details_parquet(rows, &mut bytes).unwrap(), BATCH_ROWS + 1);
+    assert_eq!(write_vote_details_parquet(Vec::new(), Vec::new()).unwrap(), 0);
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes)).unwrap();
+    assert_eq!(builder.metadata().num_row_groups(), 2);
+    let reader = builder.build().unwrap();
+    let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+    assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), BATCH_ROWS + 1);
+    let batch = &batches[0];
+    assert_eq!(batch.schema().field(2).data_type(), &DataType::Decimal128(38, STAKE_SCALE));
+    assert_eq!(batch.column(0).as_string::<i32>().value(1), "B62q1");
+    assert_eq!(batch.column(1).as_string::<i32>().value(0), "yes");
+    let stake = batch.column(2).as_primitive::<Decimal128Type>();
+    assert_eq!(stake.value_as_string(0), "1.500000001");
+    let delegated = batch.column(3).as_primitive::<Decimal128Type>();
+    assert_eq!(delegated.value_as_string(0), "10.000000000");
+    assert_eq!(batch.column(6).as_string::<i32>().value(0), "canonical");
+  }
+}
@@ -1,59 +1,362 @@
-use std::{collections::HashMap, fs, io::Read, path::PathBuf};
+use std::{
+  collections::HashMap,
+  fs,
+  io::{BufRead, BufReader, Read},
+  path::PathBuf,
+  sync::Arc,
+};
 
 use anyhow::{Result, anyhow};
 use flate2::read::GzDecoder;
+use futures_util::TryStreamExt;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use tar::Archive;
 
-use crate::{Ocv, ProposalVersion, Vote, Wrapper};
+use crate::{
+  LedgerParseError, Network, ObjectMetadata, Ocv, Proposal, ProposalVersion, PublicKey, TallyError, Vote, Wrapper,
+  clear_ledger_cache, ledger_cache_path, ledger_etag_path, ledger_object_key_path, touch_cached_ledger,
+  validate_ledger_hash,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Ledger(pub Vec<LedgerAccount>);
 
 impl Ledger {
-  pub async fn fetch(ocv: &Ocv, hash: &String) -> Result<Ledger> {
-    let dest = ocv.ledger_storage_path.join(format!("{hash}.json"));
+  /// Returns the parsed ledger for `hash`, checking the in-memory cache, then
+  /// the on-disk cache, before downloading it from the bucket.
+  pub async fn fetch(ocv: &Ocv, hash: &String, epoch: Option<i64>) -> Result<Arc<Ledger>> {
+    Self::fetch_for_network(ocv, hash, ocv.network, epoch, LedgerKind::Staking).await
+  }
+
+  /// Like `fetch`, but expands `{network}` in the key prefix with `network`
+  /// rather than the server's network, and only accepts objects holding a
+  /// ledger of `kind`.
+  pub async fn fetch_for_network(
+    ocv: &Ocv,
+    hash: &String,
+    network: Network,
+    epoch: Option<i64>,
+    kind: LedgerKind,
+  ) -> Result<Arc<Ledger>> {
+    Self::fetch_with(ocv, hash, network, LedgerSelection::Epoch { epoch, kind }).await
+  }
+
+  /// Fetches ledger `hash` for `proposal`: listed under its network and epoch,
+  /// and of the kind its `ledger_selection` names.
+  pub async fn fetch_for_proposal(ocv: &Ocv, hash: &String, proposal: &Proposal) -> Result<Arc<Ledger>> {
+    let kind = proposal.ledger_selection.ledger_kind();
+    Self::fetch_for_network(ocv, hash, proposal.network, Some(proposal.epoch), kind).await
+  }
+
+  /// Like `fetch_for_network`, but only accepts a bucket object named after
+  /// exactly `hash`, whatever its epoch. Fails with
+  /// `TallyError::LedgerNotFound` when there is none.
+  pub async fn fetch_exact(ocv: &Ocv, hash: &String, network: Network) -> Result<Arc<Ledger>> {
+    validate_ledger_hash(hash).map_err(|_| TallyError::InvalidLedgerHash(hash.clone()))?;
+    Self::fetch_with(ocv, hash, network, LedgerSelection::ExactHash).await
+  }
+
+  async fn fetch_with(ocv: &Ocv, hash: &String, network: Network, selection: LedgerSelection) -> Result<Arc<Ledger>> {
+    if ocv.cache_validate_on_read && !Self::cached_copy_is_fresh(ocv, hash).await {
+      clear_ledger_cache(&ocv.ledger_storage_path, Some(hash), false)?;
+      ocv.caches.ledger.invalidate(hash).await;
+    }
+    if let Some(ledger) = ocv.caches.get_ledger(hash).await {
+      return Ok(ledger);
+    }
+    let dest = ledger_cache_path(&ocv.ledger_storage_path, hash);
     if !dest.exists() {
-      Self::download(ocv, hash, &dest).await?;
+      Self::download(ocv, hash, network, selection, &dest).await?;
+    } else if let Err(err) = touch_cached_ledger(&dest) {
+      tracing::debug!("Could not record the use of cached ledger {}: {}", hash, err);
     }
-    let contents = fs::read(dest)?;
-    Ok(Ledger(serde_json::from_slice(&contents[..]).expect("Expecting a valid list of ledger accounts.")))
+    let ledger = match Ledger::from_reader(BufReader::new(fs::File::open(dest)?)) {
+      Ok(ledger) => Arc::new(ledger),
+      Err(err) => return Err(Self::parse_failure(ocv, hash, err)),
+    };
+    ledger.check_total_stake(hash, ocv.min_ledger_total_stake, ocv.max_ledger_total_stake);
+    ocv.caches.ledger.insert(hash.clone(), ledger.clone()).await;
+    Ok(ledger)
   }
 
-  async fn download(ocv: &Ocv, hash: &String, to: &PathBuf) -> Result<()> {
-    let storage = ocv.storage_provider.as_ref();
-    tracing::info!("Using storage provider: {}", storage.provider_name());
+  /// Names the bucket object of a ledger that failed to parse, and drops the
+  /// cached copy so the next fetch downloads it again.
+  fn parse_failure(ocv: &Ocv, hash: &String, err: anyhow::Error) -> anyhow::Error {
+    let parse_error = match err.downcast::<LedgerParseError>() {
+      Ok(parse_error) => parse_error,
+      Err(err) => return err,
+    };
+    let object_key = fs::read_to_string(ledger_object_key_path(&ocv.ledger_storage_path, hash));
+    let parse_error = parse_error.with_key(object_key.map_or_else(|_| hash.clone(), |key| key.trim().to_string()));
+    tracing::error!("{}", parse_error);
+    if let Err(err) = clear_ledger_cache(&ocv.ledger_storage_path, Some(hash), false) {
+      tracing::warn!("Could not drop the cached copy of malformed ledger {}: {}", hash, err);
+    }
+    TallyError::from(parse_error).into()
+  }
 
-    // List objects to find the one with matching hash
-    tracing::info!("Looking for ledger with hash: {} in bucket: {}", hash, ocv.bucket_name);
-    let objects = storage.list_objects(&ocv.bucket_name, None).await?;
-    tracing::info!("Found {} objects total, searching for hash '{}'", objects.len(), hash);
+  /// Whether the cached copy of `hash` was downloaded from the current version
+  /// of its bucket object. Copies without a recorded object key and etag, and
+  /// objects whose metadata can't be fetched, are assumed fresh.
+  async fn cached_copy_is_fresh(ocv: &Ocv, hash: &str) -> bool {
+    let dir = &ocv.ledger_storage_path;
+    let (Ok(object_key), Ok(cached_etag)) =
+      (fs::read_to_string(ledger_object_key_path(dir, hash)), fs::read_to_string(ledger_etag_path(dir, hash)))
+    else {
+      return true;
+    };
+    if !ocv.storage_provider.capabilities().can_head {
+      return true;
+    }
+    match ocv.storage_provider.head_object(&ocv.bucket_name, object_key.trim()).await {
+      Ok(ObjectMetadata { etag: Some(etag), .. }) if etag != cached_etag.trim() => {
+        tracing::info!("Cached ledger {} is stale: {} changed (etag {} -> {})", hash, object_key, cached_etag, etag);
+        false
+      }
+      Ok(_) => true,
+      Err(err) => {
+        tracing::warn!("Could not validate cached ledger {} against {}: {}", hash, object_key, err);
+        true
+      }
+    }
+  }
 
-    // Enhanced debugging for hash matching
-    let matching_objects: Vec<&String> = objects.iter().filter(|key| key.contains(hash)).collect();
+  /// Parses a ledger dump. Accounts with an invalid public key are logged
+  /// and skipped.
+  pub fn from_slice(bytes: &[u8]) -> Result<Ledger> {
+    Self::from_reader(bytes)
+  }
 
-    tracing::info!("Objects containing hash '{}': {:?}", hash, matching_objects);
+  /// Parses a ledger dump given either as a JSON array of accounts or as
+  /// newline-delimited JSON with one account per line, telling them apart by
+  /// the first non-whitespace byte. NDJSON is read line by line, so only the
+  /// parsed accounts are held in memory. Malformed ledgers fail with a
+  /// `LedgerParseError`.
+  pub fn from_reader(mut reader: impl BufRead) -> Result<Ledger> {
+    let accounts = match first_non_whitespace(&mut reader)? {
+      Some(b'[') => {
+        let raw_accounts: Vec<RawLedgerAccount> = serde_json::from_reader(reader).map_err(LedgerParseError::from_json)?;
+        raw_accounts.into_iter().filter_map(LedgerAccount::from_raw_or_skip).collect()
+      }
+      Some(b'{') => {
+        let mut accounts = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+          let line = line?;
+          if line.trim().is_empty() {
+            continue;
+          }
+          let raw: RawLedgerAccount = serde_json::from_str(&line)
+            .map_err(|err| LedgerParseError { line: Some(index + 1), ..LedgerParseError::from_json(err) })?;
+          accounts.extend(LedgerAccount::from_raw_or_skip(raw));
+        }
+        accounts
+      }
+      Some(byte) => {
+        let message = format!("Unrecognized ledger format: expected a JSON array or NDJSON, found {:?}", byte as char);
+        return Err(LedgerParseError::new(message).into());
+      }
+      None => return Err(LedgerParseError::new("Ledger is empty").into()),
+    };
+    Ok(Ledger(accounts))
+  }
 
-    if matching_objects.is_empty() {
-      // Try partial hash matching for debugging
-      let partial_matches: Vec<&String> =
-        objects.iter().filter(|key| hash.len() >= 10 && key.contains(&hash[.. 10])).collect();
+  /// Balances of the accounts on the ledger, by public key, for looking up
+  /// many accounts without scanning the ledger for each.
+  pub fn balances(&self) -> HashMap<&str, Decimal> {
+    let zero = Decimal::new(0, LEDGER_BALANCE_SCALE);
+    self.0.iter().map(|account| (account.pk.as_str(), account.balance.parse().unwrap_or(zero))).collect()
+  }
+
+  /// Sum of all account balances. Unparseable balances count as zero, as in
+  /// stake weighting.
+  pub fn total_stake(&self) -> Decimal {
+    self
+      .0
+      .iter()
+      .map(|account| account.balance.parse().unwrap_or_else(|_| Decimal::new(0, LEDGER_BALANCE_SCALE)))
+      .sum()
+  }
 
+  /// Logs the account count and total stake of ledger `hash`, warning when the
+  /// total is outside `min ..= max`. A total below `min` usually means a
+  /// truncated or partial ledger, which would skew weighting. Returns whether
+  /// the total is within range.
+  pub fn check_total_stake(&self, hash: &str, min: Option<Decimal>, max: Option<Decimal>) -> bool {
+    let total = self.total_stake();
+    tracing::info!("Ledger {} has {} accounts holding {} in total", hash, self.0.len(), total);
+    if let Some(min) = min.filter(|min| total < *min) {
       tracing::warn!(
-        "No exact hash matches found. Partial matches (first 10 chars): {:?}",
-        partial_matches.iter().take(5).collect::<Vec<_>>()
+        "Ledger {} total stake {} is below the expected minimum {}; it may be truncated or partial",
+        hash,
+        total,
+        min
       );
-      tracing::warn!("Sample available objects: {:?}", objects.iter().take(10).collect::<Vec<_>>());
-      return Err(anyhow!("Could not retrieve dump corresponding to {hash}"));
+      return false;
+    }
+    if let Some(max) = max.filter(|max| total > *max) {
+      tracing::warn!("Ledger {} total stake {} is above the expected maximum {}", hash, total, max);
+      return false;
+    }
+    true
+  }
+
+  /// Streams the listing under `prefix` until an object holding ledger `hash`
+  /// turns up, so a large bucket needn't be listed in full.
+  async fn search_listing(
+    ocv: &Ocv,
+    prefix: Option<&str>,
+    hash: &str,
+    selection: LedgerSelection,
+  ) -> Result<LedgerSearch> {
+    let mut keys = ocv.storage_provider.list_objects_stream(&ocv.bucket_name, prefix);
+    let mut listed = Vec::new();
+    while let Some(key) = keys.try_next().await? {
+      if selection.matches(&key, hash) {
+        return Ok(LedgerSearch::Found(key));
+      }
+      listed.push(key);
+    }
+    Ok(LedgerSearch::Missing(listed))
+  }
+
+  /// Looks for the object holding ledger `hash`. When the key prefix template
+  /// names the epoch, only that epoch's subtree is searched first; if nothing
+  /// there matches, the search falls back to the prefix without the epoch.
+  async fn find_object(
+    ocv: &Ocv,
+    hash: &str,
+    network: Network,
+    selection: LedgerSelection,
+    epoch: Option<i64>,
+  ) -> Result<LedgerSearch> {
+    let prefix = render_ledger_key_prefix(&ocv.ledger_key_prefix, network, epoch);
+    let base_prefix = render_ledger_key_prefix(&ocv.ledger_key_prefix, network, None);
+    tracing::info!("Looking for ledger with hash: {} in bucket: {} (prefix {:?})", hash, ocv.bucket_name, prefix);
+    let search = Self::search_listing(ocv, prefix.as_deref(), hash, selection).await?;
+    if prefix == base_prefix {
+      return Ok(search);
+    }
+    match search {
+      LedgerSearch::Found(key) => {
+        tracing::info!("Found ledger {} under epoch prefix {:?} (fast path)", hash, prefix);
+        Ok(LedgerSearch::Found(key))
+      }
+      LedgerSearch::Missing(objects) => {
+        tracing::info!(
+          "Ledger {} not under epoch prefix {:?} ({} objects), falling back to prefix {:?}",
+          hash,
+          prefix,
+          objects.len(),
+          base_prefix
+        );
+        Self::search_listing(ocv, base_prefix.as_deref(), hash, selection).await
+      }
     }
+  }
+
+  async fn download(
+    ocv: &Ocv,
+    hash: &String,
+    network: Network,
+    selection: LedgerSelection,
+    to: &PathBuf,
+  ) -> Result<()> {
+    let epoch = match selection {
+      LedgerSelection::Epoch { epoch, .. } => epoch,
+      LedgerSelection::ExactHash => None,
+    };
+    let storage = ocv.storage_provider.as_ref();
+    tracing::info!("Using storage provider: {}", storage.provider_name());
 
-    let object_key = matching_objects[0].to_string();
+    // List objects until the one with matching hash turns up
+    let object_key = match Self::find_object(ocv, hash, network, selection, epoch).await? {
+      LedgerSearch::Found(object_key) => object_key,
+      // An empty listing points at the wrong bucket or prefix rather than at a
+      // ledger that isn't exported yet.
+      LedgerSearch::Missing(objects) if objects.is_empty() => {
+        let prefix = render_ledger_key_prefix(&ocv.ledger_key_prefix, network, None);
+        tracing::error!("Bucket {} has no objects under prefix {:?}", ocv.bucket_name, prefix);
+        return Err(anyhow!(
+          "No objects found in bucket {} under prefix {:?}; check BUCKET_NAME and LEDGER_KEY_PREFIX",
+          ocv.bucket_name,
+          prefix.unwrap_or_default()
+        ));
+      }
+      LedgerSearch::Missing(_) if selection == LedgerSelection::ExactHash => {
+        return Err(TallyError::LedgerNotFound(hash.clone()).into());
+      }
+      LedgerSearch::Missing(objects) => {
+        tracing::info!("None of the {} objects listed holds ledger '{}'", objects.len(), hash);
+        // Try partial hash matching for debugging
+        let partial_matches: Vec<&String> =
+          objects.iter().filter(|key| hash.len() >= 10 && key.contains(&hash[.. 10])).collect();
+
+        tracing::warn!(
+          "No exact hash matches found. Partial matches (first 10 chars): {:?}",
+          partial_matches.iter().take(5).collect::<Vec<_>>()
+        );
+        tracing::warn!("Sample available objects: {:?}", objects.iter().take(10).collect::<Vec<_>>());
+        // Ledgers of the current or a future epoch may simply not be exported
+        // yet; older ones should be in the bucket already.
+        let current_epoch = ocv.archive.current_epoch_and_slot().map(|(epoch, _)| i64::from(epoch));
+        return Err(match (epoch, current_epoch) {
+          (Some(epoch), Ok(current_epoch)) if epoch < current_epoch => anyhow!(
+            "Staking ledger {} for past epoch {} (current epoch {}) is missing from bucket {}",
+            hash,
+            epoch,
+            current_epoch,
+            ocv.bucket_name
+          ),
+          _ => {
+            tracing::warn!("Staking ledger for epoch {:?} with expected hash {} is not in the bucket yet", epoch, hash);
+            TallyError::LedgerUnavailable { epoch, hash: hash.clone() }.into()
+          }
+        });
+      }
+    };
 
     tracing::info!("Found ledger object: {} for hash: {}", object_key, hash);
+    ocv.caches.ledger_object_keys.insert(hash.clone(), object_key.clone()).await;
+
+    // Check the object's size before downloading it
+    let metadata = match storage.capabilities().can_head {
+      true => match storage.head_object(&ocv.bucket_name, &object_key).await {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+          tracing::warn!("Could not fetch metadata of ledger object {}: {}", object_key, err);
+          None
+        }
+      },
+      false => None,
+    };
+    if let Some(metadata) = &metadata {
+      check_ledger_size(&object_key, metadata.size, ocv.max_ledger_bytes)?;
+    }
 
-    // Download object
-    let bytes = storage.get_object(&ocv.bucket_name, &object_key).await?;
+    // Download object, sharing the request with concurrent downloads of it.
+    // Without its size, only enough of it is read to tell it's too large.
+    let storage_provider = ocv.storage_provider.clone();
+    let max_bytes = (metadata.is_none() && storage.capabilities().can_range).then_some(ocv.max_ledger_bytes);
+    let bytes = ocv
+      .caches
+      .get_object_once(storage_provider, &ocv.bucket_name, &object_key, max_bytes, &ocv.request_deadline)
+      .await?;
+    if max_bytes.is_some() && bytes.len() as u64 > ocv.max_ledger_bytes {
+      tracing::error!("Refusing ledger object {} of over {} bytes", object_key, ocv.max_ledger_bytes);
+      return Err(anyhow!(
+        "Ledger object {} exceeds the {} byte limit (MAX_LEDGER_BYTES)",
+        object_key,
+        ocv.max_ledger_bytes
+      ));
+    }
+    check_ledger_size(&object_key, bytes.len() as u64, ocv.max_ledger_bytes)?;
+    fs::write(ledger_object_key_path(&ocv.ledger_storage_path, hash), &object_key)?;
+
+    // Remember the object's etag so stale cache entries can be detected later
+    if let Some(ObjectMetadata { etag: Some(etag), .. }) = metadata {
+      fs::write(ledger_etag_path(&ocv.ledger_storage_path, hash), etag)?;
+    }
 
     // Determine file type and process accordingly
     if object_key.ends_with(".json") {
@@ -75,6 +378,7 @@ impl Ledger {
 
         // Look for JSON file within the archive or use the same name logic
         if object_key.contains(&path) || path.ends_with(".json") {
+          check_ledger_size(&format!("{object_key}:{path}"), entry.size(), ocv.max_ledger_bytes)?;
           let mut buffer = Vec::new();
           entry.read_to_end(&mut buffer)?;
           fs::write(to, buffer)?;
@@ -109,14 +413,14 @@ impl Ledger {
 
     match version {
       ProposalVersion::V1 => {
-        if account.delegate.clone().unwrap_or(public_key.clone()) != public_key {
+        if !account.delegates_to(&public_key) {
           return Ok(Decimal::new(0, LEDGER_BALANCE_SCALE));
         }
 
         let delegators = self
           .0
           .iter()
-          .filter(|d| d.delegate.clone().unwrap_or(d.pk.clone()) == public_key && d.pk != public_key)
+          .filter(|d| d.delegates_to(&public_key) && d.pk != public_key)
           .collect::<Vec<&LedgerAccount>>();
 
         if delegators.is_empty() {
@@ -133,9 +437,7 @@ impl Ledger {
         let delegators = self
           .0
           .iter()
-          .filter(|d| {
-            d.delegate.clone().unwrap_or(d.pk.clone()) == public_key && d.pk != public_key && !map.0.contains_key(&d.pk)
-          })
+          .filter(|d| d.delegates_to(&public_key) && d.pk != public_key && !map.0.contains_key(d.pk.as_str()))
           .collect::<Vec<&LedgerAccount>>();
 
         if delegators.is_empty() {
@@ -163,14 +465,14 @@ impl Ledger {
 
     let balance = account.balance.parse().unwrap_or_else(|_| Decimal::new(0, LEDGER_BALANCE_SCALE));
 
-    if account.delegate.clone().unwrap_or(public_key.clone()) != public_key {
+    if !account.delegates_to(&public_key) {
       return Ok(Decimal::new(0, LEDGER_BALANCE_SCALE));
     }
 
     let delegators = self
       .0
       .iter()
-      .filter(|d| d.delegate.clone().unwrap_or(d.pk.clone()) == public_key && d.pk != public_key)
+      .filter(|d| d.delegates_to(&public_key) && d.pk != public_key)
       .collect::<Vec<&LedgerAccount>>();
 
     if delegators.is_empty() {
@@ -185,17 +487,175 @@ impl Ledger {
   }
 }
 
+/// Which epoch ledger a bucket object holds.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerKind {
+  Staking,
+  NextStaking,
+}
+
+impl LedgerKind {
+  /// The kind of ledger held by object `key`, going by its file name. Keys
+  /// without the `next-staking-` prefix are taken to hold staking ledgers.
+  pub fn of_key(key: &str) -> LedgerKind {
+    match key.rsplit('/').next().is_some_and(|name| name.starts_with("next-staking-")) {
+      true => LedgerKind::NextStaking,
+      false => LedgerKind::Staking,
+    }
+  }
+}
+
+/// The parts of a ledger object key such as
+/// `mainnet/staking-epoch-42-jx...json` or `next-staking-epoch-42-jx...tar.gz`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerObjectKey {
+  pub kind: LedgerKind,
+  pub epoch: i64,
+  pub hash: String,
+}
+
+impl LedgerObjectKey {
+  /// Parses the file name of `key`, returning `None` when it doesn't follow
+  /// the `[next-]staking-epoch-<epoch>-<hash>` convention.
+  pub fn parse(key: &str) -> Option<LedgerObjectKey> {
+    let name = key.rsplit('/').next()?;
+    let name = name.split('.').next()?;
+    let (kind, rest) = match name.strip_prefix("next-staking-epoch-") {
+      Some(rest) => (LedgerKind::NextStaking, rest),
+      None => (LedgerKind::Staking, name.strip_prefix("staking-epoch-")?),
+    };
+    let (epoch, rest) = rest.split_once('-')?;
+    let hash = rest.split('-').next().filter(|hash| !hash.is_empty())?;
+    Some(LedgerObjectKey { kind, epoch: epoch.parse().ok()?, hash: hash.to_string() })
+  }
+}
+
+/// How `Ledger::download` picks the bucket object for a hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedgerSelection {
+  /// Any object of `kind` whose key contains the hash, listed under the
+  /// epoch's prefix.
+  Epoch { epoch: Option<i64>, kind: LedgerKind },
+  /// Only objects whose hash, or whole file name stem, is exactly the hash.
+  ExactHash,
+}
+
+impl LedgerSelection {
+  fn matches(&self, key: &str, hash: &str) -> bool {
+    match self {
+      LedgerSelection::Epoch { kind, .. } => key.contains(hash) && LedgerKind::of_key(key) == *kind,
+      LedgerSelection::ExactHash => object_key_has_hash(key, hash),
+    }
+  }
+}
+
+/// Outcome of searching a listing for a ledger's object.
+enum LedgerSearch {
+  /// The first object listed that holds the ledger.
+  Found(String),
+  /// No object holds the ledger; all the keys listed, for diagnostics.
+  Missing(Vec<String>),
+}
+
+/// Refuses ledger objects larger than `max_bytes`, which would otherwise be
+/// held in memory whole.
+fn check_ledger_size(object_key: &str, size: u64, max_bytes: u64) -> Result<()> {
+  if size > max_bytes {
+    tracing::error!("Refusing ledger object {} of {} bytes (limit {} bytes)", object_key, size, max_bytes);
+    return Err(anyhow!(
+      "Ledger object {} is {} bytes, which exceeds the {} byte limit (MAX_LEDGER_BYTES)",
+      object_key,
+      size,
+      max_bytes
+    ));
+  }
+  Ok(())
+}
+
+pub(crate) fn object_key_has_hash(key: &str, hash: &str) -> bool {
+  let stem = key.rsplit('/').next().and_then(|name| name.split('.').next());
+  stem == Some(hash) || LedgerObjectKey::parse(key).is_some_and(|parsed| parsed.hash == hash)
+}
+
+/// Expands `{network}` and `{epoch}` in a ledger key prefix template.
+///
+/// When the epoch is unknown, the template is cut at `{epoch}` so the prefix
+/// still narrows the listing. Returns `None` for an empty prefix.
+pub fn render_ledger_key_prefix(template: &str, network: Network, epoch: Option<i64>) -> Option<String> {
+  let prefix = match epoch {
+    Some(epoch) => template.replace("{epoch}", &epoch.to_string()),
+    None => template.split("{epoch}").next().unwrap_or_default().to_string(),
+  };
+  let prefix = prefix.replace("{network}", &network.to_string());
+  (!prefix.is_empty()).then_some(prefix)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct LedgerAccount {
-  pub pk: String,
+  pub pk: PublicKey,
   pub balance: String,
-  pub delegate: Option<String>,
+  pub delegate: Option<PublicKey>,
 }
 
 impl LedgerAccount {
+  /// Builds an account without validating the keys.
   pub fn new(pk: String, balance: String, delegate: Option<String>) -> LedgerAccount {
-    LedgerAccount { pk, balance, delegate }
+    LedgerAccount {
+      pk: PublicKey::new_unchecked(pk),
+      balance,
+      delegate: delegate.map(PublicKey::new_unchecked),
+    }
+  }
+
+  /// Whether the account's stake is delegated to `public_key`. Accounts
+  /// without a delegate delegate to themselves.
+  pub fn delegates_to(&self, public_key: &str) -> bool {
+    self.delegate.as_ref().unwrap_or(&self.pk) == public_key
+  }
+
+  /// Validates a raw account, logging and dropping it if its keys are invalid.
+  fn from_raw_or_skip(raw: RawLedgerAccount) -> Option<LedgerAccount> {
+    LedgerAccount::try_from(raw).map_err(|err| tracing::warn!("Skipping ledger account: {}", err)).ok()
+  }
+}
+
+/// Peeks at the first non-whitespace byte of `reader` without consuming it.
+fn first_non_whitespace(reader: &mut impl BufRead) -> Result<Option<u8>> {
+  loop {
+    let buf = reader.fill_buf()?;
+    if buf.is_empty() {
+      return Ok(None);
+    }
+    if let Some(position) = buf.iter().position(|byte| !byte.is_ascii_whitespace()) {
+      let byte = buf[position];
+      reader.consume(position);
+      return Ok(Some(byte));
+    }
+    let len = buf.len();
+    reader.consume(len);
+  }
+}
+
+/// A ledger account as found in the dump, before its keys are validated.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLedgerAccount {
+  pk: String,
+  balance: String,
+  delegate: Option<String>,
+}
+
+impl TryFrom<RawLedgerAccount> for LedgerAccount {
+  type Error = anyhow::Error;
+
+  fn try_from(raw: RawLedgerAccount) -> Result<Self> {
+    Ok(LedgerAccount {
+      pk: PublicKey::parse(&raw.pk)?,
+      balance: raw.balance,
+      delegate: raw.delegate.as_deref().map(PublicKey::parse).transpose()?,
+    })
   }
 }
 
@@ -281,6 +741,252 @@ mod tests {
     assert_eq!(b_weight.unwrap(), Decimal::new(2000000000, LEDGER_BALANCE_SCALE));
   }
 
+  #[test]
+  fn test_parse_ledger_object_key() {
+    assert_eq!(
+      LedgerObjectKey::parse("mainnet/staking-epoch-42-jxAbc.json"),
+      Some(LedgerObjectKey { kind: LedgerKind::Staking, epoch: 42, hash: "jxAbc".to_string() })
+    );
+    assert_eq!(
+      LedgerObjectKey::parse("next-staking-epoch-7-jxDef-mainnet.tar.gz"),
+      Some(LedgerObjectKey { kind: LedgerKind::NextStaking, epoch: 7, hash: "jxDef".to_string() })
+    );
+    assert_eq!(LedgerObjectKey::parse("staking-epoch-x-jxAbc.json"), None);
+    assert_eq!(LedgerObjectKey::parse("staking-epoch-42-.json"), None);
+    assert_eq!(LedgerObjectKey::parse("jxAbc.json"), None);
+  }
+
+  #[test]
+  fn test_object_key_has_hash() {
+    assert!(object_key_has_hash("mainnet/staking-epoch-42-jxAbc.json", "jxAbc"));
+    assert!(object_key_has_hash("jxAbc.json", "jxAbc"));
+    assert!(!object_key_has_hash("staking-epoch-42-jxAbcd.json", "jxAbc"));
+    assert!(!object_key_has_hash("jxAbc-old.json", "jxAbc"));
+  }
+
+  #[test]
+  fn test_render_ledger_key_prefix() {
+    assert_eq!(render_ledger_key_prefix("", Network::Mainnet, Some(42)), None);
+    assert_eq!(
+      render_ledger_key_prefix("{network}/staking-{epoch}-", Network::Devnet, Some(42)).as_deref(),
+      Some("devnet/staking-42-")
+    );
+    assert_eq!(
+      render_ledger_key_prefix("{network}/staking-{epoch}-", Network::Mainnet, None).as_deref(),
+      Some("mainnet/staking-")
+    );
+    assert_eq!(render_ledger_key_prefix("{epoch}", Network::Mainnet, None), None);
+  }
+
+  #[tokio::test]
+  async fn test_fetch_epoch_prefix_fallback() {
+    let provider = crate::MemoryProvider::new()
+      .with_object("test-ledgers", "epoch-5/staking-epoch-5-jxA.json", "[]")
+      .with_object("test-ledgers", "epoch-5/staking-epoch-5-jxB.json", "[]");
+    let mut ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+    ocv.ledger_key_prefix = "epoch-{epoch}/".to_string();
+
+    // Listed under the proposal's epoch.
+    assert!(Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.is_ok());
+    // Filed under another epoch than the proposal's: found by the fallback scan.
+    assert!(Ledger::fetch(&ocv, &"jxB".to_string(), Some(6)).await.is_ok());
+    assert!(Ledger::fetch(&ocv, &"jxC".to_string(), Some(6)).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_fetch_empty_listing() {
+    let ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), crate::MemoryProvider::new(), Vec::new());
+    let err = Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.unwrap_err();
+    assert!(err.downcast_ref::<TallyError>().is_none());
+    assert!(err.to_string().contains("No objects found in bucket test-ledgers"));
+
+    // Other ledgers are there, so this one just isn't exported yet.
+    let provider = crate::MemoryProvider::new().with_object("test-ledgers", "staking-epoch-4-jxB.json", "[]");
+    let ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+    let err = Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::LedgerUnavailable { epoch: Some(5), .. })));
+  }
+
+  #[tokio::test]
+  async fn test_fetch_for_network() {
+    let provider = crate::MemoryProvider::new()
+      .with_object("test-ledgers", "mainnet/staking-epoch-5-jxA.json", "[]")
+      .with_object("test-ledgers", "devnet/staking-epoch-5-jxB.json", "[]");
+    let mut ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+    ocv.ledger_key_prefix = "{network}/".to_string();
+
+    assert!(Ledger::fetch(&ocv, &"jxB".to_string(), Some(5)).await.is_err());
+    assert!(
+      Ledger::fetch_for_network(&ocv, &"jxB".to_string(), Network::Devnet, Some(5), LedgerKind::Staking).await.is_ok()
+    );
+    assert!(Ledger::fetch_exact(&ocv, &"jxA".to_string(), Network::Devnet).await.is_err());
+    assert!(Ledger::fetch_exact(&ocv, &"jxA".to_string(), Network::Mainnet).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_fetch_ledger_kind() {
+    // The next staking ledger of epoch 4 is the staking ledger of epoch 5.
+    for (kind, expected_key) in
+      [(LedgerKind::Staking, "staking-epoch-5-jxA.json"), (LedgerKind::NextStaking, "next-staking-epoch-4-jxA.json")]
+    {
+      let provider = crate::MemoryProvider::new()
+        .with_object("test-ledgers", "next-staking-epoch-4-jxA.json", "[]")
+        .with_object("test-ledgers", "staking-epoch-5-jxA.json", "[]");
+      let ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+
+      assert!(Ledger::fetch_for_network(&ocv, &"jxA".to_string(), Network::Mainnet, Some(5), kind).await.is_ok());
+      let object_key = fs::read_to_string(ledger_object_key_path(&ocv.ledger_storage_path, "jxA")).unwrap();
+      assert_eq!(object_key, expected_key);
+    }
+  }
+
+  #[test]
+  fn test_ledger_kind_of_key() {
+    assert_eq!(LedgerKind::of_key("mainnet/staking-epoch-5-jxA.json"), LedgerKind::Staking);
+    assert_eq!(LedgerKind::of_key("next-staking/staking-epoch-5-jxA.json"), LedgerKind::Staking);
+    assert_eq!(LedgerKind::of_key("mainnet/next-staking-epoch-4-jxA.tar.gz"), LedgerKind::NextStaking);
+    assert_eq!(LedgerKind::of_key("jxA.json"), LedgerKind::Staking);
+  }
+
+  #[tokio::test]
+  async fn test_fetch_refuses_oversized_ledger() {
+    let provider = crate::MemoryProvider::new().with_object("test-ledgers", "staking-epoch-5-jxA.json", "[]");
+    let mut ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+    ocv.max_ledger_bytes = 1;
+
+    let err = Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.unwrap_err();
+    assert!(err.to_string().contains("exceeds the 1 byte limit"));
+    assert!(!ledger_cache_path(&ocv.ledger_storage_path, "jxA").exists());
+
+    ocv.max_ledger_bytes = 2;
+    assert!(Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.is_ok());
+  }
+
+  /// Start and optional end of each range read.
+  type ReadRanges = Arc<std::sync::Mutex<Vec<(u64, Option<u64>)>>>;
+
+  /// Can't HEAD, and records the ranges read.
+  struct NoHeadProvider {
+    inner: crate::MemoryProvider,
+    ranges: ReadRanges,
+  }
+
+  #[async_trait::async_trait]
+  impl crate::storage::StorageProvider for NoHeadProvider {
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+      self.inner.list_objects(bucket, prefix).await
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<bytes::Bytes> {
+      self.inner.get_object(bucket, key).await
+    }
+
+    async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<bytes::Bytes> {
+      self.ranges.lock().unwrap().push((start, end));
+      self.inner.get_object_range(bucket, key, start, end).await
+    }
+
+    fn provider_name(&self) -> &'static str {
+      "NoHead"
+    }
+
+    fn capabilities(&self) -> crate::StorageCapabilities {
+      crate::StorageCapabilities { can_head: false, ..self.inner.capabilities() }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_fetch_without_head_reads_past_limit_only() {
+    let ledger = "[]".repeat(50);
+    let inner = crate::MemoryProvider::new().with_object("test-ledgers", "staking-epoch-5-jxA.json", ledger);
+    let ranges = Arc::default();
+    let provider = NoHeadProvider { inner, ranges: Arc::clone(&ranges) };
+    let mut ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+    ocv.max_ledger_bytes = 10;
+
+    let err = Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.unwrap_err();
+    assert!(err.to_string().contains("exceeds the 10 byte limit"), "{err}");
+    // One byte past the limit is enough to refuse it.
+    assert_eq!(*ranges.lock().unwrap(), vec![(0, Some(10))]);
+  }
+
+  #[tokio::test]
+  async fn test_fetch_malformed_ledger() {
+    let truncated = "[\n  {\"pk\": \"B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM\", \"balance\": ";
+    let provider = crate::MemoryProvider::new().with_object("test-ledgers", "staking-epoch-5-jxA.json", truncated);
+    let ocv = Ocv::new_for_test(crate::StubArchive::new(100, 100), provider, Vec::new());
+
+    let err = Ledger::fetch(&ocv, &"jxA".to_string(), Some(5)).await.unwrap_err();
+    let Some(TallyError::LedgerParse(parse_error)) = err.downcast_ref::<TallyError>() else {
+      panic!("expected a ledger parse error, got {err}");
+    };
+    assert_eq!(parse_error.key, "staking-epoch-5-jxA.json");
+    assert_eq!(parse_error.line, Some(2));
+    assert!(err.to_string().starts_with("Malformed ledger object staking-epoch-5-jxA.json at line 2 column"), "{err}");
+    assert_eq!(err.downcast_ref::<TallyError>().unwrap().status_code(), axum::http::StatusCode::BAD_GATEWAY);
+    // The corrupt copy isn't kept in the cache.
+    assert!(!ledger_cache_path(&ocv.ledger_storage_path, "jxA").exists());
+  }
+
+  #[test]
+  fn test_check_total_stake() {
+    let (a, b, c, d, e) = get_accounts();
+    let ledger = Ledger(vec![a, b, c, d, e]);
+    let total = ledger.total_stake();
+    assert_eq!(total, Decimal::new(5, 0));
+
+    assert!(ledger.check_total_stake("jxA", None, None));
+    assert!(ledger.check_total_stake("jxA", Some(total), Some(total)));
+    assert!(!ledger.check_total_stake("jxA", Some(Decimal::new(6, 0)), None));
+    assert!(!ledger.check_total_stake("jxA", None, Some(Decimal::new(4, 0))));
+  }
+
+  #[test]
+  fn test_from_slice_skips_invalid_keys() {
+    let json = r#"[
+      {"pk": "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM", "balance": "1", "delegate": null},
+      {"pk": "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSN", "balance": "2", "delegate": null},
+      {
+        "pk": "B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy",
+        "balance": "3",
+        "delegate": "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM"
+      },
+      {"pk": "B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy", "balance": "4", "delegate": "corrupt"}
+    ]"#;
+
+    let ledger = Ledger::from_slice(json.as_bytes()).unwrap();
+    assert_eq!(ledger.0.len(), 2);
+    assert_eq!(ledger.0[0].balance, "1");
+    assert_eq!(ledger.0[1].balance, "3");
+    assert!(ledger.0[1].delegates_to("B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM"));
+  }
+
+  #[test]
+  fn test_from_slice_ndjson() {
+    let ndjson = concat!(
+      "{\"pk\": \"B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM\", \"balance\": \"1\", \"delegate\": null}\n",
+      "\n",
+      "{\"pk\": \"B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSN\", \"balance\": \"2\", \"delegate\": null}\n",
+      "{\"pk\": \"B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy\", \"balance\": \"3\", \"delegate\": null}",
+    );
+    let ledger = Ledger::from_slice(ndjson.as_bytes()).unwrap();
+    assert_eq!(ledger.0.len(), 2);
+    assert_eq!(ledger.0[1].balance, "3");
+
+    let array = format!("  \n[{}]", ndjson.lines().filter(|line| !line.is_empty()).collect::<Vec<_>>().join(","));
+    assert_eq!(Ledger::from_slice(array.as_bytes()).unwrap(), ledger);
+
+    // An array line inside NDJSON is rejected with its line number.
+    let mixed = format!("{}\n[]\n", ndjson.lines().next().unwrap());
+    let err = Ledger::from_slice(mixed.as_bytes()).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+    assert_eq!(err.downcast_ref::<LedgerParseError>().unwrap().line, Some(2));
+
+    assert!(Ledger::from_slice(b"{\"pk\": \"B62q\"").is_err());
+    assert!(Ledger::from_slice(b"pk,balance").is_err());
+    assert!(Ledger::from_slice(b" \n ").is_err());
+  }
+
   fn get_accounts() -> (LedgerAccount, LedgerAccount, LedgerAccount, LedgerAccount, LedgerAccount) {
     (
       LedgerAccount::new("A".to_string(), "1".to_string(), None),
@@ -0,0 +1,372 @@
+use std::{
+  collections::HashSet,
+  fs, io,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+/// Path of the parsed ledger for `hash` inside the cache directory.
+pub fn ledger_cache_path(dir: &Path, hash: &str) -> PathBuf {
+  dir.join(format!("{hash}.json"))
+}
+
+/// Path of the sidecar file holding the bucket object's etag at download time.
+pub fn ledger_etag_path(dir: &Path, hash: &str) -> PathBuf {
+  dir.join(format!("{hash}.etag"))
+}
+
+/// Path of the sidecar file holding the bucket object key the ledger was
+/// downloaded from.
+pub fn ledger_object_key_path(dir: &Path, hash: &str) -> PathBuf {
+  dir.join(format!("{hash}.key"))
+}
+
+/// File recording which layout the cache directory is in.
+const CACHE_LAYOUT_FILE: &str = "cache-layout-version";
+
+/// Layout written by this version: `<hash>.json`, with `<hash>.etag` and
+/// `<hash>.key` sidecars. Directories without a layout file predate it and
+/// are version 1.
+pub const CACHE_LAYOUT_VERSION: u32 = 2;
+
+/// What `ensure_cache_layout` found and did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheLayoutCheck {
+  /// Already in the current layout.
+  Current,
+  /// Nothing was cached yet; the layout file was written.
+  Initialized,
+  /// An older layout was migrated in place.
+  Migrated { from: u32 },
+  /// An unknown layout was cleared.
+  Cleared { from: Option<u32> },
+}
+
+/// Brings the cache directory to the current layout.
+///
+/// This keeps files written by another version from shadowing what this one
+/// expects. Version 1 caches are migrated by dropping sidecars whose ledger
+/// is gone; caches in an unrecognized layout are cleared.
+pub fn ensure_cache_layout(dir: &Path) -> Result<CacheLayoutCheck> {
+  let marker = dir.join(CACHE_LAYOUT_FILE);
+  let version = match fs::read_to_string(&marker) {
+    Ok(contents) => Some(contents.trim().parse::<u32>().ok()),
+    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+    Err(err) => return Err(err.into()),
+  };
+  let check = match version {
+    Some(Some(CACHE_LAYOUT_VERSION)) => return Ok(CacheLayoutCheck::Current),
+    None if cached_ledgers(dir)?.is_empty() && orphaned_sidecars(dir)?.is_empty() => CacheLayoutCheck::Initialized,
+    None => {
+      let orphans = orphaned_sidecars(dir)?;
+      for path in &orphans {
+        fs::remove_file(path)?;
+      }
+      tracing::info!("Migrated ledger cache {} from layout 1, removed {} orphaned files", dir.display(), orphans.len());
+      CacheLayoutCheck::Migrated { from: 1 }
+    }
+    Some(from) => {
+      let cleared = clear_ledger_cache(dir, None, false)?;
+      for path in orphaned_sidecars(dir)? {
+        fs::remove_file(path)?;
+      }
+      tracing::warn!("Ledger cache {} is in unknown layout {:?}, cleared {} files", dir.display(), from, cleared.count);
+      CacheLayoutCheck::Cleared { from }
+    }
+  };
+  fs::write(marker, CACHE_LAYOUT_VERSION.to_string())?;
+  Ok(check)
+}
+
+/// `.etag` and `.key` sidecars whose `<hash>.json` ledger is missing.
+fn orphaned_sidecars(dir: &Path) -> Result<Vec<PathBuf>> {
+  let mut orphans = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+      continue;
+    };
+    let Some(hash) = name.strip_suffix(".etag").or_else(|| name.strip_suffix(".key")) else {
+      continue;
+    };
+    if validate_ledger_hash(hash).is_ok() && !ledger_cache_path(dir, hash).exists() {
+      orphans.push(path);
+    }
+  }
+  orphans.sort();
+  Ok(orphans)
+}
+
+/// A ledger found in the on-disk cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedLedger {
+  pub hash: String,
+  pub path: PathBuf,
+  pub size_bytes: u64,
+  pub etag: Option<String>,
+}
+
+/// Lists the `<hash>.json` files under `dir`, sorted by hash. Other files,
+/// such as the proposals fallback manifest, are ignored.
+pub fn cached_ledgers(dir: &Path) -> Result<Vec<CachedLedger>> {
+  let mut ledgers = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    let path = entry.path();
+    let Some(hash) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".json"))
+    else {
+      continue;
+    };
+    if validate_ledger_hash(hash).is_err() || !entry.file_type()?.is_file() {
+      continue;
+    }
+    let hash = hash.to_string();
+    let etag = fs::read_to_string(ledger_etag_path(dir, &hash)).ok().map(|etag| etag.trim().to_string());
+    ledgers.push(CachedLedger { size_bytes: entry.metadata()?.len(), etag, hash, path });
+  }
+  ledgers.sort_by(|a, b| a.hash.cmp(&b.hash));
+  Ok(ledgers)
+}
+
+/// Rejects anything but a plain alphanumeric ledger hash, so user input can't
+/// escape the cache directory.
+pub fn validate_ledger_hash(hash: &str) -> Result<()> {
+  if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+    bail!("Invalid ledger hash '{}'", hash);
+  }
+  Ok(())
+}
+
+/// Removes the cached ledger `hash` (with its sidecars), or every cached
+/// ledger when `hash` is `None`. With `dry_run`, only reports what would be
+/// removed.
+pub fn clear_ledger_cache(dir: &Path, hash: Option<&str>, dry_run: bool) -> Result<LedgerCacheClearResponse> {
+  let mut paths = match hash {
+    Some(hash) => {
+      validate_ledger_hash(hash)?;
+      vec![ledger_cache_path(dir, hash), ledger_etag_path(dir, hash), ledger_object_key_path(dir, hash)]
+    }
+    None => cached_ledgers(dir)?
+      .into_iter()
+      .flat_map(|ledger| {
+        [ledger_etag_path(dir, &ledger.hash), ledger_object_key_path(dir, &ledger.hash), ledger.path]
+      })
+      .collect(),
+  };
+  paths.sort();
+
+  let mut files = Vec::new();
+  let mut bytes_freed = 0;
+  for path in paths {
+    let Ok(metadata) = fs::metadata(&path) else {
+      continue;
+    };
+    if !dry_run {
+      fs::remove_file(&path)?;
+    }
+    bytes_freed += metadata.len();
+    files.push(path.display().to_string());
+  }
+
+  Ok(LedgerCacheClearResponse { dry_run, count: files.len(), bytes_freed, files })
+}
+
+/// Marks the cached ledger at `path` as used now, by bumping its modification
+/// time, which [`prune_ledger_cache`] evicts by.
+pub fn touch_cached_ledger(path: &Path) -> io::Result<()> {
+  fs::File::options().write(true).open(path)?.set_modified(SystemTime::now())
+}
+
+/// Evicts cached ledgers by age and then by size.
+///
+/// Removes ledgers last used more than `max_age` before `now`, then the least
+/// recently used ones until the cache holds at most `max_bytes`. Ledgers in
+/// `keep` are never removed, though they count towards the size.
+pub fn prune_ledger_cache(
+  dir: &Path,
+  max_bytes: Option<u64>,
+  max_age: Option<Duration>,
+  now: SystemTime,
+  keep: &HashSet<String>,
+) -> Result<LedgerCachePruneResponse> {
+  let mut ledgers = Vec::new();
+  for ledger in cached_ledgers(dir)? {
+    let last_used = fs::metadata(&ledger.path)?.modified()?;
+    ledgers.push((last_used, ledger));
+  }
+  ledgers.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+  let mut bytes_remaining: u64 = ledgers.iter().map(|(_, ledger)| ledger.size_bytes).sum();
+  let mut response = LedgerCachePruneResponse::default();
+  for (last_used, ledger) in ledgers {
+    let expired = max_age.is_some_and(|max_age| now.duration_since(last_used).unwrap_or_default() > max_age);
+    let oversized = max_bytes.is_some_and(|max_bytes| bytes_remaining > max_bytes);
+    if keep.contains(&ledger.hash) || !(expired || oversized) {
+      continue;
+    }
+    let cleared = clear_ledger_cache(dir, Some(&ledger.hash), false)?;
+    bytes_remaining -= ledger.size_bytes;
+    response.bytes_freed += cleared.bytes_freed;
+    response.pruned.push(ledger.hash);
+  }
+  response.bytes_remaining = bytes_remaining;
+  Ok(response)
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedgerCachePruneResponse {
+  /// Hashes of the removed ledgers, least recently used first.
+  pub pruned: Vec<String>,
+  /// Bytes reclaimed, sidecars included.
+  pub bytes_freed: u64,
+  /// Size of the ledgers left in the cache.
+  pub bytes_remaining: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerCacheClearResponse {
+  pub dry_run: bool,
+  pub count: usize,
+  pub bytes_freed: u64,
+  pub files: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerCacheStatusResponse {
+  pub ledger_storage_path: String,
+  pub bucket_name: String,
+  pub ledgers: Vec<LedgerCacheEntryStatus>,
+}
+
+/// Cache state of a ledger that is either cached or referenced by a proposal.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerCacheEntryStatus {
+  pub hash: String,
+  /// Referenced by a loaded proposal.
+  pub expected: bool,
+  pub cached: bool,
+  pub size_bytes: Option<u64>,
+  /// Bucket object holding this ledger, if any.
+  pub object_key: Option<String>,
+  /// Whether the cached copy was downloaded from the current version of the
+  /// object. `None` when either etag is unknown.
+  pub etag_match: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ocv-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn test_cached_ledgers() {
+    let dir = temp_cache_dir("cached-ledgers");
+    fs::write(ledger_cache_path(&dir, "jxB"), "[1]").unwrap();
+    fs::write(ledger_cache_path(&dir, "jxA"), "[]").unwrap();
+    fs::write(ledger_etag_path(&dir, "jxA"), "abc\n").unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+    fs::write(dir.join("proposals.last-known-good.json"), "{}").unwrap();
+
+    let ledgers = cached_ledgers(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(ledgers.len(), 2);
+    assert_eq!(ledgers[0].hash, "jxA");
+    assert_eq!(ledgers[0].etag.as_deref(), Some("abc"));
+    assert_eq!(ledgers[1].hash, "jxB");
+    assert_eq!(ledgers[1].size_bytes, 3);
+    assert_eq!(ledgers[1].etag, None);
+  }
+
+  #[test]
+  fn test_ensure_cache_layout() {
+    let dir = temp_cache_dir("cache-layout");
+    assert_eq!(ensure_cache_layout(&dir).unwrap(), CacheLayoutCheck::Initialized);
+    assert_eq!(ensure_cache_layout(&dir).unwrap(), CacheLayoutCheck::Current);
+
+    // A version 1 cache: no layout file, and a sidecar left from a ledger
+    // removed by hand.
+    fs::remove_file(dir.join(CACHE_LAYOUT_FILE)).unwrap();
+    fs::write(ledger_cache_path(&dir, "jxA"), "[]").unwrap();
+    fs::write(ledger_etag_path(&dir, "jxA"), "abc").unwrap();
+    fs::write(ledger_etag_path(&dir, "jxB"), "def").unwrap();
+    assert_eq!(ensure_cache_layout(&dir).unwrap(), CacheLayoutCheck::Migrated { from: 1 });
+    assert!(ledger_etag_path(&dir, "jxA").exists());
+    assert!(!ledger_etag_path(&dir, "jxB").exists());
+    assert_eq!(cached_ledgers(&dir).unwrap().len(), 1);
+
+    fs::write(dir.join(CACHE_LAYOUT_FILE), "99\n").unwrap();
+    assert_eq!(ensure_cache_layout(&dir).unwrap(), CacheLayoutCheck::Cleared { from: Some(99) });
+    assert!(cached_ledgers(&dir).unwrap().is_empty());
+    assert_eq!(ensure_cache_layout(&dir).unwrap(), CacheLayoutCheck::Current);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_clear_ledger_cache() {
+    let dir = temp_cache_dir("clear-ledger-cache");
+    fs::write(ledger_cache_path(&dir, "jxA"), "[]").unwrap();
+    fs::write(ledger_etag_path(&dir, "jxA"), "abc").unwrap();
+    fs::write(ledger_cache_path(&dir, "jxB"), "[1]").unwrap();
+
+    let dry_run = clear_ledger_cache(&dir, None, true).unwrap();
+    assert_eq!((dry_run.count, dry_run.bytes_freed), (3, 8));
+    assert_eq!(cached_ledgers(&dir).unwrap().len(), 2);
+
+    let single = clear_ledger_cache(&dir, Some("jxA"), false).unwrap();
+    assert_eq!((single.count, single.bytes_freed), (2, 5));
+    assert_eq!(cached_ledgers(&dir).unwrap().len(), 1);
+
+    assert!(clear_ledger_cache(&dir, Some("../jxB"), false).is_err());
+    assert!(clear_ledger_cache(&dir, Some(""), false).is_err());
+
+    let all = clear_ledger_cache(&dir, None, false).unwrap();
+    assert_eq!((all.count, all.bytes_freed), (1, 3));
+    assert!(cached_ledgers(&dir).unwrap().is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_prune_ledger_cache() {
+    let dir = temp_cache_dir("prune-ledger-cache");
+    let now = SystemTime::now();
+    // Least recently used first: jxC (3 days ago), jxA, jxB, jxD (an hour ago).
+    let ledgers = [("jxA", "[10]", 48), ("jxB", "[100]", 24), ("jxC", "[1]", 72), ("jxD", "[]", 1)];
+    for (hash, contents, hours_ago) in ledgers {
+      let path = ledger_cache_path(&dir, hash);
+      fs::write(&path, contents).unwrap();
+      let last_used = now - Duration::from_secs(hours_ago * 3600);
+      fs::File::options().write(true).open(&path).unwrap().set_modified(last_used).unwrap();
+    }
+    fs::write(ledger_etag_path(&dir, "jxA"), "abc").unwrap();
+    let keep = HashSet::from(["jxC".to_string()]);
+
+    let unlimited = prune_ledger_cache(&dir, None, None, now, &keep).unwrap();
+    assert!(unlimited.pruned.is_empty());
+    assert_eq!(unlimited.bytes_remaining, 14);
+
+    // jxC is expired too, but kept.
+    let expired = prune_ledger_cache(&dir, None, Some(Duration::from_secs(36 * 3600)), now, &keep).unwrap();
+    assert_eq!(expired.pruned, ["jxA"]);
+    assert_eq!((expired.bytes_freed, expired.bytes_remaining), (7, 10));
+
+    // Touching jxB makes jxD the least recently used.
+    touch_cached_ledger(&ledger_cache_path(&dir, "jxB")).unwrap();
+    let oversized = prune_ledger_cache(&dir, Some(4), None, now, &keep).unwrap();
+    assert_eq!(oversized.pruned, ["jxD", "jxB"]);
+    assert_eq!(oversized.bytes_remaining, 3);
+
+    // The kept ledger alone exceeds the limit.
+    assert!(prune_ledger_cache(&dir, Some(0), None, now, &keep).unwrap().pruned.is_empty());
+    assert_eq!(cached_ledgers(&dir).unwrap().len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,24 +1,45 @@
 mod archive;
+mod audit;
+mod cache;
 mod config;
+mod error;
+mod export;
 mod ledger;
+mod ledger_cache;
+mod metrics;
 mod ocv;
 mod proposals;
+mod proposals_cli;
+mod proposals_loader;
+pub mod proto;
+mod public_key;
 mod ranked_vote;
 mod ranked_vote_builder;
 mod ranked_vote_config;
+mod results_archive;
 mod serve;
 mod storage;
 mod util;
 mod vote;
 
 pub use archive::*;
+pub use audit::*;
+pub use cache::*;
 pub use config::*;
+pub use error::*;
+pub use export::*;
 pub use ledger::*;
+pub use ledger_cache::*;
+pub use metrics::*;
 pub use ocv::*;
 pub use proposals::*;
+pub use proposals_cli::*;
+pub use proposals_loader::*;
+pub use public_key::*;
 pub use ranked_vote::*;
 pub use ranked_vote_builder::*;
 pub use ranked_vote_config::*;
+pub use results_archive::*;
 pub use serve::*;
 pub use storage::*;
 pub use util::*;
@@ -0,0 +1,133 @@
+use std::{
+  collections::BTreeMap,
+  fmt::Write,
+  sync::{Arc, RwLock},
+};
+
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+
+use crate::{CircuitState, TallyMode};
+
+/// Gauges describing the latest tally of each proposal, exported in the
+/// Prometheus text format.
+#[derive(Clone, Default)]
+pub struct ProposalMetrics {
+  tallies: Arc<RwLock<BTreeMap<usize, ProposalTallyGauges>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ProposalTallyGauges {
+  mode: TallyMode,
+  yes_votes: usize,
+  no_votes: usize,
+  participation_ratio: f64,
+}
+
+impl ProposalMetrics {
+  /// Records a tally of proposal `id`, replacing the previous one. The
+  /// participation ratio is the counted voting weight over the ledger's total
+  /// stake.
+  pub fn record_tally(
+    &self,
+    id: usize,
+    mode: TallyMode,
+    yes_votes: usize,
+    no_votes: usize,
+    vote_weight: Decimal,
+    ledger_total_stake: Decimal,
+  ) {
+    let participation_ratio = match ledger_total_stake.is_zero() {
+      true => 0.0,
+      false => (vote_weight / ledger_total_stake).to_f64().unwrap_or_default(),
+    };
+    let gauges = ProposalTallyGauges { mode, yes_votes, no_votes, participation_ratio };
+    self.tallies.write().expect("proposal metrics lock poisoned").insert(id, gauges);
+  }
+
+  /// Renders the gauges in the Prometheus text exposition format.
+  pub fn render(&self) -> String {
+    let tallies = self.tallies.read().expect("proposal metrics lock poisoned");
+    let mut out = String::new();
+    out.push_str("# HELP proposal_votes_total Votes counted in the latest tally of a proposal.\n");
+    out.push_str("# TYPE proposal_votes_total gauge\n");
+    for (id, gauges) in tallies.iter() {
+      let mode = mode_label(gauges.mode);
+      for (choice, votes) in [("yes", gauges.yes_votes), ("no", gauges.no_votes)] {
+        let labels = format!("proposal_id=\"{id}\",choice=\"{choice}\",mode=\"{mode}\"");
+        let _ = writeln!(out, "proposal_votes_total{{{labels}}} {votes}");
+      }
+    }
+    out.push_str(
+      "# HELP proposal_participation_ratio Voting weight counted in the latest tally of a proposal over the ledger's \
+       total stake.\n",
+    );
+    out.push_str("# TYPE proposal_participation_ratio gauge\n");
+    for (id, gauges) in tallies.iter() {
+      let (mode, ratio) = (mode_label(gauges.mode), gauges.participation_ratio);
+      let _ = writeln!(out, "proposal_participation_ratio{{proposal_id=\"{id}\",mode=\"{mode}\"}} {ratio}");
+    }
+    out
+  }
+}
+
+/// Renders the storage circuit breaker state as a gauge: 0 closed, 1
+/// half-open, 2 open.
+pub fn render_circuit_state(state: CircuitState) -> String {
+  let value = match state {
+    CircuitState::Closed => 0,
+    CircuitState::HalfOpen => 1,
+    CircuitState::Open => 2,
+  };
+  format!(
+    "# HELP storage_circuit_breaker_state Storage circuit breaker state (0 closed, 1 half-open, 2 open).\n\
+     # TYPE storage_circuit_breaker_state gauge\nstorage_circuit_breaker_state {value}\n"
+  )
+}
+
+/// Renders the number of archive queries waiting for a permit as a gauge.
+pub fn render_queued_archive_queries(queued: usize) -> String {
+  format!(
+    "# HELP archive_queries_queued Archive queries waiting for a concurrency permit.\n\
+     # TYPE archive_queries_queued gauge\narchive_queries_queued {queued}\n"
+  )
+}
+
+fn mode_label(mode: TallyMode) -> &'static str {
+  match mode {
+    TallyMode::Live => "live",
+    TallyMode::Final => "final",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_render() {
+    let metrics = ProposalMetrics::default();
+    metrics.record_tally(2, TallyMode::Live, 3, 1, Decimal::new(25, 0), Decimal::new(100, 0));
+    metrics.record_tally(1, TallyMode::Live, 1, 0, Decimal::ONE, Decimal::ZERO);
+    metrics.record_tally(1, TallyMode::Final, 4, 2, Decimal::new(5, 0), Decimal::new(10, 0));
+
+    let rendered = metrics.render();
+    let samples: Vec<&str> = rendered.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(samples, [
+      "proposal_votes_total{proposal_id=\"1\",choice=\"yes\",mode=\"final\"} 4",
+      "proposal_votes_total{proposal_id=\"1\",choice=\"no\",mode=\"final\"} 2",
+      "proposal_votes_total{proposal_id=\"2\",choice=\"yes\",mode=\"live\"} 3",
+      "proposal_votes_total{proposal_id=\"2\",choice=\"no\",mode=\"live\"} 1",
+      "proposal_participation_ratio{proposal_id=\"1\",mode=\"final\"} 0.5",
+      "proposal_participation_ratio{proposal_id=\"2\",mode=\"live\"} 0.25",
+    ]);
+    assert!(rendered.contains("# TYPE proposal_participation_ratio gauge"));
+  }
+
+  #[test]
+  fn test_render_circuit_state() {
+    let rendered = render_circuit_state(CircuitState::Open);
+    assert!(rendered.contains("# TYPE storage_circuit_breaker_state gauge\n"));
+    assert!(rendered.ends_with("\nstorage_circuit_breaker_state 2\n"));
+    assert!(render_queued_archive_queries(3).ends_with("\narchive_queries_queued 3\n"));
+  }
+}
@@ -1,47 +1,114 @@
-use std::{path::PathBuf, sync::Arc};
+#[cfg(any(test, feature = "test-util"))]
+use std::{
+  ops::{Deref, DerefMut},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+  future::Future,
+  path::PathBuf,
+  sync::Arc,
+  time::{Duration, Instant, SystemTime},
+};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, stream};
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-  Archive, ElectionResult, ElectionStats, Ledger, Network, Proposal, RankedVote, ReleaseStage, Vote, VoteRules,
-  VoteWithWeight, Wrapper, ranked_vote::run_simple_election, storage::StorageProvider,
+  ArchiveInterface, AuditInputs, AuditLog, BlockStatus, CachedTally, Caches, Clock, ElectionResult, ElectionStats,
+  EpochLedger, FetchTransactionResult, Ledger, LedgerCacheClearResponse, LedgerCacheEntryStatus,
+  LedgerCachePruneResponse, LedgerCacheStats, LedgerCacheStatusResponse, LedgerKind, LedgerObjectKey, MemoCheck,
+  Network, Proposal, ProposalMetrics, ProposalPhase, ProposalsLoader, PublicKey, QueryLimiter, RankedVote, ReleaseStage,
+  RequestDeadline, TallyAuditRecord, TallyError, TimeseriesBucket, Vote, VoteChoice, VoteDetail, VoteKeywords,
+  VoteRules, VoteTimeseriesPoint, VoteWithWeight, WeightingScheme, WindowBound, WindowMarker, Wrapper,
+  archived_result_path, cached_ledgers, clear_ledger_cache, decode_memo_text, ledger::object_key_has_hash, proto,
+  prune_ledger_cache, ranked_vote::run_simple_election, read_archived_result, render_ledger_key_prefix,
+  storage::StorageProvider, write_archived_result,
 };
 
 #[derive(Clone)]
 pub struct Ocv {
-  pub archive: Archive,
+  pub archive: Arc<dyn ArchiveInterface + Send + Sync>,
   pub network: Network,
   pub release_stage: ReleaseStage,
   pub ledger_storage_path: PathBuf,
   pub bucket_name: String,
+  pub ledger_key_prefix: String,
   pub storage_provider: Arc<dyn StorageProvider + Send + Sync>,
-  pub proposals: Vec<Proposal>,
+  pub proposals: Arc<ArcSwap<Vec<Proposal>>>,
+  pub proposals_loader: ProposalsLoader,
+  pub confirmation_depth: i64,
+  /// Votes listed in a proposal or result response before it is truncated.
+  pub max_votes_in_response: usize,
+  /// Check cached ledgers against their bucket object's etag on every read.
+  pub cache_validate_on_read: bool,
+  /// Answer result requests from the archived result of closed proposals.
+  pub serve_archived_results: bool,
+  /// Bucket key prefix archived results are also uploaded under.
+  pub archived_results_key_prefix: Option<String>,
+  /// Longest a single archive query may take before the request fails.
+  pub db_query_timeout: Duration,
+  /// Bounds the archive queries in flight at once.
+  pub archive_queries: QueryLimiter,
+  /// Longest an HTTP request may spend on storage downloads, if bounded.
+  pub request_timeout: Option<Duration>,
+  /// When the storage downloads of the request this copy serves are
+  /// abandoned; see [`Ocv::with_deadline`].
+  pub request_deadline: RequestDeadline,
+  /// Largest ledger object, or extracted ledger file, that is downloaded.
+  pub max_ledger_bytes: u64,
+  /// How long a live tally is cached.
+  pub live_tally_ttl: Duration,
+  /// How long clients and shared caches may keep a finalized result.
+  pub final_results_max_age: Duration,
+  /// Widening of timestamp vote windows on each side, tolerating archive
+  /// block timestamps that lag or lead wall-clock time.
+  pub window_skew: Duration,
+  /// Expected range of a staking ledger's total stake; ledgers outside it are
+  /// logged as suspect.
+  pub min_ledger_total_stake: Option<Decimal>,
+  pub max_ledger_total_stake: Option<Decimal>,
+  /// Stake below which votes aren't counted, for proposals that don't set
+  /// their own.
+  pub min_voting_stake: Option<Decimal>,
+  pub clock: Arc<dyn Clock + Send + Sync>,
+  pub caches: Caches,
+  pub metrics: ProposalMetrics,
+  pub audit_log: Arc<AuditLog>,
 }
 
 impl Ocv {
+  /// The current time according to `clock`.
+  pub fn now(&self) -> DateTime<Utc> {
+    self.clock.now()
+  }
+
   pub async fn info(&self) -> Result<GetCoreApiInfoResponse> {
-    let chain_tip = self.archive.fetch_chain_tip()?;
-    let current_slot = self.archive.fetch_latest_slot()?;
-    Ok(GetCoreApiInfoResponse { chain_tip, current_slot })
+    let chain_tip = self.archive_chain_tip().await?;
+    let current_slot = self.archive_latest_slot().await?;
+    Ok(GetCoreApiInfoResponse { chain_tip, current_slot, ledger_cache: self.caches.ledger_stats() })
   }
 
   pub async fn proposal(&self, id: usize) -> Result<ProposalResponse> {
     let proposal = self.find_proposal(id)?;
 
-    let transactions = self.archive.fetch_transactions(proposal.start_time, proposal.end_time)?;
+    let transactions = self.proposal_transactions(&proposal).await?;
     tracing::info!("Fetched {} transactions for proposal {}", transactions.len(), id);
 
-    let chain_tip = self.archive.fetch_chain_tip()?;
+    let chain_tip = self.archive_chain_tip().await?;
 
-    let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
-      .process(&proposal.key, chain_tip)
+    let mut votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
+      .process_keywords(&proposal.vote_keywords(), chain_tip, self.confirmation_depth)
       .sort_by_timestamp()
       .to_vec()
       .0;
+    let (total_votes, truncated) = truncate_votes(&mut votes, self.max_votes_in_response);
 
-    Ok(ProposalResponse { proposal, votes })
+    Ok(ProposalResponse { proposal, votes, total_votes, truncated })
   }
 
   /// Checks whether the positive community vote threshold has been met based
@@ -83,11 +150,11 @@ impl Ocv {
     end_time: i64,
     ledger_hash: Option<String>,
   ) -> Result<GetMinaProposalConsiderationResponse> {
-    let transactions = self.archive.fetch_transactions(start_time, end_time)?;
+    let transactions = self.archive_transactions(start_time, end_time).await?;
 
-    let chain_tip = self.archive.fetch_chain_tip()?;
+    let chain_tip = self.archive_chain_tip().await?;
     let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
-      .process_mep(round_id, proposal_id, chain_tip)
+      .process_mep(round_id, proposal_id, chain_tip, self.confirmation_depth)
       .sort_by_timestamp()
       .to_vec()
       .0;
@@ -127,14 +194,14 @@ impl Ocv {
 
     // Calculate weighted votes if ledger_hash params is provided
     if let Some(hash) = ledger_hash {
-      let transactions = self.archive.fetch_transactions(start_time, end_time)?;
+      let transactions = self.archive_transactions(start_time, end_time).await?;
 
-      let chain_tip = self.archive.fetch_chain_tip()?;
+      let chain_tip = self.archive_chain_tip().await?;
 
-      let ledger = Ledger::fetch(self, &hash).await?;
+      let ledger = Ledger::fetch(self, &hash, None).await?;
 
       let votes_weighted = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
-        .into_weighted_mep(round_id, proposal_id, &ledger, chain_tip)
+        .into_weighted_mep(round_id, proposal_id, &ledger, chain_tip, self.confirmation_depth, self.min_voting_stake)
         .sort_by_timestamp()
         .0;
 
@@ -169,50 +236,497 @@ impl Ocv {
     })
   }
 
-  pub async fn proposal_result(&self, id: usize) -> Result<GetMinaProposalResultResponse> {
+  /// Determines whether a proposal's result is still live or final, based on
+  /// whether its voting window has closed.
+  pub fn tally_mode(&self, proposal: &Proposal) -> Result<TallyMode> {
+    let closed = match proposal.window_end() {
+      WindowBound::Timestamp(end) => self.now().timestamp_millis() > end,
+      WindowBound::Slot(end) => self.archive.current_epoch_and_slot()?.1 as i64 > end,
+    };
+    Ok(if closed { TallyMode::Final } else { TallyMode::Live })
+  }
+
+  /// The inputs of proposal `id`'s current result.
+  pub async fn proposal_inputs(&self, id: usize) -> Result<TallyInputs> {
+    Ok(self.proposal_result(id, None).await?.inputs())
+  }
+
+  /// Tallies the proposals `ids` concurrently. A ledger used by several of
+  /// them is downloaded once. Failures are reported per proposal.
+  pub async fn proposal_results(&self, ids: &[usize]) -> BulkResultsResponse {
+    self.bulk(ids, |id| async move { self.proposal_result(id, None).await }).await
+  }
+
+  /// Like `proposal_results`, with each proposal's votes unweighted, as from
+  /// `proposal`.
+  pub async fn proposal_votes(&self, ids: &[usize]) -> BulkResultsResponse<ProposalResponse> {
+    self.bulk(ids, |id| self.proposal(id)).await
+  }
+
+  /// Runs `run` for each distinct id, at most `BULK_CONCURRENCY` at once.
+  async fn bulk<T, F, Fut>(&self, ids: &[usize], run: F) -> BulkResultsResponse<T>
+  where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = Result<T>>,
+  {
+    let ids: BTreeSet<usize> = ids.iter().copied().collect();
+    let mut outcomes = stream::iter(ids)
+      .map(|id| {
+        let outcome = run(id);
+        async move { (id, outcome.await) }
+      })
+      .buffer_unordered(BULK_CONCURRENCY);
+    let mut response = BulkResultsResponse { results: BTreeMap::new(), errors: BTreeMap::new() };
+    while let Some((id, outcome)) = outcomes.next().await {
+      match outcome {
+        Ok(result) => {
+          response.results.insert(id, result);
+        }
+        Err(err) => {
+          tracing::warn!("Bulk tally of proposal {} failed: {}", id, err);
+          response.errors.insert(id, err.to_string());
+        }
+      }
+    }
+    response
+  }
+
+  /// Tallies every proposal open at `now`.
+  pub async fn tally_all_open(&self, now: DateTime<Utc>) -> Result<BulkResultsResponse> {
+    let ids: Vec<usize> = self.open_proposals(now)?.iter().map(|proposal| proposal.id).collect();
+    Ok(self.proposal_results(&ids).await)
+  }
+
+  /// Tallies proposal `id`. A `ledger_hash_override` replaces the proposal's
+  /// ledger and must match a bucket object exactly.
+  ///
+  /// Tallies without an override are cached: live ones for
+  /// `live_tally_ttl`, final ones until the archive's chain tip moves. With
+  /// `cache_validate_on_read`, final tallies are recomputed so a changed
+  /// ledger object is picked up.
+  pub async fn proposal_result(
+    &self,
+    id: usize,
+    ledger_hash_override: Option<String>,
+  ) -> Result<GetMinaProposalResultResponse> {
+    self.tally(id, ledger_hash_override, false).await
+  }
+
+  /// The result of proposal `id`. When `strict`, fails with
+  /// `TallyError::LedgerUnavailable` if any ledger the proposal needs is
+  /// missing, instead of weighting the votes it covers by another ledger, or
+  /// counting no stake at all, with a `MissingLedger` warning.
+  pub async fn tally(&self, id: usize, ledger_hash_override: Option<String>, strict: bool) -> Result<TallyResult> {
+    if ledger_hash_override.is_some() {
+      return self.tally_proposal(id, ledger_hash_override, strict).await;
+    }
+    if self.serve_archived_results {
+      if let Some(result) = read_archived_result(&self.ledger_storage_path, id)? {
+        return Ok(result);
+      }
+    }
+    if let Some(cached) = self.caches.tallies.get(&id).await {
+      let fresh_final = !self.cache_validate_on_read && cached.chain_tip == self.archive_chain_tip().await?;
+      let partial = cached.result.warnings.iter().any(|warning| warning.code == TallyWarningCode::MissingLedger);
+      if (cached.ttl.is_some() || fresh_final) && !(strict && partial) {
+        return Ok(cached.result.as_ref().clone());
+      }
+    }
+    let chain_tip = self.archive_chain_tip().await?;
+    let result = self.tally_proposal(id, None, strict).await?;
+    let ttl = result.metadata.cache_ttl_secs.map(Duration::from_secs);
+    self.caches.tallies.insert(id, CachedTally { result: Arc::new(result.clone()), chain_tip, ttl }).await;
+    Ok(result)
+  }
+
+  async fn tally_proposal(
+    &self,
+    id: usize,
+    ledger_hash_override: Option<String>,
+    strict: bool,
+  ) -> Result<GetMinaProposalResultResponse> {
     let proposal = self.find_proposal(id)?;
-    let hash = match proposal.ledger_hash.clone() {
+    self.check_network(&proposal)?;
+    let mode = self.tally_mode(&proposal)?;
+    let mut metadata = TallyMetadata {
+      mode,
+      cache_ttl_secs: (mode == TallyMode::Live).then_some(self.live_tally_ttl.as_secs()),
+      confirmation_depth: self.confirmation_depth,
+      pending_votes: 0,
+      weighting: proposal.weighting,
+      positive_votes: 0,
+      negative_votes: 0,
+      vote_threshold_met: false,
+      ledger_hashes: Vec::new(),
+      staking_epoch: proposal.epoch,
+      window_start: Some(proposal.window_start()),
+      window_end: Some(proposal.window_end()),
+      window_skew_ms: self.window_skew.as_millis() as i64,
+      chain_tip: None,
+      min_block_height: None,
+      max_block_height: None,
+      input_digest: String::new(),
+    };
+    let hash = match ledger_hash_override.clone() {
+      Some(hash) => Some(hash),
+      None => self.proposal_ledger_hash(&proposal).await?,
+    };
+    let hash = match hash {
+      None if strict => {
+        return Err(TallyError::LedgerUnavailable { epoch: Some(proposal.epoch), hash: "unknown".to_string() }.into());
+      }
       None => {
+        let warning = TallyWarning {
+          code: TallyWarningCode::MissingLedger,
+          message: format!("The ledger of epoch {} is not known yet, so no stake was counted", proposal.epoch),
+          count: None,
+        };
         return Ok(GetMinaProposalResultResponse {
           proposal,
           total_stake_weight: Decimal::ZERO,
           positive_stake_weight: Decimal::ZERO,
           negative_stake_weight: Decimal::ZERO,
           votes: Vec::new(),
+          total_votes: 0,
+          truncated: false,
+          below_threshold: BelowThresholdVotes::default(),
+          metadata,
+          warnings: vec![warning],
+          finalized_at: None,
         });
       }
       Some(value) => value,
     };
 
-    let transactions = self.archive.fetch_transactions(proposal.start_time, proposal.end_time)?;
+    let transactions = self.proposal_transactions(&proposal).await?;
+
+    let chain_tip = self.archive_chain_tip().await?;
+    metadata.chain_tip = Some(chain_tip);
 
-    let chain_tip = self.archive.fetch_chain_tip()?;
+    let ledger = match ledger_hash_override {
+      Some(_) => Ledger::fetch_exact(self, &hash, proposal.network).await?,
+      None => Ledger::fetch_for_proposal(self, &hash, &proposal).await?,
+    };
+    let mut warnings = Vec::new();
+    let epoch_ledgers = match ledger_hash_override {
+      Some(_) => BTreeMap::new(),
+      None => self.epoch_ledgers(&proposal, strict, &mut warnings).await?,
+    };
+    metadata.ledger_hashes =
+      std::iter::once(hash.clone()).chain(epoch_ledgers.values().map(|(hash, _)| hash.clone())).collect();
+    let vote_epochs: HashMap<String, i64> =
+      transactions.iter().map(|transaction| (transaction.hash.clone(), transaction.epoch())).collect();
+    // Epoch whose ledger weights the vote in transaction `hash`; `None` for the
+    // proposal's own ledger.
+    let ledger_epoch =
+      |hash: &str| vote_epochs.get(hash).copied().filter(|epoch| epoch_ledgers.contains_key(epoch));
 
-    let ledger = Ledger::fetch(self, &hash).await?;
+    let keywords = proposal.vote_keywords();
+    if proposal.network != self.network {
+      warnings.push(TallyWarning {
+        code: TallyWarningCode::NetworkMismatch,
+        message: format!(
+          "Proposal {} is for {}, but votes are read from this server's {} archive",
+          proposal.id, proposal.network, self.network
+        ),
+        count: None,
+      });
+    }
+    if let Some(object_key) = self.caches.ledger_object_keys.get(&hash).await {
+      if !object_key_has_hash(&object_key, &hash) {
+        warnings.push(TallyWarning {
+          code: TallyWarningCode::InexactLedgerMatch,
+          message: format!("Ledger {hash} was loaded from {object_key}, whose name only contains the hash"),
+          count: None,
+        });
+      }
+    }
 
-    let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
-      .into_weighted(&proposal, &ledger, chain_tip)
-      .sort_by_timestamp()
-      .0;
+    let raw_votes: Vec<Vote> = transactions.into_iter().map(std::convert::Into::into).collect();
+    let superseded = superseded_votes(&raw_votes, &keywords);
+    if superseded > 0 {
+      warnings.push(TallyWarning {
+        code: TallyWarningCode::SupersededVotes,
+        message: format!("{superseded} votes were replaced by a later vote from the same account"),
+        count: Some(superseded),
+      });
+    }
+
+    let ledger_for = |vote: &Vote| match ledger_epoch(&vote.hash).and_then(|epoch| epoch_ledgers.get(&epoch)) {
+      Some((_, epoch_ledger)) => epoch_ledger.as_ref(),
+      None => ledger.as_ref(),
+    };
+    let min_voting_stake = proposal.min_voting_stake.or(self.min_voting_stake);
+    let (votes, below_threshold) = Wrapper(raw_votes).into_weighted_above(
+      &proposal,
+      ledger_for,
+      chain_tip,
+      self.confirmation_depth,
+      min_voting_stake,
+    );
+    let mut votes = votes.sort_by_timestamp().0;
+    let below_threshold = BelowThresholdVotes {
+      min_voting_stake,
+      votes: below_threshold.len(),
+      stake_weight: below_threshold.iter().map(|vote| vote.weight).sum(),
+    };
+
+    let mut ledger_accounts: HashSet<(Option<i64>, &str)> =
+      ledger.0.iter().map(|account| (None, account.pk.as_str())).collect();
+    for (epoch, (_, epoch_ledger)) in &epoch_ledgers {
+      ledger_accounts.extend(epoch_ledger.0.iter().map(|account| (Some(*epoch), account.pk.as_str())));
+    }
+    let missing = votes
+      .iter()
+      .filter(|vote| !ledger_accounts.contains(&(ledger_epoch(&vote.hash), vote.account.as_str())))
+      .count();
+    if missing > 0 {
+      let ledger_name = match epoch_ledgers.is_empty() {
+        true => format!("ledger {hash}"),
+        false => "the ledger of their vote's epoch".to_string(),
+      };
+      warnings.push(TallyWarning {
+        code: TallyWarningCode::VotersMissingFromLedger,
+        message: format!("{missing} voters are not in {ledger_name} and were counted with zero stake"),
+        count: Some(missing),
+      });
+    }
 
     let mut positive_stake_weight = Decimal::from(0);
     let mut negative_stake_weight = Decimal::from(0);
 
     for vote in &votes {
-      if vote.memo.split_whitespace().next().eq(&Some("no")) {
+      // Final results only count votes buried under the confirmation depth.
+      if mode == TallyMode::Final && vote.status == BlockStatus::Pending {
+        metadata.pending_votes += 1;
+        continue;
+      }
+      metadata.min_block_height = Some(metadata.min_block_height.map_or(vote.height, |min| min.min(vote.height)));
+      metadata.max_block_height = Some(metadata.max_block_height.map_or(vote.height, |max| max.max(vote.height)));
+      if keywords.choice(&vote.memo) == Some(VoteChoice::No) {
         negative_stake_weight += vote.weight;
+        metadata.negative_votes += 1;
       } else {
         positive_stake_weight += vote.weight;
+        metadata.positive_votes += 1;
       }
     }
+    metadata.vote_threshold_met = match proposal.quorum {
+      Some(quorum) => metadata.positive_votes >= quorum,
+      None => self.has_met_vote_threshold(metadata.positive_votes, metadata.negative_votes),
+    };
+    if ledger_hash_override.is_none() {
+      self.metrics.record_tally(
+        proposal.id,
+        mode,
+        metadata.positive_votes,
+        metadata.negative_votes,
+        positive_stake_weight + negative_stake_weight,
+        ledger.total_stake(),
+      );
+    }
+    let audit_inputs = AuditInputs::of(&metadata.ledger_hashes, &votes);
+    metadata.input_digest = audit_inputs.input_digest.clone();
+    let (total_votes, truncated) = truncate_votes(&mut votes, self.max_votes_in_response);
 
-    Ok(GetMinaProposalResultResponse {
+    let result = GetMinaProposalResultResponse {
       proposal,
       total_stake_weight: positive_stake_weight + negative_stake_weight,
       positive_stake_weight,
       negative_stake_weight,
       votes,
-    })
+      total_votes,
+      truncated,
+      below_threshold,
+      metadata,
+      warnings,
+      finalized_at: None,
+    };
+    // A tally against an overridden ledger isn't the proposal's result.
+    if mode == TallyMode::Final && ledger_hash_override.is_none() {
+      self.audit_tally(&result, audit_inputs, chain_tip).await;
+    }
+    Ok(result)
+  }
+
+  /// Appends the audit record of a final tally, unless the last record of the
+  /// proposal has the same inputs. Failing to write it is logged, but doesn't
+  /// fail the tally.
+  async fn audit_tally(&self, result: &GetMinaProposalResultResponse, inputs: AuditInputs, chain_tip: i64) {
+    let record = TallyAuditRecord {
+      sequence: 0,
+      recorded_at: self.now(),
+      proposal_id: result.proposal.id,
+      proposal_key: result.proposal.vote_keyword(),
+      ledger_hashes: result.metadata.ledger_hashes.clone(),
+      chain_tip,
+      inputs,
+      positive_votes: result.metadata.positive_votes,
+      negative_votes: result.metadata.negative_votes,
+      positive_stake_weight: result.positive_stake_weight,
+      negative_stake_weight: result.negative_stake_weight,
+      total_stake_weight: result.total_stake_weight,
+      outcome: result.vote_status().to_string(),
+    };
+    if let Err(err) = self.audit_log.append(record, self.storage_provider.as_ref(), &self.bucket_name).await {
+      tracing::error!("Could not write the audit record of proposal {}: {}", result.proposal.id, err);
+    }
+  }
+
+  /// Archives the result of every closed proposal whose tally can no longer
+  /// change: the window has closed and none of its votes are still pending.
+  /// Results go to the bucket when `archived_results_key_prefix` is set and
+  /// the provider can write, then to disk. A proposal whose upload or write
+  /// fails is left unarchived, to be retried on the next run. Returns the
+  /// newly archived ids.
+  pub async fn archive_closed_proposals(&self) -> Result<Vec<usize>> {
+    let mut archived = Vec::new();
+    for proposal in self.proposals.load_full().iter() {
+      if archived_result_path(&self.ledger_storage_path, proposal.id).exists()
+        || self.tally_mode(proposal)? != TallyMode::Final
+      {
+        continue;
+      }
+      let mut result = match self.tally(proposal.id, None, true).await {
+        Ok(result) => result,
+        Err(err) => {
+          tracing::warn!("Could not tally closed proposal {} for archiving: {}", proposal.id, err);
+          continue;
+        }
+      };
+      if result.metadata.pending_votes > 0 {
+        continue;
+      }
+
+      result.finalized_at = Some(self.now());
+      let bytes = serde_json::to_vec(&result)?;
+      match &self.archived_results_key_prefix {
+        Some(_) if !self.storage_provider.capabilities().can_write => {
+          tracing::info!(
+            "{} is read-only, archiving the result of proposal {} to disk only",
+            self.storage_provider.provider_name(),
+            proposal.id
+          );
+        }
+        Some(prefix) => {
+          let key = format!("{}{}.json", prefix, proposal.id);
+          if let Err(err) = self.storage_provider.put_object(&self.bucket_name, &key, bytes.clone().into()).await {
+            tracing::warn!("Could not upload the archived result of proposal {}: {}", proposal.id, err);
+            continue;
+          }
+        }
+        None => {}
+      }
+      if let Err(err) = write_archived_result(&self.ledger_storage_path, proposal.id, &bytes) {
+        tracing::warn!("Could not write the archived result of proposal {}: {}", proposal.id, err);
+        continue;
+      }
+      tracing::info!("Archived the final result of proposal {}", proposal.id);
+      archived.push(proposal.id);
+    }
+    Ok(archived)
+  }
+
+  /// Ledger accounts that haven't cast a vote for the proposal, with at least
+  /// `min_stake` balance, sorted by descending balance and paginated.
+  pub async fn proposal_nonvoters(
+    &self,
+    id: usize,
+    min_stake: Option<Decimal>,
+    offset: usize,
+    limit: usize,
+  ) -> Result<GetProposalNonvotersResponse> {
+    let proposal = self.find_proposal(id)?;
+    self.check_network(&proposal)?;
+    let hash = self.proposal_ledger_hash(&proposal).await?.ok_or_else(|| anyhow!("Proposal {} has no ledger yet", id))?;
+
+    let transactions = self.proposal_transactions(&proposal).await?;
+    let chain_tip = self.archive_chain_tip().await?;
+    let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect::<Vec<Vote>>())
+      .process_keywords(&proposal.vote_keywords(), chain_tip, self.confirmation_depth);
+
+    let ledger = Ledger::fetch_for_proposal(self, &hash, &proposal).await?;
+    let mut nonvoters: Vec<Nonvoter> = ledger
+      .0
+      .iter()
+      .filter(|account| !votes.0.contains_key(account.pk.as_str()))
+      .map(|account| Nonvoter { account: account.pk.clone(), stake: account.balance.parse().unwrap_or(Decimal::ZERO) })
+      .filter(|nonvoter| min_stake.map_or(true, |min_stake| nonvoter.stake >= min_stake))
+      .collect();
+    nonvoters.sort_by(|a, b| b.stake.cmp(&a.stake).then_with(|| a.account.cmp(&b.account)));
+
+    let total = nonvoters.len();
+    let accounts = nonvoters.into_iter().skip(offset).take(limit).collect();
+    Ok(GetProposalNonvotersResponse { proposal_id: id, total, offset, limit, accounts })
+  }
+
+  /// Cumulative votes and stake for proposal `id` per `bucket`, weighted like
+  /// its tally. Before its ledger is known, votes are counted without stake.
+  pub async fn proposal_timeseries(
+    &self,
+    id: usize,
+    bucket: TimeseriesBucket,
+  ) -> Result<GetProposalTimeseriesResponse> {
+    let proposal = self.find_proposal(id)?;
+    self.check_network(&proposal)?;
+
+    let transactions = self.proposal_transactions(&proposal).await?;
+
+    let chain_tip = self.archive_chain_tip().await?;
+
+    let keywords = proposal.vote_keywords();
+    let vote_epochs: HashMap<String, i64> =
+      transactions.iter().map(|transaction| (transaction.hash.clone(), transaction.epoch())).collect();
+    let votes: Vec<Vote> = transactions.into_iter().map(std::convert::Into::into).collect();
+    let votes = match self.proposal_ledger_hash(&proposal).await? {
+      Some(hash) => {
+        let ledger = Ledger::fetch_for_proposal(self, &hash, &proposal).await?;
+        let epoch_ledgers = self.epoch_ledgers(&proposal, false, &mut Vec::new()).await?;
+        let ledger_for = |vote: &Vote| match vote_epochs.get(&vote.hash).and_then(|epoch| epoch_ledgers.get(epoch)) {
+          Some((_, epoch_ledger)) => epoch_ledger.as_ref(),
+          None => ledger.as_ref(),
+        };
+        let min_voting_stake = proposal.min_voting_stake.or(self.min_voting_stake);
+        Wrapper(votes)
+          .into_weighted_above(&proposal, ledger_for, chain_tip, self.confirmation_depth, min_voting_stake)
+          .0
+      }
+      None => {
+        let votes = Wrapper(votes).process_keywords(&keywords, chain_tip, self.confirmation_depth).0;
+        Wrapper(votes.into_values().map(|vote| vote.to_weighted(Decimal::ZERO)).collect())
+      }
+    };
+
+    let points = votes.cumulative_timeseries(bucket, &keywords);
+    Ok(GetProposalTimeseriesResponse { proposal_id: id, bucket, points })
+  }
+
+  /// Each account's latest vote for proposal `id` on the canonical chain,
+  /// unweighted, ordered by block height. Needs no ledger.
+  pub async fn raw_votes(&self, id: usize) -> Result<Vec<RawVote>> {
+    let proposal = self.find_proposal(id)?;
+    let transactions = self.proposal_transactions(&proposal).await?;
+    let chain_tip = self.archive_chain_tip().await?;
+
+    let keywords = proposal.vote_keywords();
+    let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect::<Vec<Vote>>())
+      .process_keywords(&keywords, chain_tip, self.confirmation_depth)
+      .0;
+    let mut raw_votes: Vec<RawVote> = votes
+      .into_values()
+      .filter(|vote| vote.status == BlockStatus::Canonical)
+      .filter_map(|vote| {
+        Some(RawVote {
+          choice: keywords.choice(&vote.memo)?,
+          account: vote.account,
+          block_height: vote.height,
+          tx_hash: vote.hash,
+          decoded_memo: vote.memo,
+        })
+      })
+      .collect();
+    raw_votes.sort_by(|a, b| a.block_height.cmp(&b.block_height).then_with(|| a.tx_hash.cmp(&b.tx_hash)));
+    Ok(raw_votes)
   }
 
   pub async fn run_ranked_vote(
@@ -222,11 +736,11 @@ impl Ocv {
     end_time: i64,
     _ledger_hash: Option<String>,
   ) -> Result<GetMinaRankedVoteResponse> {
-    let transactions = self.archive.fetch_transactions(start_time, end_time)?;
-    let chain_tip = self.archive.fetch_chain_tip()?;
+    let transactions = self.archive_transactions(start_time, end_time).await?;
+    let chain_tip = self.archive_chain_tip().await?;
 
     let votes = Wrapper(transactions.into_iter().map(std::convert::Into::into).collect())
-      .process_ranked_vote(round_id, chain_tip)
+      .process_ranked_vote(round_id, chain_tip, self.confirmation_depth)
       .to_vec()
       .0;
     tracing::info!("votes {}", votes.len());
@@ -261,15 +775,589 @@ impl Ocv {
     })
   }
 
+  /// Re-fetches and validates the proposals manifest, then atomically swaps
+  /// it in. Returns the number of proposals now loaded, which is unchanged
+  /// when the manifest wasn't modified.
+  pub async fn reload_proposals(&self) -> Result<usize> {
+    let Some(proposals) = self.proposals_loader.load_if_changed().await? else {
+      let count = self.proposals.load().len();
+      tracing::info!("Proposals manifest unchanged, keeping {} proposals", count);
+      return Ok(count);
+    };
+    let count = proposals.len();
+    self.proposals.store(Arc::new(proposals));
+    self.caches.tallies.invalidate_all();
+    tracing::info!("Reloaded {} proposals", count);
+    Ok(count)
+  }
+
+  /// Cross-references the on-disk ledger cache with the bucket listing, for
+  /// ledgers that are cached or referenced by a proposal.
+  pub async fn ledger_cache_status(&self) -> Result<LedgerCacheStatusResponse> {
+    let cached = cached_ledgers(&self.ledger_storage_path)?;
+    let prefix = render_ledger_key_prefix(&self.ledger_key_prefix, self.network, None);
+    let objects = self.storage_provider.list_objects(&self.bucket_name, prefix.as_deref()).await?;
+    let expected: BTreeSet<String> =
+      self.proposals.load().iter().filter_map(|proposal| proposal.ledger_hash.clone()).collect();
+    let hashes: BTreeSet<String> = expected.iter().cloned().chain(cached.iter().map(|l| l.hash.clone())).collect();
+    let can_head = self.storage_provider.capabilities().can_head;
+
+    let mut ledgers = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+      let cached_ledger = cached.iter().find(|ledger| ledger.hash == hash);
+      let object_key = objects.iter().find(|key| key.contains(&hash)).cloned();
+      let etag_match = match (cached_ledger.and_then(|ledger| ledger.etag.as_ref()), &object_key) {
+        (Some(cached_etag), Some(key)) if can_head => {
+          match self.storage_provider.head_object(&self.bucket_name, key).await {
+            Ok(metadata) => metadata.etag.map(|etag| etag == *cached_etag),
+            Err(err) => {
+              tracing::warn!("Could not fetch etag of ledger object {}: {}", key, err);
+              None
+            }
+          }
+        }
+        _ => None,
+      };
+      ledgers.push(LedgerCacheEntryStatus {
+        expected: expected.contains(&hash),
+        cached: cached_ledger.is_some(),
+        size_bytes: cached_ledger.map(|ledger| ledger.size_bytes),
+        object_key,
+        etag_match,
+        hash,
+      });
+    }
+
+    Ok(LedgerCacheStatusResponse {
+      ledger_storage_path: self.ledger_storage_path.display().to_string(),
+      bucket_name: self.bucket_name.clone(),
+      ledgers,
+    })
+  }
+
+  /// Removes cached ledgers from disk and from memory; see
+  /// [`clear_ledger_cache`].
+  pub async fn clear_ledger_cache(&self, hash: Option<&str>, dry_run: bool) -> Result<LedgerCacheClearResponse> {
+    let response = clear_ledger_cache(&self.ledger_storage_path, hash, dry_run)?;
+    if !dry_run {
+      match hash {
+        Some(hash) => self.caches.ledger.invalidate(hash).await,
+        None => self.caches.ledger.invalidate_all(),
+      }
+      self.caches.tallies.invalidate_all();
+    }
+    tracing::info!(
+      "Cleared {} ledger cache files ({} bytes, dry run: {})",
+      response.count,
+      response.bytes_freed,
+      dry_run
+    );
+    Ok(response)
+  }
+
+  /// Removes the least recently used cached ledgers beyond `max_bytes` or
+  /// older than `max_age`; see [`prune_ledger_cache`]. The ledgers of open
+  /// proposals are kept: the one each names or the archive records for it,
+  /// and those of the later epochs its window runs into. Nothing is pruned if
+  /// one of them can't be looked up.
+  pub async fn prune_ledger_cache(
+    &self,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+  ) -> Result<LedgerCachePruneResponse> {
+    let mut keep = HashSet::new();
+    for proposal in self.open_proposals(self.now())? {
+      keep.extend(self.proposal_ledger_hash(&proposal).await?);
+      keep.extend(proposal.epoch_ledgers.into_iter().map(|epoch_ledger| epoch_ledger.ledger_hash));
+    }
+    let response = prune_ledger_cache(&self.ledger_storage_path, max_bytes, max_age, SystemTime::now(), &keep)?;
+    if !response.pruned.is_empty() {
+      tracing::info!(
+        "Pruned {} cached ledgers {:?}, reclaiming {} bytes; {} bytes remain",
+        response.pruned.len(),
+        response.pruned,
+        response.bytes_freed,
+        response.bytes_remaining
+      );
+    }
+    Ok(response)
+  }
+
+  /// A copy serving one request, whose storage downloads are abandoned once
+  /// `deadline` expires.
+  pub fn with_deadline(&self, deadline: RequestDeadline) -> Ocv {
+    Ocv { request_deadline: deadline, ..self.clone() }
+  }
+
+  /// Drops the cached tally of proposal `id`, so the next result request
+  /// recomputes it. Archived results of closed proposals are kept.
+  pub async fn invalidate_tally(&self, id: usize) -> Result<InvalidateTallyResponse> {
+    self.find_proposal(id)?;
+    let was_cached = self.caches.tallies.contains_key(&id);
+    self.caches.tallies.invalidate(&id).await;
+    tracing::info!("Invalidated the cached tally of proposal {} (cached: {})", id, was_cached);
+    Ok(InvalidateTallyResponse { proposal_id: id, was_cached })
+  }
+
+  /// Loaded proposals in `phase` at `now`. The current slot is only looked up
+  /// when a proposal has a slot-bounded window.
+  pub fn proposals_in_phase(&self, phase: ProposalPhase, now: DateTime<Utc>) -> Result<Vec<Proposal>> {
+    let proposals = self.proposals.load();
+    let current_slot = match proposals.iter().any(Proposal::has_slot_window) {
+      true => self.archive.current_epoch_and_slot()?.1 as i64,
+      false => 0,
+    };
+    let now_millis = now.timestamp_millis();
+    Ok(proposals.iter().filter(|proposal| proposal.phase(now_millis, current_slot) == phase).cloned().collect())
+  }
+
+  pub fn open_proposals(&self, now: DateTime<Utc>) -> Result<Vec<Proposal>> {
+    self.proposals_in_phase(ProposalPhase::Open, now)
+  }
+
+  pub fn upcoming_proposals(&self, now: DateTime<Utc>) -> Result<Vec<Proposal>> {
+    self.proposals_in_phase(ProposalPhase::Upcoming, now)
+  }
+
+  pub fn closed_proposals(&self, now: DateTime<Utc>) -> Result<Vec<Proposal>> {
+    self.proposals_in_phase(ProposalPhase::Closed, now)
+  }
+
+  /// Loaded proposals matching the listing filters; see
+  /// [`Proposal::matches_filters`].
+  pub fn proposals_filtered(&self, category: Option<&str>, tags: &[String]) -> Vec<Proposal> {
+    self.proposals.load().iter().filter(|proposal| proposal.matches_filters(category, tags)).cloned().collect()
+  }
+
+  /// Lists the bucket, then downloads and parses the staking ledger with the
+  /// highest epoch, to surface bucket, permission or format problems early.
+  pub async fn startup_selftest(&self) -> Result<LedgerObjectKey> {
+    let prefix = render_ledger_key_prefix(&self.ledger_key_prefix, self.network, None);
+    let objects = self
+      .storage_provider
+      .list_objects(&self.bucket_name, prefix.as_deref())
+      .await
+      .with_context(|| format!("could not list bucket '{}'", self.bucket_name))?;
+    let newest = objects
+      .iter()
+      .filter_map(|key| LedgerObjectKey::parse(key))
+      .filter(|key| key.kind == LedgerKind::Staking)
+      .max_by_key(|key| key.epoch)
+      .ok_or_else(|| anyhow!("no staking ledger found among {} objects in '{}'", objects.len(), self.bucket_name))?;
+
+    let ledger = Ledger::fetch(self, &newest.hash, Some(newest.epoch))
+      .await
+      .with_context(|| format!("could not load staking ledger {} for epoch {}", newest.hash, newest.epoch))?;
+    tracing::info!(
+      "Startup self-test passed: staking ledger {} for epoch {} has {} accounts",
+      newest.hash,
+      newest.epoch,
+      ledger.0.len()
+    );
+    Ok(newest)
+  }
+
+  /// Checks whether `memo` would count as a vote on proposal `id`.
+  pub fn check_memo(&self, id: usize, memo: &str) -> Result<MemoCheck> {
+    let proposal = self.find_proposal(id)?;
+    Ok(MemoCheck::new(memo, &proposal.vote_keywords()))
+  }
+
+  async fn archive_chain_tip(&self) -> Result<i64> {
+    self.archive_query("fetch_chain_tip", |archive| archive.fetch_chain_tip()).await
+  }
+
+  async fn archive_latest_slot(&self) -> Result<i64> {
+    self.archive_query("fetch_latest_slot", |archive| archive.fetch_latest_slot()).await
+  }
+
+  /// Ledgers of the later epochs `proposal`'s window runs into, by epoch.
+  /// Votes cast in one of them are weighted by its ledger.
+  /// Unless `strict`, an epoch ledger that isn't available yet is left out,
+  /// so the proposal's ledger weights that epoch's votes, and a warning is
+  /// added to `warnings`.
+  async fn epoch_ledgers(
+    &self,
+    proposal: &Proposal,
+    strict: bool,
+    warnings: &mut Vec<TallyWarning>,
+  ) -> Result<BTreeMap<i64, (String, Arc<Ledger>)>> {
+    let mut epoch_ledgers = BTreeMap::new();
+    for EpochLedger { epoch, ledger_hash } in &proposal.epoch_ledgers {
+      let kind = proposal.ledger_selection.ledger_kind();
+      let fetched = Ledger::fetch_for_network(self, ledger_hash, proposal.network, Some(*epoch), kind).await;
+      let epoch_ledger = match fetched {
+        Ok(epoch_ledger) => epoch_ledger,
+        Err(err) if !strict && matches!(err.downcast_ref(), Some(TallyError::LedgerUnavailable { .. })) => {
+          warnings.push(TallyWarning {
+            code: TallyWarningCode::MissingLedger,
+            message: format!("{err}; the proposal's ledger weighted the votes of epoch {epoch} instead"),
+            count: None,
+          });
+          continue;
+        }
+        Err(err) => return Err(err),
+      };
+      epoch_ledgers.insert(*epoch, (ledger_hash.clone(), epoch_ledger));
+    }
+    Ok(epoch_ledgers)
+  }
+
+  /// Per-account detail of every vote counted for proposal `id`, in block
+  /// order, for exports. Unlike `proposal_result`, nothing is truncated;
+  /// pending votes are included with their status. Votes below the effective
+  /// `min_voting_stake` aren't counted, so they're left out as well.
+  pub async fn vote_details(&self, id: usize) -> Result<Vec<VoteDetail>> {
+    let proposal = self.find_proposal(id)?;
+    self.check_network(&proposal)?;
+    let hash = self.proposal_ledger_hash(&proposal).await?.ok_or_else(|| anyhow!("Proposal {} has no ledger yet", id))?;
+    let transactions = self.proposal_transactions(&proposal).await?;
+    let chain_tip = self.archive_chain_tip().await?;
+    let ledger = Ledger::fetch_for_proposal(self, &hash, &proposal).await?;
+    let epoch_ledgers = self.epoch_ledgers(&proposal, true, &mut Vec::new()).await?;
+    let vote_epochs: HashMap<String, i64> =
+      transactions.iter().map(|transaction| (transaction.hash.clone(), transaction.epoch())).collect();
+
+    let keywords = proposal.vote_keywords();
+    let raw_votes: Vec<Vote> = transactions.into_iter().map(std::convert::Into::into).collect();
+    let ledger_for = |vote: &Vote| match vote_epochs.get(&vote.hash).and_then(|epoch| epoch_ledgers.get(epoch)) {
+      Some((_, epoch_ledger)) => epoch_ledger.as_ref(),
+      None => ledger.as_ref(),
+    };
+    let min_voting_stake = proposal.min_voting_stake.or(self.min_voting_stake);
+    let (votes, _) =
+      Wrapper(raw_votes).into_staked_above(&proposal, ledger_for, chain_tip, self.confirmation_depth, min_voting_stake);
+    let ledger_balances = ledger.balances();
+    let epoch_balances: HashMap<i64, HashMap<&str, Decimal>> =
+      epoch_ledgers.iter().map(|(epoch, (_, epoch_ledger))| (*epoch, epoch_ledger.balances())).collect();
+    let mut details: Vec<VoteDetail> = votes
+      .into_iter()
+      .filter_map(|(vote, total)| {
+        let choice = keywords.choice(&vote.memo)?;
+        let balances = match vote_epochs.get(&vote.hash).and_then(|epoch| epoch_balances.get(epoch)) {
+          Some(balances) => balances,
+          None => &ledger_balances,
+        };
+        let account = vote.account.as_str();
+        // V1 doesn't count the balance of an account delegating elsewhere.
+        let stake = balances.get(account).copied().unwrap_or(Decimal::ZERO).min(total);
+        Some(VoteDetail {
+          account: account.to_string(),
+          choice,
+          stake,
+          delegated_stake: total - stake,
+          weight: proposal.weighting.apply(total),
+          block_height: vote.height,
+          status: vote.status,
+        })
+      })
+      .collect();
+    details.sort_by(|a, b| a.block_height.cmp(&b.block_height).then_with(|| a.account.cmp(&b.account)));
+    Ok(details)
+  }
+
+  /// Fails with `TallyError::NetworkMismatch` unless `proposal`'s votes can
+  /// be read from this server's archive. Proposals of the network set with
+  /// `proposals_network` are let through for testing, with a warning in
+  /// their result; others, as served with `include_all_networks`, can't be
+  /// tallied here.
+  fn check_network(&self, proposal: &Proposal) -> Result<()> {
+    if proposal.network == self.network || proposal.network == self.proposals_loader.network {
+      return Ok(());
+    }
+    Err(
+      TallyError::NetworkMismatch {
+        id: proposal.id,
+        proposal_network: proposal.network.to_string(),
+        archive_network: self.network.to_string(),
+      }
+      .into(),
+    )
+  }
+
+  /// The hash of `proposal`'s ledger: the one it names, else the one the
+  /// archive records for its epoch. `None` before that epoch has begun.
+  async fn proposal_ledger_hash(&self, proposal: &Proposal) -> Result<Option<String>> {
+    match proposal.ledger_hash.clone() {
+      Some(hash) => Ok(Some(hash)),
+      None => self.archive_ledger_hash(proposal).await,
+    }
+  }
+
+  /// The ledger hash the archive records for a proposal that doesn't name
+  /// one, or `None` before its epoch has begun. Lookup failures are
+  /// `TallyError`s, so they aren't mistaken for a ledger that isn't known
+  /// yet.
+  async fn archive_ledger_hash(&self, proposal: &Proposal) -> Result<Option<String>> {
+    let Ok(epoch) = u32::try_from(proposal.epoch) else {
+      return Ok(None);
+    };
+    let (current_epoch, _) = self
+      .archive_query("current_epoch_and_slot", |archive| archive.current_epoch_and_slot())
+      .await
+      .map_err(|err| archive_unavailable("current_epoch_and_slot", err))?;
+    if epoch > current_epoch {
+      return Ok(None);
+    }
+    let hash = match proposal.ledger_selection.ledger_kind() {
+      LedgerKind::Staking => {
+        self.archive_query("staking_ledger_hash", move |archive| archive.staking_ledger_hash(epoch)).await
+      }
+      LedgerKind::NextStaking => {
+        self.archive_query("next_staking_ledger_hash", move |archive| archive.next_staking_ledger_hash(epoch)).await
+      }
+    };
+    let hash = hash.map_err(|err| archive_unavailable("ledger_hash", err))?;
+    Ok(Some(hash))
+  }
+
+  async fn archive_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>> {
+    self.archive_query("fetch_transactions", move |archive| archive.fetch_transactions(start_time, end_time)).await
+  }
+
+  /// Transactions of `proposal`'s voting window, narrowed to the window its
+  /// on-chain markers open and close.
+  async fn proposal_transactions(&self, proposal: &Proposal) -> Result<Vec<FetchTransactionResult>> {
+    let transactions = self.archive_transactions_in_window(proposal.window_start(), proposal.window_end()).await?;
+    if proposal.open_marker.is_none() && proposal.close_marker.is_none() {
+      return Ok(transactions);
+    }
+    let chain_tip = self.archive_chain_tip().await?;
+    Ok(apply_window_markers(proposal, transactions, chain_tip, self.confirmation_depth))
+  }
+
+  /// Fetches the transactions of a vote window, widening timestamp bounds by
+  /// `window_skew`. Slot bounds are exact and kept as they are.
+  async fn archive_transactions_in_window(
+    &self,
+    start: WindowBound,
+    end: WindowBound,
+  ) -> Result<Vec<FetchTransactionResult>> {
+    let skew = self.window_skew.as_millis() as i64;
+    let widen = |bound: WindowBound, by: i64| match bound {
+      WindowBound::Timestamp(millis) => WindowBound::Timestamp(millis.saturating_add(by)),
+      WindowBound::Slot(_) => bound,
+    };
+    let (query_start, query_end) = (widen(start, -skew), widen(end, skew));
+    let transactions = self
+      .archive_query("fetch_transactions_in_window", move |archive| {
+        archive.fetch_transactions_in_window(query_start, query_end)
+      })
+      .await?;
+    let outside = |tx: &&FetchTransactionResult| {
+      matches!(start, WindowBound::Timestamp(start) if tx.timestamp < start)
+        || matches!(end, WindowBound::Timestamp(end) if tx.timestamp > end)
+    };
+    let skewed = transactions.iter().filter(outside).count();
+    if skewed > 0 {
+      tracing::info!(
+        "Considering {} transactions outside the window {:?} to {:?} within the {:?} clock skew tolerance",
+        skewed,
+        start,
+        end,
+        self.window_skew
+      );
+    }
+    Ok(transactions)
+  }
+
+  /// Runs a blocking archive query off the async runtime, giving up with
+  /// `TallyError::ArchiveTimeout` after `db_query_timeout`. Waits for an
+  /// `archive_queries` permit first, without a timeout. A timed out query
+  /// keeps its connection and permit until the database returns.
+  async fn archive_query<T, F>(&self, query: &'static str, run: F) -> Result<T>
+  where
+    T: Send + 'static,
+    F: FnOnce(&(dyn ArchiveInterface + Send + Sync)) -> Result<T> + Send + 'static,
+  {
+    let archive = self.archive.clone();
+    let permit = self.archive_queries.acquire().await;
+    let started = Instant::now();
+    let query_task = tokio::task::spawn_blocking(move || {
+      let _permit = permit;
+      run(archive.as_ref())
+    });
+    match tokio::time::timeout(self.db_query_timeout, query_task).await {
+      Ok(result) => result?,
+      Err(_) => {
+        let elapsed = started.elapsed();
+        tracing::error!("Archive query {} timed out after {:?}", query, elapsed);
+        Err(TallyError::ArchiveTimeout { query, elapsed_secs: elapsed.as_secs_f64() }.into())
+      }
+    }
+  }
+
   fn find_proposal(&self, id: usize) -> Result<Proposal> {
-    Ok(self.proposals.iter().find(|proposal| proposal.id == id).ok_or(anyhow!("Proposal {id} dne."))?.to_owned())
+    let proposals = self.proposals.load();
+    Ok(proposals.iter().find(|proposal| proposal.id == id).ok_or(TallyError::ProposalNotFound(id))?.to_owned())
+  }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Ocv {
+  /// Assembles an `Ocv` around the given archive and storage provider, with
+  /// ledgers cached in a fresh temporary directory and proposals taken as is.
+  pub fn new_for_test(
+    archive: impl ArchiveInterface + Send + Sync + 'static,
+    storage_provider: impl StorageProvider + Send + Sync + 'static,
+    proposals: Vec<Proposal>,
+  ) -> TestOcv {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let ledger_storage_path = std::env::temp_dir()
+      .join(format!("ocv-test-{}-{}", std::process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&ledger_storage_path).expect("failed to create test ledger directory");
+
+    TestOcv(Ocv {
+      archive: Arc::new(archive),
+      network: Network::Mainnet,
+      release_stage: ReleaseStage::Development,
+      ledger_storage_path,
+      bucket_name: "test-ledgers".to_string(),
+      ledger_key_prefix: String::new(),
+      storage_provider: Arc::new(storage_provider),
+      proposals: Arc::new(ArcSwap::from_pointee(proposals)),
+      proposals_loader: ProposalsLoader {
+        network: Network::Mainnet,
+        include_all_networks: false,
+        release_stage: ReleaseStage::Development,
+        maybe_proposals_url: None,
+        max_proposal_window_days: 90,
+        conditional_fetch: false,
+        fallback_path: None,
+        user_agent: crate::DEFAULT_USER_AGENT.to_string(),
+        signature_key: None,
+        retry_policy: crate::RetryPolicy::default(),
+        validators: Arc::default(),
+      },
+      confirmation_depth: 15,
+      max_votes_in_response: 10_000,
+      cache_validate_on_read: false,
+      serve_archived_results: false,
+      archived_results_key_prefix: None,
+      db_query_timeout: Duration::from_secs(30),
+      archive_queries: QueryLimiter::new(8),
+      request_timeout: None,
+      request_deadline: RequestDeadline::default(),
+      max_ledger_bytes: 1 << 30,
+      live_tally_ttl: Duration::from_secs(15),
+      final_results_max_age: Duration::from_secs(300),
+      window_skew: Duration::ZERO,
+      min_ledger_total_stake: None,
+      max_ledger_total_stake: None,
+      min_voting_stake: None,
+      clock: Arc::new(crate::SystemClock),
+      caches: Caches::build(4),
+      metrics: ProposalMetrics::default(),
+      audit_log: Arc::default(),
+    })
+  }
+}
+
+/// An `Ocv` built by [`Ocv::new_for_test`], removing its ledger directory
+/// when dropped. Clones of the `Ocv` share the directory, so keep this alive
+/// while they're in use.
+#[cfg(any(test, feature = "test-util"))]
+pub struct TestOcv(Ocv);
+
+#[cfg(any(test, feature = "test-util"))]
+impl Deref for TestOcv {
+  type Target = Ocv;
+
+  fn deref(&self) -> &Ocv {
+    &self.0
   }
 }
 
+#[cfg(any(test, feature = "test-util"))]
+impl DerefMut for TestOcv {
+  fn deref_mut(&mut self) -> &mut Ocv {
+    &mut self.0
+  }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Drop for TestOcv {
+  fn drop(&mut self) {
+    // Best effort: a test may have removed the directory itself.
+    let _ = std::fs::remove_dir_all(&self.0.ledger_storage_path);
+  }
+}
+
+/// Narrows `transactions` to the window `proposal`'s markers mark out: from
+/// the first open marker to the first close marker after it, both inclusive
+/// by block timestamp. Only markers sent by the proposal's `marker_account`
+/// in canonical blocks at least `confirmation_depth` below `chain_tip` count.
+/// A side whose marker is unset or not on chain keeps the configured bound.
+/// Marker transactions themselves, from any sender, aren't votes.
+fn apply_window_markers(
+  proposal: &Proposal,
+  mut transactions: Vec<FetchTransactionResult>,
+  chain_tip: i64,
+  confirmation_depth: i64,
+) -> Vec<FetchTransactionResult> {
+  if proposal.open_marker.is_none() && proposal.close_marker.is_none() {
+    return transactions;
+  }
+  let marker =
+    |tx: &FetchTransactionResult| decode_memo_text(&tx.memo).ok().and_then(|memo| proposal.window_marker(&memo));
+  let trusted = |tx: &&FetchTransactionResult| {
+    proposal.marker_account.as_deref() == Some(tx.account.as_str())
+      && tx.status == BlockStatus::Canonical
+      && chain_tip - tx.height >= confirmation_depth
+  };
+  let trusted_marker = |tx: &&FetchTransactionResult, kind: WindowMarker| trusted(tx) && marker(tx) == Some(kind);
+  let opened_at = transactions.iter().filter(|tx| trusted_marker(tx, WindowMarker::Open)).map(|tx| tx.timestamp).min();
+  let closed_at = transactions
+    .iter()
+    .filter(|tx| trusted_marker(tx, WindowMarker::Close))
+    .map(|tx| tx.timestamp)
+    .filter(|timestamp| opened_at.map_or(true, |opened_at| *timestamp >= opened_at))
+    .min();
+  tracing::info!(
+    "Proposal {} window markers: opened at {:?}, closed at {:?}; missing markers fall back to the configured window",
+    proposal.id,
+    opened_at,
+    closed_at
+  );
+  transactions.retain(|tx| {
+    marker(tx).is_none()
+      && opened_at.map_or(true, |opened_at| tx.timestamp >= opened_at)
+      && closed_at.map_or(true, |closed_at| tx.timestamp <= closed_at)
+  });
+  transactions
+}
+
+/// `err` from archive query `query` as a `TallyError`, keeping one it
+/// already is, such as a timeout.
+fn archive_unavailable(query: &'static str, err: anyhow::Error) -> anyhow::Error {
+  if err.is::<TallyError>() {
+    return err;
+  }
+  TallyError::ArchiveUnavailable { query, message: format!("{err:#}") }.into()
+}
+
+/// Number of votes matching `keywords` that a later vote from the same account
+/// replaced.
+fn superseded_votes(votes: &[Vote], keywords: &VoteKeywords) -> usize {
+  let matching: Vec<&Vote> =
+    votes.iter().filter(|vote| vote.decode_memo().is_ok_and(|memo| keywords.choice(&memo).is_some())).collect();
+  let accounts: HashSet<&PublicKey> = matching.iter().map(|vote| &vote.account).collect();
+  matching.len() - accounts.len()
+}
+
+/// Caps `votes` at `max` entries, returning the original number of votes and
+/// whether any were dropped.
+fn truncate_votes<T>(votes: &mut Vec<T>, max: usize) -> (usize, bool) {
+  let total = votes.len();
+  votes.truncate(max);
+  (total, total > max)
+}
+
 #[derive(Serialize)]
 pub struct GetCoreApiInfoResponse {
   chain_tip: i64,
   current_slot: i64,
+  ledger_cache: LedgerCacheStats,
 }
 
 #[derive(Serialize)]
@@ -277,9 +1365,27 @@ pub struct ProposalResponse {
   #[serde(flatten)]
   proposal: Proposal,
   votes: Vec<Vote>,
+  /// Number of votes before truncation.
+  total_votes: usize,
+  /// Whether `votes` was cut at `max_votes_in_response`.
+  truncated: bool,
 }
 
+/// Proposals tallied at once by a bulk request.
+pub const BULK_CONCURRENCY: usize = 4;
+
+/// Most proposal ids a bulk request may name.
+pub const MAX_BULK_IDS: usize = 50;
+
+/// Results of several proposals, by id.
 #[derive(Serialize)]
+pub struct BulkResultsResponse<T = TallyResult> {
+  pub results: BTreeMap<usize, T>,
+  /// Proposals whose tally failed, with the error.
+  pub errors: BTreeMap<usize, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct GetMinaProposalResultResponse {
   #[serde(flatten)]
   proposal: Proposal,
@@ -287,6 +1393,326 @@ pub struct GetMinaProposalResultResponse {
   positive_stake_weight: Decimal,
   negative_stake_weight: Decimal,
   votes: Vec<VoteWithWeight>,
+  /// Number of votes before truncation.
+  total_votes: usize,
+  /// Whether `votes` was cut at `max_votes_in_response`. The totals always
+  /// cover every vote.
+  truncated: bool,
+  /// Votes left out of `votes` and every total for coming from accounts with
+  /// less than the minimum voting stake.
+  #[serde(default)]
+  below_threshold: BelowThresholdVotes,
+  metadata: TallyMetadata,
+  /// Non-fatal data-quality issues found while tallying.
+  warnings: Vec<TallyWarning>,
+  /// When the result was archived as final; `None` for computed results.
+  #[serde(default)]
+  finalized_at: Option<DateTime<Utc>>,
+}
+
+/// Result of tallying a proposal.
+pub type TallyResult = GetMinaProposalResultResponse;
+
+impl GetMinaProposalResultResponse {
+  pub fn inputs(&self) -> TallyInputs {
+    let metadata = &self.metadata;
+    TallyInputs {
+      proposal_id: self.proposal.id,
+      mode: metadata.mode,
+      ledger_hashes: metadata.ledger_hashes.clone(),
+      staking_epoch: metadata.staking_epoch,
+      window_start: metadata.window_start,
+      window_end: metadata.window_end,
+      window_skew_ms: metadata.window_skew_ms,
+      chain_tip: metadata.chain_tip,
+      confirmation_depth: metadata.confirmation_depth,
+      min_block_height: metadata.min_block_height,
+      max_block_height: metadata.max_block_height,
+      weighting: metadata.weighting,
+      min_voting_stake: self.below_threshold.min_voting_stake,
+    }
+  }
+
+  /// Whether the result can no longer change: the window has closed, every
+  /// vote is confirmed, and the ledger was known.
+  pub fn is_finalized(&self) -> bool {
+    self.metadata.mode == TallyMode::Final
+      && self.metadata.pending_votes == 0
+      && !self.warnings.iter().any(|warning| warning.code == TallyWarningCode::MissingLedger)
+  }
+
+  /// `Insufficient voters` when the vote threshold wasn't met, otherwise
+  /// `Approved` or `Rejected` by whether the yes share of stake exceeds the
+  /// proposal's approval threshold.
+  pub fn vote_status(&self) -> &'static str {
+    let threshold = self.proposal.approval_threshold.unwrap_or(Decimal::new(5, 1));
+    let counted = self.positive_stake_weight + self.negative_stake_weight;
+    if !self.metadata.vote_threshold_met {
+      "Insufficient voters"
+    } else if self.positive_stake_weight > counted * threshold {
+      "Approved"
+    } else {
+      "Rejected"
+    }
+  }
+
+  /// Renders the result in the JSON shape used by the MEF governance tools,
+  /// matching `/api/mef_proposal_consideration`:
+  ///
+  /// - `proposal_id`, `epoch`: from the proposal
+  /// - `ledger_hash`: the staking ledger votes were weighted by, as configured
+  ///   or resolved from the archive
+  /// - `proposal_key`: the keyword voters put in their memo
+  /// - `total_community_votes`, `total_positive_community_votes`,
+  ///   `total_negative_community_votes`: counted votes (pending votes are left
+  ///   out of final results)
+  /// - `total_stake_weight`, `positive_stake_weight`, `negative_stake_weight`:
+  ///   stake totals, as are `stake_by_choice.yes` and `stake_by_choice.no`
+  /// - `elegible`: whether the vote threshold was met (spelled as in MEF)
+  /// - `vote_status`: `Insufficient voters`, `Approved` or `Rejected`
+  /// - `final`: whether the voting window has closed
+  pub fn to_mef(&self) -> serde_json::Value {
+    let vote_status = self.vote_status();
+    serde_json::json!({
+      "proposal_id": self.proposal.id,
+      "proposal_key": self.proposal.vote_keyword(),
+      "epoch": self.proposal.epoch,
+      "ledger_hash": self.metadata.ledger_hashes.first(),
+      "total_community_votes": self.metadata.positive_votes + self.metadata.negative_votes,
+      "total_positive_community_votes": self.metadata.positive_votes,
+      "total_negative_community_votes": self.metadata.negative_votes,
+      "total_stake_weight": self.total_stake_weight,
+      "positive_stake_weight": self.positive_stake_weight,
+      "negative_stake_weight": self.negative_stake_weight,
+      "stake_by_choice": { "yes": self.positive_stake_weight, "no": self.negative_stake_weight },
+      "elegible": self.metadata.vote_threshold_met,
+      "vote_status": vote_status,
+      "final": self.metadata.mode == TallyMode::Final,
+    })
+  }
+
+  /// Converts the result to its Protocol Buffers message.
+  pub fn to_proto(&self) -> proto::TallyResult {
+    proto::TallyResult {
+      proposal_id: self.proposal.id as u64,
+      proposal_key: self.proposal.vote_keyword(),
+      network: self.proposal.network.to_string(),
+      epoch: self.proposal.epoch,
+      ledger_hash: self.metadata.ledger_hashes.first().cloned(),
+      total_stake_weight: self.total_stake_weight.to_string(),
+      positive_stake_weight: self.positive_stake_weight.to_string(),
+      negative_stake_weight: self.negative_stake_weight.to_string(),
+      mode: match self.metadata.mode {
+        TallyMode::Live => proto::TallyMode::Live,
+        TallyMode::Final => proto::TallyMode::Final,
+      }
+      .into(),
+      positive_votes: self.metadata.positive_votes as u64,
+      negative_votes: self.metadata.negative_votes as u64,
+      pending_votes: self.metadata.pending_votes as u64,
+      vote_threshold_met: self.metadata.vote_threshold_met,
+      votes: self
+        .votes
+        .iter()
+        .map(|vote| proto::Vote {
+          account: vote.account.to_string(),
+          hash: vote.hash.clone(),
+          memo: vote.memo.clone(),
+          height: vote.height,
+          status: serde_name(vote.status),
+          timestamp: vote.timestamp,
+          nonce: vote.nonce,
+          weight: vote.weight.to_string(),
+        })
+        .collect(),
+      total_votes: self.total_votes as u64,
+      truncated: self.truncated,
+      warnings: self
+        .warnings
+        .iter()
+        .map(|warning| proto::TallyWarning {
+          code: serde_name(warning.code),
+          message: warning.message.clone(),
+          count: warning.count.map(|count| count as u64),
+        })
+        .collect(),
+      ledger_hashes: self.metadata.ledger_hashes.clone(),
+      finalized_at_millis: self.finalized_at.map(|finalized_at| finalized_at.timestamp_millis()),
+    }
+  }
+}
+
+/// Name of a unit enum variant as serialized to JSON.
+fn serde_name(value: impl Serialize) -> String {
+  match serde_json::to_value(value) {
+    Ok(serde_json::Value::String(name)) => name,
+    _ => String::new(),
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TallyMode {
+  /// The voting window is still open; pending votes are counted.
+  Live,
+  /// The voting window has closed; only confirmed votes are counted.
+  Final,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BelowThresholdVotes {
+  /// The minimum voting stake in force, in MINA, if any.
+  pub min_voting_stake: Option<Decimal>,
+  pub votes: usize,
+  /// Weight the votes would have carried.
+  pub stake_weight: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TallyMetadata {
+  mode: TallyMode,
+  /// How long a live tally is cached and served for. Final tallies are
+  /// cached until a new block arrives.
+  #[serde(default)]
+  cache_ttl_secs: Option<u64>,
+  confirmation_depth: i64,
+  pending_votes: usize,
+  weighting: WeightingScheme,
+  /// Counted yes votes.
+  positive_votes: usize,
+  /// Counted no votes.
+  negative_votes: usize,
+  vote_threshold_met: bool,
+  /// Ledgers the votes were weighted by: the proposal's, then those of the
+  /// later epochs its window runs into.
+  #[serde(default)]
+  ledger_hashes: Vec<String>,
+  /// Epoch whose staking or next staking ledger, per the proposal's
+  /// `ledger_selection`, weights the votes.
+  #[serde(default)]
+  staking_epoch: i64,
+  /// Voting window the votes were read from. Timestamp bounds were widened
+  /// by `window_skew_ms` on each side.
+  #[serde(default)]
+  window_start: Option<WindowBound>,
+  #[serde(default)]
+  window_end: Option<WindowBound>,
+  #[serde(default)]
+  window_skew_ms: i64,
+  /// Block height of the chain tip the votes' confirmations were judged by.
+  #[serde(default)]
+  chain_tip: Option<i64>,
+  /// Lowest and highest block height of a counted vote.
+  #[serde(default)]
+  min_block_height: Option<i64>,
+  #[serde(default)]
+  max_block_height: Option<i64>,
+  /// Digest of the ledger hashes and counted votes, as in audit records.
+  #[serde(default)]
+  input_digest: String,
+}
+
+/// What a tally was computed from: enough to recompute it independently.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TallyInputs {
+  pub proposal_id: usize,
+  pub mode: TallyMode,
+  pub ledger_hashes: Vec<String>,
+  pub staking_epoch: i64,
+  pub window_start: Option<WindowBound>,
+  pub window_end: Option<WindowBound>,
+  pub window_skew_ms: i64,
+  pub chain_tip: Option<i64>,
+  pub confirmation_depth: i64,
+  pub min_block_height: Option<i64>,
+  pub max_block_height: Option<i64>,
+  pub weighting: WeightingScheme,
+  /// The proposal's `min_voting_stake`, or else the server's.
+  pub min_voting_stake: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TallyWarning {
+  pub code: TallyWarningCode,
+  pub message: String,
+  /// Number of affected votes, where that applies.
+  pub count: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TallyWarningCode {
+  /// Voters without an account in the ledger; their stake counts as zero.
+  VotersMissingFromLedger,
+  /// Earlier votes replaced by a later vote from the same account.
+  SupersededVotes,
+  /// The ledger came from an object whose name contains the hash but isn't
+  /// named after exactly that hash.
+  InexactLedgerMatch,
+  /// The proposal's network differs from the server's.
+  NetworkMismatch,
+  /// A ledger the proposal needs isn't available, so votes were weighted by
+  /// another ledger or not at all. Strict tallies fail instead.
+  MissingLedger,
+}
+
+#[derive(Serialize)]
+pub struct GetProposalTimeseriesResponse {
+  proposal_id: usize,
+  bucket: TimeseriesBucket,
+  points: Vec<VoteTimeseriesPoint>,
+}
+
+#[derive(Serialize)]
+pub struct GetProposalNonvotersResponse {
+  proposal_id: usize,
+  /// Number of non-voters before pagination.
+  total: usize,
+  offset: usize,
+  limit: usize,
+  accounts: Vec<Nonvoter>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Nonvoter {
+  account: PublicKey,
+  stake: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InvalidateTallyResponse {
+  pub proposal_id: usize,
+  /// Whether a tally was cached.
+  pub was_cached: bool,
+}
+
+/// A counted vote without its stake.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RawVote {
+  pub account: PublicKey,
+  pub choice: VoteChoice,
+  pub block_height: i64,
+  pub tx_hash: String,
+  pub decoded_memo: String,
+}
+
+#[derive(Serialize)]
+pub struct GetProposalRawVotesResponse {
+  pub proposal_id: usize,
+  /// Number of votes before pagination.
+  pub total: usize,
+  pub offset: usize,
+  pub limit: usize,
+  pub votes: Vec<RawVote>,
+  /// Opaque cursor for the page after this one, if there is one.
+  pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct GetProposalsPageResponse {
+  pub proposals: Vec<Proposal>,
+  /// Opaque cursor for the page after this one, if there is one.
+  pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -312,3 +1738,796 @@ pub struct GetMinaRankedVoteResponse {
   stats: Vec<ElectionStats>,
   votes: Vec<RankedVote>,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{MemoryProvider, ProposalTime, SLOTS_PER_EPOCH, StakingOrNext, StubArchive};
+
+  const KEY_A: &str = "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM";
+  const KEY_B: &str = "B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy";
+  const KEY_C: &str = "B62qn9Yd4nBzkGVNegaAdAhELysTc5E25C2wBU3eK3ZcvEMrZ3v34ef";
+  const KEY_D: &str = "B62qkzyaUQ6EUuMPBwQVZkfaHTKvoSR9k5puPJtWqM7jhh4rfR9X8hd";
+
+  fn test_proposal_with_weighting(weighting: WeightingScheme) -> Proposal {
+    let mut proposal = test_proposal();
+    proposal.weighting = weighting;
+    proposal
+  }
+
+  fn test_proposal() -> Proposal {
+    serde_json::from_value(serde_json::json!({
+      "id": 1,
+      "key": "cftest-2",
+      "start_time": 1000,
+      "end_time": 9000,
+      "epoch": 1,
+      "ledger_hash": "jxTest",
+      "category": "Core",
+      "version": "V2",
+      "title": "",
+      "description": "",
+      "url": "",
+      "network": "mainnet",
+      "is_complete": false
+    }))
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_in_memory() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Pending, 2000)
+      .with_vote(KEY_B, "no cftest-2", 190, BlockStatus::Pending, 3000)
+      .with_vote(KEY_B, "cftest-2", 100, BlockStatus::Pending, 9500);
+    let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+
+    // The window has closed, so B's vote is still pending and left out, and
+    // B's vote after the window is ignored.
+    assert_eq!(result.metadata.mode, TallyMode::Final);
+    assert_eq!(result.metadata.pending_votes, 1);
+    assert_eq!(result.positive_stake_weight, Decimal::from(10));
+    assert_eq!(result.negative_stake_weight, Decimal::ZERO);
+    assert_eq!(result.votes.len(), 2);
+
+    let inputs = result.inputs();
+    assert_eq!(inputs.ledger_hashes, ["jxTest"]);
+    assert_eq!((inputs.staking_epoch, inputs.chain_tip, inputs.confirmation_depth), (1, Some(200), 15));
+    assert_eq!(inputs.window_start, Some(WindowBound::Timestamp(1000)));
+    assert_eq!(inputs.window_end, Some(WindowBound::Timestamp(9000)));
+    // Only A's vote is counted.
+    assert_eq!((inputs.min_block_height, inputs.max_block_height), (Some(100), Some(100)));
+    assert_eq!((inputs.weighting, inputs.min_voting_stake), (WeightingScheme::Linear, None));
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_window_markers() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+      { "pk": KEY_C, "balance": "7", "delegate": null },
+    ]);
+    let provider = || MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let votes = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 1500)
+      .with_vote(KEY_B, "cftest-2", 100, BlockStatus::Canonical, 3000)
+      .with_vote(KEY_C, "cftest-2", 100, BlockStatus::Canonical, 6000);
+    let marked = votes
+      .clone()
+      .with_vote(KEY_D, "Open  CFTEST-2", 100, BlockStatus::Canonical, 2000)
+      .with_vote(KEY_D, "close cftest-2", 100, BlockStatus::Canonical, 1000)
+      .with_vote(KEY_D, "close cftest-2", 100, BlockStatus::Canonical, 5000);
+    let mut proposal = test_proposal();
+    proposal.open_marker = Some("open cftest-2".to_string());
+    proposal.close_marker = Some("close cftest-2".to_string());
+    proposal.marker_account = Some(KEY_D.to_string());
+
+    // Only B voted between the opening and the first closing after it.
+    let ocv = Ocv::new_for_test(marked.clone(), provider(), vec![proposal.clone()]);
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(5));
+    assert_eq!(result.votes.len(), 1);
+
+    // Markers from other accounts, or not yet buried, are ignored.
+    let untrusted = votes
+      .clone()
+      .with_vote(KEY_B, "close cftest-2", 100, BlockStatus::Canonical, 2500)
+      .with_vote(KEY_D, "open cftest-2", 195, BlockStatus::Pending, 2000);
+    let ocv = Ocv::new_for_test(untrusted, provider(), vec![proposal.clone()]);
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(22));
+
+    // Without markers on chain, the configured window applies.
+    let ocv = Ocv::new_for_test(votes, provider(), vec![proposal]);
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(22));
+
+    // Proposals without markers ignore marker transactions.
+    let ocv = Ocv::new_for_test(marked, provider(), vec![test_proposal()]);
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(22));
+  }
+
+  #[tokio::test]
+  async fn test_raw_votes() {
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Pending, 2000)
+      .with_vote(KEY_A, "no cftest-2", 150, BlockStatus::Pending, 3000)
+      .with_vote(KEY_B, "CFTEST-2", 120, BlockStatus::Pending, 2500)
+      .with_vote(KEY_C, "cftest-2", 190, BlockStatus::Pending, 4000)
+      .with_vote(KEY_D, "unrelated", 100, BlockStatus::Canonical, 4000);
+    // No ledger is needed.
+    let ocv = Ocv::new_for_test(archive, MemoryProvider::new(), vec![test_proposal()]);
+
+    // A's latest vote replaces its first; C's is not yet buried.
+    let votes = ocv.raw_votes(1).await.unwrap();
+    let summary: Vec<(&str, VoteChoice, i64, &str)> = votes
+      .iter()
+      .map(|vote| (vote.account.as_str(), vote.choice, vote.block_height, vote.decoded_memo.as_str()))
+      .collect();
+    assert_eq!(summary, [(KEY_B, VoteChoice::Yes, 120, "CFTEST-2"), (KEY_A, VoteChoice::No, 150, "no cftest-2")]);
+    assert_eq!(votes[1].tx_hash, format!("{KEY_A}-1"));
+  }
+
+  #[tokio::test]
+  async fn test_window_skew() {
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 500)
+      .with_vote(KEY_B, "cftest-2", 100, BlockStatus::Canonical, 9400)
+      .with_vote(KEY_C, "cftest-2", 100, BlockStatus::Canonical, 9600);
+    let mut ocv = Ocv::new_for_test(archive, MemoryProvider::new(), vec![test_proposal()]);
+    let (start, end) = (WindowBound::Timestamp(1000), WindowBound::Timestamp(9000));
+    assert!(ocv.archive_transactions_in_window(start, end).await.unwrap().is_empty());
+
+    ocv.window_skew = Duration::from_millis(500);
+    let transactions = ocv.archive_transactions_in_window(start, end).await.unwrap();
+    assert_eq!(transactions.iter().map(|tx| tx.timestamp).collect::<Vec<_>>(), [500, 9400]);
+
+    // Slot bounds aren't widened.
+    let slots = ocv.archive_transactions_in_window(WindowBound::Slot(0), WindowBound::Slot(10)).await.unwrap();
+    assert_eq!(slots.len(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_vote_details() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": KEY_A },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "no cftest-2", 150, BlockStatus::Canonical, 2000)
+      .with_vote(KEY_C, "cftest-2", 160, BlockStatus::Canonical, 3000);
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+    // KEY_C has no stake, so its vote isn't counted.
+    ocv.min_voting_stake = Some(Decimal::ONE);
+
+    let details = ocv.vote_details(1).await.unwrap();
+    assert_eq!(details, [VoteDetail {
+      account: KEY_A.to_string(),
+      choice: VoteChoice::No,
+      stake: Decimal::from(10),
+      delegated_stake: Decimal::from(5),
+      weight: Decimal::from(15),
+      block_height: 150,
+      status: BlockStatus::Canonical,
+    }]);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_archive_ledger_hash() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 7_200)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+      .with_ledger_hash(1, LedgerKind::Staking, "jxTest");
+    let proposals = vec![
+      Proposal { ledger_hash: None, ..test_proposal() },
+      Proposal { id: 2, epoch: 2, ledger_hash: None, ..test_proposal() },
+      Proposal { id: 3, ledger_hash: None, ledger_selection: StakingOrNext::Next, ..test_proposal() },
+    ];
+    let ocv = Ocv::new_for_test(archive, provider, proposals);
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    // Epoch 2 hasn't begun, so its ledger isn't known yet.
+    let future = ocv.proposal_result(2, None).await.unwrap();
+    // A failed lookup isn't a ledger that isn't known yet.
+    let failed = ocv.proposal_result(3, None).await.err().unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(10));
+    assert_eq!(result.metadata.ledger_hashes, ["jxTest"]);
+    // Exports report the resolved ledger.
+    assert_eq!(result.to_mef()["ledger_hash"], "jxTest");
+    assert_eq!(result.to_proto().ledger_hash.as_deref(), Some("jxTest"));
+    assert_eq!(future.total_stake_weight, Decimal::ZERO);
+    let failed = failed.downcast_ref::<TallyError>().unwrap();
+    assert!(matches!(failed, TallyError::ArchiveUnavailable { .. }));
+    assert_eq!(failed.status_code(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_truncated_votes() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+      .with_vote(KEY_B, "no cftest-2", 100, BlockStatus::Canonical, 3000);
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+    ocv.max_votes_in_response = 1;
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    let proposal = ocv.proposal(1).await.unwrap();
+
+    assert_eq!((result.votes.len(), result.total_votes, result.truncated), (1, 2, true));
+    assert_eq!(result.total_stake_weight, Decimal::from(15));
+    assert_eq!((proposal.votes.len(), proposal.total_votes, proposal.truncated), (1, 2, true));
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_ledger_hash_override() {
+    let ledger = |balance: &str| serde_json::json!([{ "pk": KEY_A, "balance": balance, "delegate": null }]).to_string();
+    let provider = MemoryProvider::new()
+      .with_object("test-ledgers", "staking-1-jxTest.json", ledger("10"))
+      .with_object("test-ledgers", "staking-epoch-5-jxOther.json", ledger("70"))
+      .with_object("test-ledgers", "staking-epoch-6-jxOtherer.json", ledger("90"));
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+    ocv.audit_log = Arc::new(AuditLog::open(None, Some("audit/".to_string())).unwrap());
+
+    let result = ocv.proposal_result(1, Some("jxOther".to_string())).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(70));
+    // Only the tally against the proposal's own ledger is audited, once.
+    ocv.proposal_result(1, None).await.unwrap();
+    ocv.invalidate_tally(1).await.unwrap();
+    ocv.proposal_result(1, None).await.unwrap();
+    let audited = ocv.storage_provider.list_objects("test-ledgers", Some("audit/")).await.unwrap();
+    assert_eq!(audited, ["audit/000000000000-1.json"]);
+
+    let missing = ocv.proposal_result(1, Some("jxTes".to_string())).await.err().unwrap();
+    assert!(matches!(missing.downcast_ref::<TallyError>(), Some(TallyError::LedgerNotFound(_))));
+    let invalid = ocv.proposal_result(1, Some("../jxTest".to_string())).await.err().unwrap();
+    assert!(matches!(invalid.downcast_ref::<TallyError>(), Some(TallyError::InvalidLedgerHash(_))));
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_warnings() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "no cftest-2", 90, BlockStatus::Canonical, 2000)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 3000)
+      .with_vote(KEY_B, "cftest-2", 100, BlockStatus::Canonical, 4000)
+      .with_vote(KEY_C, "unrelated", 100, BlockStatus::Canonical, 4000);
+    let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+
+    let warnings: Vec<(TallyWarningCode, Option<usize>)> =
+      result.warnings.iter().map(|warning| (warning.code, warning.count)).collect();
+    assert_eq!(
+      warnings,
+      [
+        (TallyWarningCode::InexactLedgerMatch, None),
+        (TallyWarningCode::SupersededVotes, Some(1)),
+        (TallyWarningCode::VotersMissingFromLedger, Some(1)),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_tally_mode_with_fixed_clock() {
+    let mut ocv = Ocv::new_for_test(StubArchive::default(), MemoryProvider::new(), vec![test_proposal()]);
+    let proposal = test_proposal();
+    let mode_at = |ocv: &mut Ocv, millis: i64| {
+      ocv.clock = Arc::new(crate::FixedClock(DateTime::from_timestamp_millis(millis).unwrap()));
+      ocv.tally_mode(&proposal).unwrap()
+    };
+    // The window ends at 9000 inclusive.
+    assert_eq!(mode_at(&mut ocv, 9000), TallyMode::Live);
+    assert_eq!(mode_at(&mut ocv, 9001), TallyMode::Final);
+    assert_eq!(ocv.now().timestamp_millis(), 9001);
+    assert_eq!(ocv.open_proposals(ocv.now()).unwrap().len(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_prune_ledger_cache_keeps_open_proposals() {
+    let mut open = test_proposal();
+    open.epoch_ledgers = vec![EpochLedger { epoch: 2, ledger_hash: "jxNext".to_string() }];
+    let mut closed = test_proposal();
+    closed.id = 2;
+    closed.ledger_hash = Some("jxOld".to_string());
+    closed.end_time = ProposalTime::Millis(2000);
+    // Open, with the ledger the archive records for epoch 1.
+    let resolved = Proposal { id: 3, ledger_hash: None, ..test_proposal() };
+    let archive = StubArchive::new(200, SLOTS_PER_EPOCH + 10).with_ledger_hash(1, LedgerKind::Staking, "jxResolved");
+    let mut ocv = Ocv::new_for_test(archive, MemoryProvider::new(), vec![open, closed, resolved]);
+    ocv.clock = Arc::new(crate::FixedClock(DateTime::from_timestamp_millis(5000).unwrap()));
+    for (hash, contents) in [("jxTest", "[]"), ("jxOld", "[1]"), ("jxNext", "[]"), ("jxResolved", "[]")] {
+      std::fs::write(crate::ledger_cache_path(&ocv.ledger_storage_path, hash), contents).unwrap();
+    }
+
+    let response = ocv.prune_ledger_cache(Some(0), None).await.unwrap();
+    let cached = cached_ledgers(&ocv.ledger_storage_path).unwrap();
+    assert_eq!(response.pruned, ["jxOld"]);
+    assert_eq!((response.bytes_freed, response.bytes_remaining), (3, 6));
+    let mut cached: Vec<&str> = cached.iter().map(|ledger| ledger.hash.as_str()).collect();
+    cached.sort_unstable();
+    assert_eq!(cached, ["jxNext", "jxResolved", "jxTest"]);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_results_bulk() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let mut second = test_proposal();
+    second.id = 2;
+    let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal(), second]);
+
+    let response = ocv.proposal_results(&[2, 1, 99, 2]).await;
+    assert_eq!(response.results.keys().copied().collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(response.results[&2].positive_stake_weight, Decimal::from(10));
+    assert_eq!(response.errors.keys().copied().collect::<Vec<_>>(), [99]);
+    let votes = ocv.proposal_votes(&[1, 2]).await;
+    assert_eq!(votes.results[&1].votes.len(), 1);
+    assert!(votes.errors.is_empty());
+
+    // Both windows closed long ago.
+    let open = ocv.tally_all_open(ocv.now()).await.unwrap();
+    assert!(open.results.is_empty() && open.errors.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_cross_network() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new()
+      .with_object("test-ledgers", "mainnet/staking-epoch-1-jxTest-2.json", "[]")
+      .with_object("test-ledgers", "devnet/staking-epoch-1-jxTest-1.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let mut devnet = test_proposal();
+    devnet.network = Network::Devnet;
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![devnet]);
+    ocv.ledger_key_prefix = "{network}/".to_string();
+
+    // Served alongside mainnet's, as with `include_all_networks`.
+    let rejected = ocv.proposal_result(1, None).await.err().unwrap();
+    let rejected = rejected.downcast_ref::<TallyError>().unwrap();
+    assert!(matches!(rejected, TallyError::NetworkMismatch { id: 1, .. }));
+    assert_eq!(rejected.status_code(), axum::http::StatusCode::CONFLICT);
+
+    // Served on purpose, as with `proposals_network`.
+    ocv.proposals_loader.network = Network::Devnet;
+    let result = ocv.proposal_result(1, None).await.unwrap();
+
+    // The devnet ledger was used, and the mixed networks are flagged.
+    assert_eq!(result.positive_stake_weight, Decimal::from(10));
+    assert_eq!(result.warnings[0].code, TallyWarningCode::NetworkMismatch);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_across_epochs() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+    ]);
+    let next_ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "20", "delegate": null },
+      { "pk": KEY_B, "balance": "7", "delegate": null },
+    ]);
+    let provider = MemoryProvider::new()
+      .with_object("test-ledgers", "staking-epoch-1-jxTest.json", ledger.to_string())
+      .with_object("test-ledgers", "staking-epoch-2-jxNext.json", next_ledger.to_string());
+    // KEY_A votes in epoch 1, KEY_B once in each epoch.
+    let archive = StubArchive::new(200, 20_000)
+      .with_vote_at_slot(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000, 7_200)
+      .with_vote_at_slot(KEY_B, "no cftest-2", 101, BlockStatus::Canonical, 3000, 7_300)
+      .with_vote_at_slot(KEY_B, "cftest-2", 102, BlockStatus::Canonical, 4000, 2 * 7_140 + 5);
+    let mut proposal = test_proposal();
+    proposal.epoch_ledgers = vec![EpochLedger { epoch: 2, ledger_hash: "jxNext".to_string() }];
+    let ocv = Ocv::new_for_test(archive, provider, vec![proposal]);
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+
+    // KEY_A is weighted by the epoch 1 ledger; KEY_B's latest vote by epoch 2's.
+    assert_eq!(result.positive_stake_weight, Decimal::from(17));
+    assert_eq!(result.negative_stake_weight, Decimal::ZERO);
+    assert_eq!(result.metadata.ledger_hashes, ["jxTest", "jxNext"]);
+  }
+
+  #[tokio::test]
+  async fn test_strict_tally_missing_epoch_ledger() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+    ]);
+    // Epoch 2's ledger isn't in the bucket.
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-epoch-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 20_000)
+      .with_vote_at_slot(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000, 7_200)
+      .with_vote_at_slot(KEY_B, "cftest-2", 102, BlockStatus::Canonical, 4000, 2 * 7_140 + 5);
+    let mut proposal = test_proposal();
+    proposal.epoch_ledgers = vec![EpochLedger { epoch: 2, ledger_hash: "jxNext".to_string() }];
+    let ocv = Ocv::new_for_test(archive, provider, vec![proposal]);
+
+    // Epoch 2's vote is weighted by the proposal's ledger instead.
+    let partial = ocv.tally(1, None, false).await.unwrap();
+    assert_eq!(partial.positive_stake_weight, Decimal::from(15));
+    assert_eq!(partial.metadata.ledger_hashes, ["jxTest"]);
+    assert_eq!(partial.warnings.len(), 1);
+    assert_eq!(partial.warnings[0].code, TallyWarningCode::MissingLedger);
+
+    // The cached partial result isn't served to a strict tally.
+    let err = ocv.tally(1, None, true).await.err().unwrap();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::LedgerUnavailable { epoch: Some(2), .. })));
+  }
+
+  #[tokio::test]
+  async fn test_result_to_proto_round_trip() {
+    use prost::Message;
+
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    // Voted on by its keyword rather than its key.
+    let proposal = Proposal { key: "MIP2".to_string(), vote_keyword: Some("cftest-2".to_string()), ..test_proposal() };
+    let ocv = Ocv::new_for_test(archive, provider, vec![proposal]);
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.to_mef()["proposal_key"], "cftest-2");
+
+    let message = result.to_proto();
+    let decoded = proto::TallyResult::decode(message.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, message);
+    assert_eq!(decoded.proposal_key, "cftest-2");
+    assert_eq!(decoded.positive_stake_weight.parse::<Decimal>().unwrap(), Decimal::from(10));
+    assert_eq!(decoded.mode(), proto::TallyMode::Final);
+    assert_eq!(decoded.votes[0].status, "Canonical");
+    assert_eq!(decoded.ledger_hashes, ["jxTest"]);
+    assert_eq!(decoded.finalized_at_millis, None);
+  }
+
+  #[tokio::test]
+  async fn test_archive_closed_proposals() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, SLOTS_PER_EPOCH + 10)
+      .with_ledger_hash(1, LedgerKind::Staking, "jxTest")
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    // The second proposal's ledger is resolved from the archive.
+    let mut resolved = test_proposal();
+    resolved.id = 2;
+    resolved.ledger_hash = None;
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal(), resolved]);
+    ocv.serve_archived_results = true;
+    ocv.archived_results_key_prefix = Some("results/".to_string());
+
+    assert_eq!(ocv.archive_closed_proposals().await.unwrap(), [1, 2]);
+    assert!(ocv.archive_closed_proposals().await.unwrap().is_empty());
+    assert!(ocv.storage_provider.get_object("test-ledgers", "results/1.json").await.is_ok());
+
+    // The archived result is served even once the ledger is gone.
+    ocv.clear_ledger_cache(None, false).await.unwrap();
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert!(result.finalized_at.is_some());
+    assert_eq!(result.positive_stake_weight, Decimal::from(10));
+  }
+
+  #[tokio::test]
+  async fn test_cache_validate_on_read() {
+    let ledger = |balance: &str| serde_json::json!([{ "pk": KEY_A, "balance": balance, "delegate": null }]).to_string();
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger("10"));
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    assert_eq!(ocv.proposal_result(1, None).await.unwrap().positive_stake_weight, Decimal::from(10));
+    ocv.storage_provider.put_object("test-ledgers", "staking-1-jxTest.json", ledger("20").into()).await.unwrap();
+    assert_eq!(ocv.proposal_result(1, None).await.unwrap().positive_stake_weight, Decimal::from(10));
+
+    ocv.cache_validate_on_read = true;
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(20));
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_cache() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let mut ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.metadata.cache_ttl_secs, None);
+    assert_eq!(ocv.caches.tallies.get(&1).await.unwrap().chain_tip, 200);
+
+    // A final tally is served until the chain tip moves past it.
+    let stale = |chain_tip| {
+      let result = GetMinaProposalResultResponse { positive_stake_weight: Decimal::from(99), ..result.clone() };
+      CachedTally { result: Arc::new(result), chain_tip, ttl: None }
+    };
+    ocv.caches.tallies.insert(1, stale(200)).await;
+    assert_eq!(ocv.proposal_result(1, None).await.unwrap().positive_stake_weight, Decimal::from(99));
+    ocv.caches.tallies.insert(1, stale(199)).await;
+    assert_eq!(ocv.proposal_result(1, None).await.unwrap().positive_stake_weight, Decimal::from(10));
+
+    // An invalidated tally is recomputed.
+    ocv.caches.tallies.insert(1, stale(200)).await;
+    assert!(ocv.invalidate_tally(1).await.unwrap().was_cached);
+    assert!(!ocv.invalidate_tally(1).await.unwrap().was_cached);
+    assert_eq!(ocv.proposal_result(1, None).await.unwrap().positive_stake_weight, Decimal::from(10));
+    let unknown = ocv.invalidate_tally(7).await.unwrap_err();
+    assert!(matches!(unknown.downcast_ref(), Some(TallyError::ProposalNotFound(7))));
+
+    // Override tallies bypass the cache.
+    ocv.caches.tallies.insert(1, stale(200)).await;
+    let result = ocv.proposal_result(1, Some("jxTest".to_string())).await.unwrap();
+    assert_eq!(result.positive_stake_weight, Decimal::from(10));
+
+    ocv.caches.tallies.invalidate_all();
+    ocv.clock = Arc::new(crate::FixedClock(DateTime::from_timestamp_millis(5000).unwrap()));
+    let result = ocv.proposal_result(1, None).await.unwrap();
+    assert_eq!(result.metadata.mode, TallyMode::Live);
+    assert_eq!(result.metadata.cache_ttl_secs, Some(15));
+    assert_eq!(ocv.caches.tallies.get(&1).await.unwrap().ttl, Some(Duration::from_secs(15)));
+  }
+
+  #[tokio::test]
+  async fn test_archive_query_timeout() {
+    struct SlowArchive(StubArchive);
+
+    impl ArchiveInterface for SlowArchive {
+      fn fetch_chain_tip(&self) -> Result<i64> {
+        std::thread::sleep(Duration::from_millis(200));
+        self.0.fetch_chain_tip()
+      }
+
+      fn fetch_latest_slot(&self) -> Result<i64> {
+        self.0.fetch_latest_slot()
+      }
+
+      fn current_epoch_and_slot(&self) -> Result<(u32, u64)> {
+        self.0.current_epoch_and_slot()
+      }
+
+      fn staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+        self.0.staking_ledger_hash(epoch)
+      }
+
+      fn next_staking_ledger_hash(&self, epoch: u32) -> Result<String> {
+        self.0.next_staking_ledger_hash(epoch)
+      }
+
+      fn fetch_transactions(&self, start_time: i64, end_time: i64) -> Result<Vec<FetchTransactionResult>> {
+        self.0.fetch_transactions(start_time, end_time)
+      }
+
+      fn fetch_transactions_in_window(
+        &self,
+        start: WindowBound,
+        end: WindowBound,
+      ) -> Result<Vec<FetchTransactionResult>> {
+        self.0.fetch_transactions_in_window(start, end)
+      }
+    }
+
+    let mut ocv = Ocv::new_for_test(SlowArchive(StubArchive::default()), MemoryProvider::new(), vec![test_proposal()]);
+    ocv.db_query_timeout = Duration::from_millis(20);
+    let err = ocv.proposal(1).await.err().unwrap();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::ArchiveTimeout { query: "fetch_chain_tip", .. })));
+    assert_eq!(err.downcast_ref::<TallyError>().unwrap().status_code(), axum::http::StatusCode::GATEWAY_TIMEOUT);
+
+    ocv.db_query_timeout = Duration::from_secs(5);
+    assert!(ocv.proposal(1).await.is_ok());
+  }
+
+  #[test]
+  fn test_proposals_by_phase() {
+    let with_window = |id: usize, start: i64, end: i64| {
+      let mut proposal = test_proposal();
+      proposal.id = id;
+      proposal.start_time = ProposalTime::Millis(start);
+      proposal.end_time = ProposalTime::Millis(end);
+      proposal
+    };
+    let proposals = vec![with_window(1, 1000, 2000), with_window(2, 2000, 3000), with_window(3, 3001, 4000)];
+    let ocv = Ocv::new_for_test(StubArchive::default(), MemoryProvider::new(), proposals);
+    let ids = |proposals: Vec<Proposal>| proposals.iter().map(|proposal| proposal.id).collect::<Vec<_>>();
+    let at = |millis: i64| DateTime::from_timestamp_millis(millis).unwrap();
+
+    assert_eq!(ids(ocv.open_proposals(at(2000)).unwrap()), [1, 2]);
+    assert_eq!(ids(ocv.upcoming_proposals(at(2000)).unwrap()), [3]);
+    assert!(ocv.closed_proposals(at(2000)).unwrap().is_empty());
+    assert_eq!(ids(ocv.open_proposals(at(3001)).unwrap()), [3]);
+    assert_eq!(ids(ocv.closed_proposals(at(3001)).unwrap()), [1, 2]);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_to_mef() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "10", "delegate": null },
+      { "pk": KEY_B, "balance": "5", "delegate": null },
+      { "pk": KEY_C, "balance": "1", "delegate": null },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+      .with_vote(KEY_B, "no cftest-2", 100, BlockStatus::Canonical, 3000)
+      .with_vote(KEY_C, "cftest-2", 100, BlockStatus::Canonical, 4000);
+    let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    let mef = ocv.proposal_result(1, None).await.unwrap().to_mef();
+
+    assert_eq!(mef["proposal_id"], 1);
+    assert_eq!(mef["ledger_hash"], "jxTest");
+    assert_eq!(mef["total_community_votes"], 3);
+    assert_eq!(mef["total_positive_community_votes"], 2);
+    assert_eq!(mef["stake_by_choice"]["yes"], serde_json::json!(Decimal::from(11)));
+    assert_eq!(mef["stake_by_choice"]["no"], serde_json::json!(Decimal::from(5)));
+    assert_eq!(mef["elegible"], true);
+    assert_eq!(mef["vote_status"], "Approved");
+    assert_eq!(mef["final"], true);
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_weighting_schemes() {
+    let cases = [
+      (WeightingScheme::Linear, Decimal::from(125)),
+      (WeightingScheme::Sqrt, Decimal::from(15)),
+      (WeightingScheme::Capped { max: Decimal::from(50) }, Decimal::from(75)),
+    ];
+    for (weighting, expected) in cases {
+      let ledger = serde_json::json!([
+        { "pk": KEY_A, "balance": "100", "delegate": null },
+        { "pk": KEY_B, "balance": "25", "delegate": null },
+      ]);
+      let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+      let archive = StubArchive::new(200, 300)
+        .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+        .with_vote(KEY_B, "cftest-2", 100, BlockStatus::Canonical, 3000);
+      let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal_with_weighting(weighting)]);
+
+      let result = ocv.proposal_result(1, None).await.unwrap();
+
+      assert_eq!(result.metadata.weighting, weighting);
+      assert_eq!(result.positive_stake_weight.round_dp(6), expected, "{:?}", weighting);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_quorum_and_approval_threshold() {
+    // One yes vote holding 100 of 160 counted stake.
+    let cases = [
+      (None, None, "Insufficient voters"),
+      (Some(1), None, "Approved"),
+      (Some(1), Some(Decimal::new(6, 1)), "Approved"),
+      (Some(1), Some(Decimal::new(7, 1)), "Rejected"),
+      (Some(2), Some(Decimal::ZERO), "Insufficient voters"),
+    ];
+    for (quorum, approval_threshold, expected) in cases {
+      let ledger = serde_json::json!([
+        { "pk": KEY_A, "balance": "100", "delegate": null },
+        { "pk": KEY_B, "balance": "60", "delegate": null },
+      ]);
+      let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+      let archive = StubArchive::new(200, 300)
+        .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+        .with_vote(KEY_B, "no cftest-2", 100, BlockStatus::Canonical, 3000);
+      let mut proposal = test_proposal();
+      proposal.quorum = quorum;
+      proposal.approval_threshold = approval_threshold;
+      let ocv = Ocv::new_for_test(archive, provider, vec![proposal]);
+
+      let result = ocv.proposal_result(1, None).await.unwrap();
+
+      assert_eq!(result.vote_status(), expected, "{:?} {:?}", quorum, approval_threshold);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_proposal_result_min_voting_stake() {
+    // The proposal's minimum overrides the server's.
+    let cases = [
+      (Some(10), 50, Decimal::from(25), 1, Decimal::from(5)),
+      (None, 50, Decimal::ZERO, 2, Decimal::from(30)),
+    ];
+    for (proposal_min, server_min, negative, below_votes, below_weight) in cases {
+      let ledger = serde_json::json!([
+        { "pk": KEY_A, "balance": "100", "delegate": null },
+        { "pk": KEY_B, "balance": "25", "delegate": null },
+        { "pk": KEY_C, "balance": "5", "delegate": null },
+      ]);
+      let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+      let archive = StubArchive::new(200, 300)
+        .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+        .with_vote(KEY_B, "no cftest-2", 100, BlockStatus::Canonical, 3000)
+        .with_vote(KEY_C, "cftest-2", 100, BlockStatus::Canonical, 4000);
+      let mut proposal = test_proposal();
+      proposal.min_voting_stake = proposal_min.map(Decimal::from);
+      let mut ocv = Ocv::new_for_test(archive, provider, vec![proposal]);
+      ocv.min_voting_stake = Some(Decimal::from(server_min));
+
+      let result = ocv.proposal_result(1, None).await.unwrap();
+
+      assert_eq!(result.positive_stake_weight, Decimal::from(100));
+      assert_eq!(result.negative_stake_weight, negative);
+      assert_eq!(result.total_stake_weight, Decimal::from(100) + negative);
+      assert_eq!(result.votes.len(), 3 - below_votes);
+      assert_eq!(result.metadata.positive_votes + result.metadata.negative_votes, 3 - below_votes);
+      let expected_min = Decimal::from(proposal_min.unwrap_or(server_min));
+      let expected =
+        BelowThresholdVotes { min_voting_stake: Some(expected_min), votes: below_votes, stake_weight: below_weight };
+      assert_eq!(result.below_threshold, expected);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_startup_selftest() {
+    let ledger = serde_json::json!([{ "pk": KEY_A, "balance": "100", "delegate": null }]).to_string();
+    let provider = MemoryProvider::new()
+      .with_object("test-ledgers", "staking-epoch-1-jxOld.json", ledger.clone())
+      .with_object("test-ledgers", "staking-epoch-2-jxNew.json", ledger)
+      .with_object("test-ledgers", "next-staking-epoch-3-jxNext.json", "not json");
+    let ocv = Ocv::new_for_test(StubArchive::default(), provider, vec![]);
+    let newest = ocv.startup_selftest().await.unwrap();
+    assert_eq!((newest.epoch, newest.hash.as_str()), (2, "jxNew"));
+
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-epoch-4-jxBad.json", "not json");
+    let ocv = Ocv::new_for_test(StubArchive::default(), provider, vec![]);
+    assert!(ocv.startup_selftest().await.is_err());
+
+    let ocv = Ocv::new_for_test(StubArchive::default(), MemoryProvider::new(), vec![]);
+    assert!(ocv.startup_selftest().await.unwrap_err().to_string().contains("no staking ledger"));
+  }
+
+  #[tokio::test]
+  async fn test_proposal_nonvoters() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "100", "delegate": null },
+      { "pk": KEY_B, "balance": "25", "delegate": null },
+      { "pk": KEY_C, "balance": "50", "delegate": null },
+      { "pk": KEY_D, "balance": "1", "delegate": KEY_C },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let ocv = Ocv::new_for_test(archive, provider, vec![test_proposal()]);
+
+    let all = ocv.proposal_nonvoters(1, None, 0, 10).await.unwrap();
+    assert_eq!(all.total, 3);
+    let accounts: Vec<&str> = all.accounts.iter().map(|nonvoter| nonvoter.account.as_str()).collect();
+    assert_eq!(accounts, [KEY_C, KEY_B, KEY_D]);
+
+    let page = ocv.proposal_nonvoters(1, Some(Decimal::from(10)), 1, 1).await.unwrap();
+    assert_eq!(page.total, 2);
+    assert_eq!(page.accounts, [Nonvoter { account: PublicKey::new_unchecked(KEY_B), stake: Decimal::from(25) }]);
+  }
+
+  #[tokio::test]
+  async fn test_nonvoters_and_timeseries_resolve_archive_ledger() {
+    let ledger = serde_json::json!([
+      { "pk": KEY_A, "balance": "100", "delegate": null },
+      { "pk": KEY_B, "balance": "25", "delegate": null },
+    ]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 7_200)
+      .with_vote(KEY_A, "cftest-2", 100, BlockStatus::Canonical, 2000)
+      .with_ledger_hash(1, LedgerKind::Staking, "jxTest");
+    let ocv = Ocv::new_for_test(archive, provider, vec![Proposal { ledger_hash: None, ..test_proposal() }]);
+
+    let nonvoters = ocv.proposal_nonvoters(1, None, 0, 10).await.unwrap();
+    let timeseries = ocv.proposal_timeseries(1, TimeseriesBucket::Hour).await.unwrap();
+    let result = ocv.proposal_result(1, None).await.unwrap();
+
+    assert_eq!(nonvoters.total, 1);
+    assert_eq!(nonvoters.accounts[0].account.as_str(), KEY_B);
+    let last = timeseries.points.last().unwrap();
+    assert_eq!((last.yes_votes, last.yes_stake), (1, result.positive_stake_weight));
+    assert_eq!(last.yes_stake, Decimal::from(100));
+  }
+}
@@ -1,18 +1,86 @@
+use std::cmp::Ordering;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, MathematicalOps};
+use schemars::{JsonSchema, schema::RootSchema, schema_for};
 use serde::{Deserialize, Serialize};
 
-use crate::Network;
+use crate::{LedgerKind, Network, VoteKeywords, vote::normalize_memo};
+
+/// Length of a Mina slot in milliseconds (3 minutes).
+pub const SLOT_DURATION_MS: i64 = 3 * 60 * 1000;
+/// Number of slots in a Mina epoch.
+pub const SLOTS_PER_EPOCH: i64 = 7140;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
 pub struct ProposalsManifest {
+  /// Values proposals in this manifest inherit unless they set their own.
+  #[serde(default)]
+  pub defaults: ProposalDefaults,
   pub proposals: Vec<Proposal>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl ProposalsManifest {
+  /// Parses a manifest, filling each proposal's unset fields from its
+  /// `defaults`.
+  pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+    let mut manifest: serde_json::Value = serde_json::from_slice(bytes)?;
+    apply_manifest_defaults(&mut manifest)?;
+    Ok(serde_json::from_value(manifest)?)
+  }
+}
+
+/// Manifest-wide values for proposal fields. A proposal that sets a field,
+/// even to `null`, overrides its default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+pub struct ProposalDefaults {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quorum: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub approval_threshold: Option<Decimal>,
+  #[serde(default, alias = "weighting_scheme", skip_serializing_if = "Option::is_none")]
+  pub weighting: Option<WeightingScheme>,
+}
+
+/// Copies the `defaults` of a manifest, as JSON, into each of its proposals
+/// that doesn't set the field itself, so proposals carry their effective
+/// values once parsed.
+pub fn apply_manifest_defaults(manifest: &mut serde_json::Value) -> Result<()> {
+  let defaults: ProposalDefaults = match manifest.get("defaults") {
+    Some(defaults) => serde_json::from_value(defaults.clone()).context("Invalid manifest defaults")?,
+    None => return Ok(()),
+  };
+  let serde_json::Value::Object(defaults) = serde_json::to_value(defaults)? else {
+    return Ok(());
+  };
+  let proposals = manifest.get_mut("proposals").and_then(serde_json::Value::as_array_mut);
+  for proposal in proposals.into_iter().flatten().filter_map(serde_json::Value::as_object_mut) {
+    for (field, value) in &defaults {
+      proposal.entry(field.clone()).or_insert_with(|| value.clone());
+    }
+  }
+  Ok(())
+}
+
+/// JSON Schema of `proposals.json`, derived from `ProposalsManifest`.
+pub fn proposals_manifest_schema() -> RootSchema {
+  schema_for!(ProposalsManifest)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Proposal {
   pub id: usize,
+  /// Identifies the proposal; also its vote keyword unless `vote_keyword` is
+  /// set.
+  #[serde(default)]
   pub key: String,
-  pub start_time: i64,
-  pub end_time: i64,
+  /// Memo voting yes on the proposal, overriding `key`; see
+  /// [`Proposal::vote_keyword`].
+  #[serde(default)]
+  pub vote_keyword: Option<String>,
+  pub start_time: ProposalTime,
+  pub end_time: ProposalTime,
   pub epoch: i64,
   pub ledger_hash: Option<String>,
   pub category: ProposalCategory,
@@ -22,9 +90,281 @@ pub struct Proposal {
   pub url: String,
   pub network: Network,
   pub is_complete: bool,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub weighting: WeightingScheme,
+  /// Whether votes are weighted by the staking ledger or the next staking
+  /// epoch's ledger.
+  #[serde(default)]
+  pub ledger_selection: StakingOrNext,
+  /// Staking ledgers of later epochs the voting window runs into. Votes are
+  /// weighted by the staking ledger of the epoch their block was produced
+  /// in: this ledger for that epoch, `ledger_hash` for any other. Each
+  /// account's latest vote is the one counted, whichever epoch it fell in.
+  #[serde(default)]
+  pub epoch_ledgers: Vec<EpochLedger>,
+  /// Memos counted as yes votes; defaults to the vote keyword.
+  #[serde(default)]
+  pub yes_keywords: Vec<String>,
+  /// Memos counted as no votes; defaults to `no <vote keyword>`.
+  #[serde(default)]
+  pub no_keywords: Vec<String>,
+  /// Memo of an on-chain transaction that opens voting. Votes before the
+  /// first such transaction in the window are ignored.
+  #[serde(default)]
+  pub open_marker: Option<String>,
+  /// Memo of an on-chain transaction that closes voting. Votes after the
+  /// first such transaction following the opening are ignored.
+  #[serde(default)]
+  pub close_marker: Option<String>,
+  /// Account whose transactions alone may open or close voting. Required
+  /// when either marker is set.
+  #[serde(default)]
+  pub marker_account: Option<String>,
+  /// Stake, in MINA, below which a voter's vote isn't counted. Overrides the
+  /// server's `min_voting_stake`.
+  #[serde(default)]
+  pub min_voting_stake: Option<Decimal>,
+  /// Yes votes needed for the result to stand. Overrides the release stage's
+  /// default: 10 in production, 2 elsewhere.
+  #[serde(default)]
+  pub quorum: Option<usize>,
+  /// Share of counted stake weight, between 0 and 1, that yes votes must
+  /// exceed for the proposal to be approved. Defaults to one half.
+  #[serde(default)]
+  pub approval_threshold: Option<Decimal>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Proposal {
+  pub fn window_start(&self) -> WindowBound {
+    self.start_time.normalize()
+  }
+
+  pub fn window_end(&self) -> WindowBound {
+    match self.end_time {
+      ProposalTime::Epoch { epoch, slot_offset: None } => WindowBound::Slot((epoch + 1) * SLOTS_PER_EPOCH - 1),
+      _ => self.end_time.normalize(),
+    }
+  }
+
+  /// Replaces epoch-relative window bounds with the global slots they stand
+  /// for. Epochs span `SLOTS_PER_EPOCH` slots from genesis, so upcoming
+  /// epochs resolve like past ones. Fails if an offset falls outside its
+  /// epoch.
+  pub fn resolve_epoch_window(&mut self) -> Result<()> {
+    let id = self.id;
+    for (time, is_end) in [(&mut self.start_time, false), (&mut self.end_time, true)] {
+      let ProposalTime::Epoch { epoch, slot_offset } = *time else {
+        continue;
+      };
+      if epoch < 0 {
+        bail!("Proposal {}: invalid epoch {}", id, epoch);
+      }
+      let first = epoch * SLOTS_PER_EPOCH;
+      let last = first + SLOTS_PER_EPOCH - 1;
+      let slot = match slot_offset {
+        Some(offset) if (0 .. SLOTS_PER_EPOCH).contains(&offset) => first + offset,
+        Some(offset) => bail!("Proposal {}: slot offset {} is outside epoch {}", id, offset, epoch),
+        None if is_end => last,
+        None => first,
+      };
+      *time = ProposalTime::Slot { slot };
+    }
+    Ok(())
+  }
+
+  /// Where the voting window stands at `now_millis`, or at `current_slot`
+  /// for slot bounds. Both bounds are inclusive.
+  pub fn phase(&self, now_millis: i64, current_slot: i64) -> ProposalPhase {
+    let position = |bound: WindowBound| match bound {
+      WindowBound::Timestamp(millis) => now_millis.cmp(&millis),
+      WindowBound::Slot(slot) => current_slot.cmp(&slot),
+    };
+    if position(self.window_start()) == Ordering::Less {
+      ProposalPhase::Upcoming
+    } else if position(self.window_end()) == Ordering::Greater {
+      ProposalPhase::Closed
+    } else {
+      ProposalPhase::Open
+    }
+  }
+
+  pub fn has_slot_window(&self) -> bool {
+    matches!(self.window_start(), WindowBound::Slot(_)) || matches!(self.window_end(), WindowBound::Slot(_))
+  }
+
+  /// The memo voting yes on the proposal: `vote_keyword` if set, else `key`,
+  /// else the slugified title. Empty only if all three are.
+  pub fn vote_keyword(&self) -> String {
+    match self.vote_keyword.as_deref().map(str::trim).filter(|keyword| !keyword.is_empty()) {
+      Some(keyword) => keyword.to_string(),
+      None if !self.key.trim().is_empty() => self.key.trim().to_string(),
+      None => slugify(&self.title),
+    }
+  }
+
+  pub fn vote_keywords(&self) -> VoteKeywords {
+    let keyword = self.vote_keyword();
+    let yes = if self.yes_keywords.is_empty() { vec![keyword.clone()] } else { self.yes_keywords.clone() };
+    let no = if self.no_keywords.is_empty() { vec![format!("no {keyword}")] } else { self.no_keywords.clone() };
+    VoteKeywords::new(&yes, &no)
+  }
+
+  /// Which window marker, if any, the decoded `memo` is. Compared like vote
+  /// keywords, ignoring case and repeated whitespace.
+  pub fn window_marker(&self, memo: &str) -> Option<WindowMarker> {
+    let matches = |marker: &Option<String>| {
+      marker.as_deref().is_some_and(|marker| normalize_memo(marker) == normalize_memo(memo))
+    };
+    if matches(&self.open_marker) {
+      Some(WindowMarker::Open)
+    } else if matches(&self.close_marker) {
+      Some(WindowMarker::Close)
+    } else {
+      None
+    }
+  }
+
+  /// Whether the proposal is in `category` (if given) and carries every tag
+  /// in `tags`. Both comparisons ignore case.
+  pub fn matches_filters(&self, category: Option<&str>, tags: &[String]) -> bool {
+    let category_matches =
+      category.map_or(true, |category| format!("{:?}", self.category).eq_ignore_ascii_case(category));
+    category_matches && tags.iter().all(|tag| self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag)))
+  }
+
+  /// Checks that the voting window is well-formed: both bounds use the same
+  /// form, start precedes end, and the window is no longer than
+  /// `max_window_days`.
+  pub fn validate(&self, max_window_days: i64) -> Result<()> {
+    let max_window_ms = max_window_days * 24 * 60 * 60 * 1000;
+    let length_ms = match (self.window_start(), self.window_end()) {
+      (WindowBound::Timestamp(start), WindowBound::Timestamp(end)) => {
+        if start >= end {
+          bail!("Proposal {}: start_time {} is not before end_time {}", self.id, start, end);
+        }
+        end - start
+      }
+      (WindowBound::Slot(start), WindowBound::Slot(end)) => {
+        if start >= end {
+          bail!("Proposal {}: start slot {} is not before end slot {}", self.id, start, end);
+        }
+        (end - start) * SLOT_DURATION_MS
+      }
+      _ => bail!("Proposal {}: start_time and end_time must both be timestamps or both be slots", self.id),
+    };
+
+    if length_ms > max_window_ms {
+      bail!("Proposal {}: voting window exceeds the maximum of {} days", self.id, max_window_days);
+    }
+
+    let out_of_range = |threshold: &Decimal| *threshold < Decimal::ZERO || *threshold >= Decimal::ONE;
+    if let Some(threshold) = self.approval_threshold.filter(out_of_range) {
+      bail!("Proposal {}: approval_threshold {} is not in [0, 1)", self.id, threshold);
+    }
+
+    if (self.open_marker.is_some() || self.close_marker.is_some()) && self.marker_account.is_none() {
+      bail!("Proposal {}: open_marker and close_marker require marker_account", self.id);
+    }
+
+    if self.vote_keyword().is_empty() {
+      bail!("Proposal {}: no vote keyword; set vote_keyword, key or title", self.id);
+    }
+    let keywords = self.vote_keywords();
+    let overlapping = keywords.overlapping();
+    if !overlapping.is_empty() {
+      bail!("Proposal {}: keywords {:?} count as both yes and no", self.id, overlapping);
+    }
+    Ok(())
+  }
+}
+
+/// Lowercases `title` and joins its alphanumeric runs with `-`, so
+/// `"MIP 7: Fee Burn"` becomes `mip-7-fee-burn`.
+fn slugify(title: &str) -> String {
+  title
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .map(str::to_lowercase)
+    .collect::<Vec<_>>()
+    .join("-")
+}
+
+/// A proposal window bound as written in `proposals.json`.
+///
+/// Accepts a unix timestamp in milliseconds, an RFC3339 timestamp string, a
+/// global slot in the form `{ "slot": <n> }`, or a slot of an epoch in the
+/// form `{ "epoch": <n>, "slot_offset": <n> }`. Without an offset, an epoch
+/// starts the window at its first slot and ends it at its last, so a window
+/// from `{ "epoch": 55 }` to `{ "epoch": 55 }` spans the full epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum ProposalTime {
+  Millis(i64),
+  Rfc3339(DateTime<Utc>),
+  Epoch {
+    epoch: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slot_offset: Option<i64>,
+  },
+  Slot { slot: i64 },
+}
+
+impl ProposalTime {
+  /// The bound as a window start; see [`Proposal::window_end`] for ends.
+  pub fn normalize(&self) -> WindowBound {
+    match self {
+      ProposalTime::Millis(millis) => WindowBound::Timestamp(*millis),
+      ProposalTime::Rfc3339(datetime) => WindowBound::Timestamp(datetime.timestamp_millis()),
+      ProposalTime::Epoch { epoch, slot_offset } => {
+        WindowBound::Slot(epoch * SLOTS_PER_EPOCH + slot_offset.unwrap_or(0))
+      }
+      ProposalTime::Slot { slot } => WindowBound::Slot(*slot),
+    }
+  }
+}
+
+/// A normalized window bound consumed by the archive queries.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowBound {
+  /// Unix timestamp in milliseconds.
+  Timestamp(i64),
+  /// Global slot.
+  Slot(i64),
+}
+
+/// An on-chain transaction opening or closing a proposal's voting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMarker {
+  Open,
+  Close,
+}
+
+/// Where a proposal's voting window stands relative to a point in time.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProposalPhase {
+  Upcoming,
+  Open,
+  Closed,
+}
+
+impl std::str::FromStr for ProposalPhase {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "upcoming" => Ok(ProposalPhase::Upcoming),
+      "open" => Ok(ProposalPhase::Open),
+      "closed" => Ok(ProposalPhase::Closed),
+      _ => bail!("Unsupported status '{s}'. Supported statuses: upcoming, open, closed"),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub enum ProposalCategory {
   Core,
   Networking,
@@ -33,8 +373,293 @@ pub enum ProposalCategory {
   Cryptography,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// How a voter's stake is turned into voting weight.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+pub enum WeightingScheme {
+  /// Weight equals stake.
+  #[default]
+  Linear,
+  /// Weight is the square root of stake, dampening large holders.
+  Sqrt,
+  /// Weight is stake clamped at `max`.
+  Capped { max: Decimal },
+}
+
+impl WeightingScheme {
+  pub fn apply(&self, stake: Decimal) -> Decimal {
+    match self {
+      WeightingScheme::Linear => stake,
+      WeightingScheme::Sqrt => stake.sqrt().unwrap_or(Decimal::ZERO),
+      WeightingScheme::Capped { max } => stake.min(*max),
+    }
+  }
+}
+
+/// The staking ledger weighting votes cast during `epoch`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+pub struct EpochLedger {
+  pub epoch: i64,
+  pub ledger_hash: String,
+}
+
+/// Which ledger of the proposal's epoch weights its votes, as set by the
+/// governance rules it falls under.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StakingOrNext {
+  /// A `staking-epoch-` ledger.
+  #[default]
+  Staking,
+  /// A `next-staking-epoch-` ledger.
+  Next,
+}
+
+impl StakingOrNext {
+  pub fn ledger_kind(self) -> LedgerKind {
+    match self {
+      StakingOrNext::Staking => LedgerKind::Staking,
+      StakingOrNext::Next => LedgerKind::NextStaking,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub enum ProposalVersion {
   V1,
   V2,
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn proposal_with_window(start_time: serde_json::Value, end_time: serde_json::Value) -> Result<Proposal> {
+    let value = serde_json::json!({
+      "id": 7,
+      "key": "MIP7",
+      "start_time": start_time,
+      "end_time": end_time,
+      "epoch": 1,
+      "ledger_hash": null,
+      "category": "Core",
+      "version": "V2",
+      "title": "",
+      "description": "",
+      "url": "",
+      "network": "mainnet",
+      "is_complete": false
+    });
+    Ok(serde_json::from_value(value)?)
+  }
+
+  #[test]
+  fn test_window_forms() {
+    let millis = proposal_with_window(1684562400000i64.into(), 1685253600000i64.into()).unwrap();
+    assert_eq!(millis.window_start(), WindowBound::Timestamp(1684562400000));
+    assert!(millis.validate(90).is_ok());
+
+    let rfc3339 = proposal_with_window("2023-05-20T06:00:00Z".into(), "2023-05-28T06:00:00Z".into()).unwrap();
+    assert_eq!(rfc3339.window_start(), WindowBound::Timestamp(1684562400000));
+    assert!(rfc3339.validate(90).is_ok());
+
+    let slots = proposal_with_window(serde_json::json!({ "slot": 100 }), serde_json::json!({ "slot": 200 })).unwrap();
+    assert_eq!(slots.window_end(), WindowBound::Slot(200));
+    assert!(slots.validate(90).is_ok());
+  }
+
+  #[test]
+  fn test_window_validation() {
+    let swapped = proposal_with_window(1685253600000i64.into(), 1684562400000i64.into()).unwrap();
+    assert!(swapped.validate(90).unwrap_err().to_string().starts_with("Proposal 7"));
+
+    let mixed = proposal_with_window(serde_json::json!({ "slot": 100 }), 1684562400000i64.into()).unwrap();
+    assert!(mixed.validate(90).is_err());
+
+    let too_long =
+      proposal_with_window(serde_json::json!({ "slot": 0 }), serde_json::json!({ "slot": 480 * 31 })).unwrap();
+    assert!(too_long.validate(30).is_err());
+
+    assert!(proposal_with_window("not a date".into(), 1684562400000i64.into()).is_err());
+  }
+
+  #[test]
+  fn test_resolve_epoch_window() {
+    let epoch = |epoch: i64| serde_json::json!({ "epoch": epoch });
+
+    let mut whole = proposal_with_window(epoch(55), epoch(55)).unwrap();
+    assert_eq!(whole.window_end(), WindowBound::Slot(56 * SLOTS_PER_EPOCH - 1));
+    whole.resolve_epoch_window().unwrap();
+    assert_eq!(whole.start_time, ProposalTime::Slot { slot: 55 * SLOTS_PER_EPOCH });
+    assert_eq!(whole.end_time, ProposalTime::Slot { slot: 56 * SLOTS_PER_EPOCH - 1 });
+    assert!(whole.validate(90).is_ok());
+
+    let offsets = serde_json::json!({ "epoch": 56, "slot_offset": 480 });
+    let mut offset = proposal_with_window(serde_json::json!({ "epoch": 56, "slot_offset": 5 }), offsets).unwrap();
+    offset.resolve_epoch_window().unwrap();
+    assert_eq!(offset.window_start(), WindowBound::Slot(56 * SLOTS_PER_EPOCH + 5));
+    assert_eq!(offset.window_end(), WindowBound::Slot(56 * SLOTS_PER_EPOCH + 480));
+
+    // Proposals are announced ahead of their epoch.
+    let mut upcoming = proposal_with_window(epoch(1000), epoch(1001)).unwrap();
+    upcoming.resolve_epoch_window().unwrap();
+    assert_eq!(upcoming.window_start(), WindowBound::Slot(1000 * SLOTS_PER_EPOCH));
+    assert_eq!(upcoming.window_end(), WindowBound::Slot(1002 * SLOTS_PER_EPOCH - 1));
+
+    let outside = serde_json::json!({ "epoch": 55, "slot_offset": SLOTS_PER_EPOCH });
+    let err = proposal_with_window(epoch(55), outside).unwrap().resolve_epoch_window().unwrap_err();
+    assert!(err.to_string().contains("slot offset 7140 is outside epoch 55"));
+    assert!(proposal_with_window(epoch(-1), epoch(1)).unwrap().resolve_epoch_window().is_err());
+  }
+
+  #[test]
+  fn test_proposals_manifest_schema() {
+    let schema = serde_json::to_value(proposals_manifest_schema()).unwrap();
+    let proposal = &schema["definitions"]["Proposal"];
+    let required: Vec<&str> = proposal["required"].as_array().unwrap().iter().filter_map(|v| v.as_str()).collect();
+    assert!(required.contains(&"start_time") && required.contains(&"network"));
+    // The vote keyword may come from `vote_keyword`, `key` or `title`.
+    assert!(!required.contains(&"key") && !required.contains(&"vote_keyword"));
+    // Fields with serde defaults, and optional ones, may be left out.
+    assert!(!required.contains(&"tags") && !required.contains(&"weighting") && !required.contains(&"ledger_hash"));
+    assert!(proposal["properties"]["yes_keywords"].is_object());
+    assert_eq!(schema["required"], serde_json::json!(["proposals"]));
+  }
+
+  #[test]
+  fn test_checked_in_schema_matches() {
+    let checked_in = include_str!("../proposals/proposals_schema.json");
+    let generated = format!("{}\n", serde_json::to_string_pretty(&proposals_manifest_schema()).unwrap());
+    assert!(
+      checked_in == generated,
+      "proposals/proposals_schema.json is stale; regenerate it with \
+       `cargo run --bin mina_ocv -- proposals schema > proposals/proposals_schema.json`"
+    );
+  }
+
+  #[test]
+  fn test_phase() {
+    let millis = proposal_with_window(1000i64.into(), 9000i64.into()).unwrap();
+    assert_eq!(millis.phase(999, 0), ProposalPhase::Upcoming);
+    assert_eq!(millis.phase(1000, 0), ProposalPhase::Open);
+    assert_eq!(millis.phase(9000, 0), ProposalPhase::Open);
+    assert_eq!(millis.phase(9001, 0), ProposalPhase::Closed);
+    assert!(!millis.has_slot_window());
+
+    let slots = proposal_with_window(serde_json::json!({ "slot": 100 }), serde_json::json!({ "slot": 200 })).unwrap();
+    assert_eq!(slots.phase(i64::MAX, 99), ProposalPhase::Upcoming);
+    assert_eq!(slots.phase(0, 150), ProposalPhase::Open);
+    assert_eq!(slots.phase(0, 201), ProposalPhase::Closed);
+    assert!(slots.has_slot_window());
+
+    assert_eq!("open".parse::<ProposalPhase>().unwrap(), ProposalPhase::Open);
+    assert!("finished".parse::<ProposalPhase>().is_err());
+  }
+
+  #[test]
+  fn test_weighting_scheme() {
+    let stake = Decimal::from(400);
+    assert_eq!(WeightingScheme::Linear.apply(stake), stake);
+    assert_eq!(WeightingScheme::Sqrt.apply(stake).round_dp(6), Decimal::from(20));
+    assert_eq!(WeightingScheme::Capped { max: Decimal::from(100) }.apply(stake), Decimal::from(100));
+    assert_eq!(WeightingScheme::Capped { max: Decimal::from(500) }.apply(stake), stake);
+
+    let proposal = proposal_with_window(1684562400000i64.into(), 1685253600000i64.into()).unwrap();
+    assert_eq!(proposal.weighting, WeightingScheme::Linear);
+    let capped: WeightingScheme = serde_json::from_value(serde_json::json!({ "Capped": { "max": "1000" } })).unwrap();
+    assert_eq!(capped, WeightingScheme::Capped { max: Decimal::from(1000) });
+  }
+
+  #[test]
+  fn test_manifest_defaults() {
+    let mut inheriting = serde_json::to_value(proposal_with_window(1000i64.into(), 9000i64.into()).unwrap()).unwrap();
+    let mut overriding = inheriting.clone();
+    for field in ["weighting", "quorum", "approval_threshold"] {
+      inheriting.as_object_mut().unwrap().remove(field);
+    }
+    overriding["id"] = 8.into();
+    overriding["weighting"] = "Linear".into();
+    overriding["quorum"] = 3.into();
+    overriding["approval_threshold"] = serde_json::Value::Null;
+    let manifest = serde_json::json!({
+      "defaults": { "quorum": 20, "approval_threshold": "0.66", "weighting_scheme": "Sqrt" },
+      "proposals": [inheriting, overriding],
+    });
+    let manifest = ProposalsManifest::from_slice(&serde_json::to_vec(&manifest).unwrap()).unwrap();
+    assert_eq!(manifest.defaults.weighting, Some(WeightingScheme::Sqrt));
+
+    let [inherited, overridden] = &manifest.proposals[..] else { panic!("expected two proposals") };
+    assert_eq!(inherited.weighting, WeightingScheme::Sqrt);
+    assert_eq!(inherited.quorum, Some(20));
+    assert_eq!(inherited.approval_threshold, Some(Decimal::new(66, 2)));
+    assert_eq!(overridden.weighting, WeightingScheme::Linear);
+    assert_eq!(overridden.quorum, Some(3));
+    assert_eq!(overridden.approval_threshold, None);
+
+    let mut out_of_range = inherited.clone();
+    out_of_range.approval_threshold = Some(Decimal::ONE);
+    assert!(inherited.validate(90).is_ok());
+    assert!(out_of_range.validate(90).is_err());
+  }
+
+  #[test]
+  fn test_custom_vote_keywords() {
+    let mut proposal = proposal_with_window(1684562400000i64.into(), 1685253600000i64.into()).unwrap();
+    assert_eq!(proposal.vote_keywords(), VoteKeywords::from_key("MIP7"));
+
+    proposal.yes_keywords = vec!["approve mip7".to_string()];
+    let keywords = proposal.vote_keywords();
+    assert!(keywords.choice("approve MIP7").is_some());
+    assert!(keywords.choice("no mip7").is_some());
+    assert!(keywords.choice("mip7").is_none());
+    assert!(proposal.validate(90).is_ok());
+
+    proposal.no_keywords = vec!["Approve MIP7".to_string()];
+    assert!(proposal.validate(90).is_err());
+  }
+
+  #[test]
+  fn test_vote_keyword_precedence() {
+    let mut proposal = proposal_with_window(1684562400000i64.into(), 1685253600000i64.into()).unwrap();
+    proposal.title = "MIP 7: Fee Burn".to_string();
+    assert_eq!(proposal.vote_keyword(), "MIP7");
+
+    proposal.vote_keyword = Some(" burn-fees ".to_string());
+    assert_eq!(proposal.vote_keyword(), "burn-fees");
+    assert_eq!(proposal.vote_keywords(), VoteKeywords::from_key("burn-fees"));
+
+    // Blank values fall through to the next field.
+    proposal.vote_keyword = Some(" ".to_string());
+    proposal.key = String::new();
+    assert_eq!(proposal.vote_keyword(), "mip-7-fee-burn");
+    assert!(proposal.vote_keywords().choice("no mip-7-fee-burn").is_some());
+    assert!(proposal.validate(90).is_ok());
+
+    proposal.title = " -- ".to_string();
+    assert_eq!(proposal.vote_keyword(), "");
+    assert!(proposal.validate(90).unwrap_err().to_string().contains("no vote keyword"));
+
+    // A manifest entry may leave out `key`.
+    let mut value = serde_json::to_value(&proposal).unwrap();
+    value.as_object_mut().unwrap().remove("key");
+    value["vote_keyword"] = "mip7".into();
+    let parsed: Proposal = serde_json::from_value(value).unwrap();
+    assert_eq!((parsed.key.as_str(), parsed.vote_keyword().as_str()), ("", "mip7"));
+  }
+
+  #[test]
+  fn test_matches_filters() {
+    let mut proposal = proposal_with_window(1684562400000i64.into(), 1685253600000i64.into()).unwrap();
+    assert!(proposal.tags.is_empty());
+    proposal.tags = vec!["mip".to_string(), "zkapps".to_string()];
+    let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+    assert!(proposal.matches_filters(None, &[]));
+    assert!(proposal.matches_filters(Some("core"), &[]));
+    assert!(!proposal.matches_filters(Some("Networking"), &[]));
+    assert!(!proposal.matches_filters(Some("unknown"), &[]));
+    assert!(proposal.matches_filters(None, &tags(&["MIP"])));
+    assert!(!proposal.matches_filters(None, &tags(&["mip", "erc"])));
+    assert!(proposal.matches_filters(Some("Core"), &tags(&["mip", "zkapps"])));
+    assert!(!proposal.matches_filters(Some("ERC"), &tags(&["mip"])));
+  }
+}
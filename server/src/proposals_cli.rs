@@ -0,0 +1,26 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use crate::proposals_manifest_schema;
+
+#[derive(Clone, Parser)]
+#[command(name = "proposals")]
+pub struct ProposalsArgs {
+  #[command(subcommand)]
+  pub command: ProposalsCommand,
+}
+
+#[derive(Clone, Subcommand)]
+pub enum ProposalsCommand {
+  /// Print the JSON Schema of `proposals.json`.
+  Schema,
+}
+
+impl ProposalsArgs {
+  pub fn run(&self) -> Result<()> {
+    match &self.command {
+      ProposalsCommand::Schema => println!("{}", serde_json::to_string_pretty(&proposals_manifest_schema())?),
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,500 @@
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use bytes::Bytes;
+use clap::ValueEnum;
+use ed25519_dalek::{Signature, VerifyingKey};
+use reqwest::{
+  StatusCode,
+  header::{ETAG, HeaderMap, HeaderName, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT},
+};
+
+use crate::{Network, Proposal, ProposalsManifest, ReleaseStage, RetryPolicy, apply_manifest_defaults};
+
+static PROPOSALS_MANIFEST_GITHUB_URL: &str =
+  "https://raw.githubusercontent.com/o1-labs/mina-on-chain-voting/main/server/proposals/proposals.json";
+
+/// Fetches, filters and validates the proposals manifest. Kept on `Ocv` so
+/// the proposals can be reloaded without rebuilding it.
+#[derive(Clone)]
+pub struct ProposalsLoader {
+  pub network: Network,
+  /// Keep the proposals of every network instead of only `network`'s.
+  pub include_all_networks: bool,
+  pub release_stage: ReleaseStage,
+  /// Manifest URL, or comma-separated URLs of manifests to merge.
+  pub maybe_proposals_url: Option<String>,
+  pub max_proposal_window_days: i64,
+  /// Send `If-None-Match`/`If-Modified-Since` on reloads.
+  pub conditional_fetch: bool,
+  /// Where the last successfully fetched remote manifest is kept, to start
+  /// from when the remote is unreachable.
+  pub fallback_path: Option<PathBuf>,
+  pub user_agent: String,
+  /// When set, a fetched manifest is only accepted with a valid detached
+  /// Ed25519 signature from this key, published at `<url>.sig`.
+  pub signature_key: Option<VerifyingKey>,
+  /// How failed or throttled manifest fetches are retried before falling
+  /// back to the last-known-good copy.
+  pub retry_policy: RetryPolicy,
+  pub(crate) validators: Arc<Mutex<HashMap<String, ManifestValidators>>>,
+}
+
+/// Cache validators returned with the last successfully loaded manifest, per
+/// URL.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct ManifestValidators {
+  etag: Option<String>,
+  last_modified: Option<String>,
+}
+
+impl ManifestValidators {
+  fn from_headers(headers: &HeaderMap) -> Self {
+    let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    Self { etag: header(ETAG), last_modified: header(LAST_MODIFIED) }
+  }
+}
+
+impl ProposalsLoader {
+  /// Fetches the manifest, falling back to the last-known-good copy when the
+  /// fetch fails and one is available.
+  pub async fn load(&self) -> Result<Vec<Proposal>> {
+    let err = match self.fetch(false).await {
+      Ok(proposals) => {
+        return proposals.ok_or_else(|| anyhow!("Unconditional proposals manifest fetch returned no content"));
+      }
+      Err(err) => err,
+    };
+    let Some(fallback_path) = self.fallback_path.as_ref().filter(|path| path.exists()) else {
+      return Err(err);
+    };
+    tracing::error!(
+      "Failed to fetch the proposals manifest ({:#}), serving the last-known-good copy from {}",
+      err,
+      fallback_path.display()
+    );
+    let bytes = fs::read(fallback_path)?;
+    self.parse_manifest(&bytes).with_context(|| format!("Invalid fallback manifest {}", fallback_path.display()))
+  }
+
+  /// Like `load`, but returns `None` when the remote manifest hasn't changed
+  /// since the last successful load.
+  pub async fn load_if_changed(&self) -> Result<Option<Vec<Proposal>>> {
+    self.fetch(self.conditional_fetch).await
+  }
+
+  /// The manifest URLs to fetch and merge, in order.
+  pub fn proposals_urls(&self) -> Vec<&str> {
+    let urls = self.maybe_proposals_url.as_deref().unwrap_or(PROPOSALS_MANIFEST_GITHUB_URL);
+    urls.split(',').map(str::trim).filter(|url| !url.is_empty()).collect()
+  }
+
+  async fn fetch(&self, conditional: bool) -> Result<Option<Vec<Proposal>>> {
+    let (manifest_bytes, validators) = match self.release_stage {
+      ReleaseStage::Development | ReleaseStage::Staging => {
+        // Use the embedded manifest of the network for non-production env
+        let networks = match self.include_all_networks {
+          true => Network::value_variants(),
+          false => std::slice::from_ref(&self.network),
+        };
+        let manifests: Vec<(&str, Bytes)> = networks
+          .iter()
+          .map(|network| embedded_manifest(*network))
+          .map(|(name, bytes)| (name, Bytes::from_static(bytes)))
+          .collect();
+        (merge_manifests(&manifests)?, None)
+      }
+      _ => {
+        // Fetch from github for all other networks
+        let urls = self.proposals_urls();
+        let mut fetched = Vec::with_capacity(urls.len());
+        for url in &urls {
+          fetched.push(self.fetch_manifest(url, conditional).await?);
+        }
+        if fetched.iter().all(Option::is_none) {
+          return Ok(None);
+        }
+        // Unchanged manifests are still needed to merge with changed ones.
+        let mut manifests = Vec::with_capacity(urls.len());
+        for (url, manifest) in urls.iter().zip(fetched) {
+          let manifest = match manifest {
+            Some(manifest) => manifest,
+            None => self.fetch_manifest(url, false).await?.ok_or_else(|| anyhow!("No content from {}", url))?,
+          };
+          manifests.push((*url, manifest));
+        }
+        let validators: HashMap<String, ManifestValidators> =
+          manifests.iter().map(|(url, (_, validators))| (url.to_string(), validators.clone())).collect();
+        let manifests: Vec<(&str, Bytes)> = manifests.into_iter().map(|(url, (bytes, _))| (url, bytes)).collect();
+        (merge_manifests(&manifests)?, Some(validators))
+      }
+    };
+
+    let proposals = self.parse_manifest(manifest_bytes.as_ref())?;
+
+    // Only remember validators of a manifest that was actually accepted.
+    if let Some(validators) = validators {
+      *self.validators.lock().expect("validators lock poisoned") = validators;
+      if let Some(fallback_path) = &self.fallback_path {
+        if let Err(err) = write_fallback(fallback_path, &manifest_bytes) {
+          tracing::warn!("Could not persist proposals manifest to {}: {}", fallback_path.display(), err);
+        }
+      }
+    }
+    Ok(Some(proposals))
+  }
+
+  /// Fetches the manifest at `url`, or `None` if `conditional` and it hasn't
+  /// changed since the last successful load.
+  async fn fetch_manifest(&self, url: &str, conditional: bool) -> Result<Option<(Bytes, ManifestValidators)>> {
+    let validators = match conditional {
+      true => self.validators.lock().expect("validators lock poisoned").get(url).cloned().unwrap_or_default(),
+      false => ManifestValidators::default(),
+    };
+    let client = reqwest::Client::new();
+    let build = || {
+      let mut request = client.get(url).header(USER_AGENT, &self.user_agent);
+      if let Some(etag) = &validators.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+      }
+      if let Some(last_modified) = &validators.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+      }
+      request
+    };
+
+    let response = self.retry_policy.send_retrying_failures(build).await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+      tracing::info!("Proposals manifest at {} is unchanged", url);
+      return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let validators = ManifestValidators::from_headers(response.headers());
+    let manifest_bytes = response.bytes().await?;
+    if let Some(signature_key) = &self.signature_key {
+      self.verify_signature(signature_key, url, &manifest_bytes).await.inspect_err(|err| {
+        tracing::error!("Rejecting the proposals manifest from {}: {:#}", url, err);
+      })?;
+    }
+    Ok(Some((manifest_bytes, validators)))
+  }
+
+  /// Fetches the detached signature of the manifest at `url` and checks it
+  /// against `manifest_bytes`.
+  async fn verify_signature(&self, signature_key: &VerifyingKey, url: &str, manifest_bytes: &[u8]) -> Result<()> {
+    let signature_url = format!("{url}.sig");
+    let client = reqwest::Client::new();
+    let build = || client.get(&signature_url).header(USER_AGENT, &self.user_agent);
+    let response = self.retry_policy.send_retrying_failures(build).await?;
+    let signature = response
+      .error_for_status()
+      .with_context(|| format!("Could not fetch the manifest signature {signature_url}"))?
+      .text()
+      .await?;
+    verify_manifest_signature(signature_key, manifest_bytes, &signature)
+  }
+
+  fn parse_manifest(&self, bytes: &[u8]) -> Result<Vec<Proposal>> {
+    let manifest = ProposalsManifest::from_slice(bytes)?;
+    let mut filtered_by_network: Vec<Proposal> = manifest
+      .proposals
+      .into_iter()
+      .filter(|proposal| self.include_all_networks || proposal.network == self.network)
+      .collect();
+    for proposal in &mut filtered_by_network {
+      proposal.resolve_epoch_window()?;
+      proposal.validate(self.max_proposal_window_days)?;
+    }
+    Ok(filtered_by_network)
+  }
+}
+
+/// The manifest compiled in for `network`'s development and staging
+/// environments, and its file name.
+fn embedded_manifest(network: Network) -> (&'static str, &'static [u8]) {
+  match network {
+    Network::Mainnet => ("mainnet.json", include_bytes!("../proposals/mainnet.json")),
+    Network::Devnet => ("devnet.json", include_bytes!("../proposals/devnet.json")),
+  }
+}
+
+/// Merges the proposals of several manifests, in order, into one manifest.
+/// Each manifest's defaults are applied to its own proposals first. Fails if
+/// two manifests define the same proposal id. A single manifest is kept as
+/// is.
+fn merge_manifests(manifests: &[(&str, Bytes)]) -> Result<Bytes> {
+  if let [(_, bytes)] = manifests {
+    return Ok(bytes.clone());
+  }
+  let mut sources: HashMap<usize, &str> = HashMap::new();
+  let mut proposals = Vec::new();
+  for (url, bytes) in manifests {
+    let manifest: ProposalsManifest =
+      ProposalsManifest::from_slice(bytes).with_context(|| format!("Invalid proposals manifest from {url}"))?;
+    for proposal in &manifest.proposals {
+      if let Some(other) = sources.insert(proposal.id, url) {
+        bail!("Proposal id {} is defined in both {} and {}", proposal.id, other, url);
+      }
+    }
+    // Merged as JSON, so the merged manifest is parsed like any other.
+    let mut manifest: serde_json::Value = serde_json::from_slice(bytes)?;
+    apply_manifest_defaults(&mut manifest)?;
+    if let Some(manifest_proposals) = manifest.get("proposals").and_then(serde_json::Value::as_array) {
+      proposals.extend(manifest_proposals.iter().cloned());
+    }
+  }
+  Ok(serde_json::to_vec(&serde_json::json!({ "proposals": proposals }))?.into())
+}
+
+/// Writes the last-known-good manifest next to `path` first and renames it
+/// into place, so a crash mid-write can't leave a truncated fallback.
+fn write_fallback(path: &Path, bytes: &[u8]) -> Result<()> {
+  let tmp = path.with_extension("json.tmp");
+  fs::write(&tmp, bytes)?;
+  fs::rename(tmp, path)?;
+  Ok(())
+}
+
+/// Parses a base64-encoded Ed25519 public key.
+pub fn parse_signature_public_key(key: &str) -> Result<VerifyingKey> {
+  let bytes = BASE64.decode(key.trim()).context("Proposals signature public key is not valid base64")?;
+  let bytes: [u8; 32] =
+    bytes.try_into().map_err(|_| anyhow!("Proposals signature public key must be 32 bytes (Ed25519)"))?;
+  Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Checks `manifest` against a base64-encoded detached Ed25519 signature.
+fn verify_manifest_signature(key: &VerifyingKey, manifest: &[u8], signature: &str) -> Result<()> {
+  let signature = BASE64.decode(signature.trim()).context("Proposals manifest signature is not valid base64")?;
+  let signature = Signature::from_slice(&signature).context("Proposals manifest signature is malformed")?;
+  key.verify_strict(manifest, &signature).map_err(|_| anyhow!("Proposals manifest signature does not match"))
+}
+
+#[cfg(test)]
+mod tests {
+  use ed25519_dalek::{Signer, SigningKey};
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  use super::*;
+  use crate::DEFAULT_USER_AGENT;
+
+  #[test]
+  fn test_verify_manifest_signature() {
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BASE64.encode(signing_key.verifying_key().as_bytes());
+    let key = parse_signature_public_key(&format!("{public_key}\n")).unwrap();
+    let manifest = include_bytes!("../proposals/proposals.json");
+    let signature = BASE64.encode(signing_key.sign(manifest).to_bytes());
+
+    assert!(verify_manifest_signature(&key, manifest, &format!("{signature}\n")).is_ok());
+    assert!(verify_manifest_signature(&key, b"{\"proposals\": []}", &signature).is_err());
+    assert!(verify_manifest_signature(&key, manifest, "not base64!").is_err());
+    assert!(verify_manifest_signature(&key, manifest, &BASE64.encode([0; 10])).is_err());
+    assert!(parse_signature_public_key(&BASE64.encode([1; 16])).is_err());
+  }
+
+  #[test]
+  fn test_parse_manifest_include_all_networks() {
+    let manifest = include_bytes!("../proposals/proposals.json");
+    let all: ProposalsManifest = serde_json::from_slice(manifest).unwrap();
+    let mut loader = ProposalsLoader {
+      network: Network::Mainnet,
+      include_all_networks: false,
+      release_stage: ReleaseStage::Production,
+      maybe_proposals_url: None,
+      max_proposal_window_days: 90,
+      conditional_fetch: false,
+      fallback_path: None,
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+      signature_key: None,
+      retry_policy: RetryPolicy::default(),
+      validators: Arc::default(),
+    };
+    let mainnet = loader.parse_manifest(manifest).unwrap();
+    assert!(mainnet.len() < all.proposals.len());
+    assert_eq!(loader.proposals_urls(), [PROPOSALS_MANIFEST_GITHUB_URL]);
+    loader.maybe_proposals_url = Some("https://a/core.json, https://b/community.json,".to_string());
+    assert_eq!(loader.proposals_urls(), ["https://a/core.json", "https://b/community.json"]);
+
+    loader.include_all_networks = true;
+    let proposals = loader.parse_manifest(manifest).unwrap();
+    assert_eq!(proposals.len(), all.proposals.len());
+    assert!(proposals.iter().any(|proposal| proposal.network == Network::Devnet));
+  }
+
+  #[test]
+  fn test_merge_manifests() {
+    let embedded: serde_json::Value = serde_json::from_slice(include_bytes!("../proposals/proposals.json")).unwrap();
+    let proposals = embedded["proposals"].as_array().unwrap();
+    let (core, community) = proposals.split_at(proposals.len() / 2);
+    let manifest = |proposals: &[serde_json::Value]| {
+      Bytes::from(serde_json::to_vec(&serde_json::json!({ "proposals": proposals })).unwrap())
+    };
+
+    let merged = merge_manifests(&[("core.json", manifest(core)), ("community.json", manifest(community))]).unwrap();
+    let merged: ProposalsManifest = serde_json::from_slice(&merged).unwrap();
+    let ids: Vec<usize> = merged.proposals.iter().map(|proposal| proposal.id).collect();
+    let expected: Vec<usize> = proposals.iter().map(|proposal| proposal["id"].as_u64().unwrap() as usize).collect();
+    assert_eq!(ids, expected);
+
+    // Each manifest's defaults apply to its own proposals only.
+    let with_defaults = serde_json::json!({ "defaults": { "quorum": 5 }, "proposals": community });
+    let with_defaults = Bytes::from(serde_json::to_vec(&with_defaults).unwrap());
+    let merged = merge_manifests(&[("core.json", manifest(core)), ("community.json", with_defaults)]).unwrap();
+    let merged = ProposalsManifest::from_slice(&merged).unwrap();
+    let (merged_core, merged_community) = merged.proposals.split_at(core.len());
+    assert!(merged_core.iter().all(|proposal| proposal.quorum.is_none()));
+    assert!(merged_community.iter().all(|proposal| proposal.quorum == Some(5)));
+
+    let conflicting = [("core.json", manifest(core)), ("community.json", manifest(&proposals[.. 1]))];
+    let err = merge_manifests(&conflicting).unwrap_err();
+    let id = &proposals[0]["id"];
+    assert_eq!(err.to_string(), format!("Proposal id {id} is defined in both core.json and community.json"));
+  }
+
+  #[tokio::test]
+  async fn test_load_embedded_manifest_per_network() {
+    let mut loader = ProposalsLoader {
+      network: Network::Devnet,
+      include_all_networks: false,
+      release_stage: ReleaseStage::Development,
+      maybe_proposals_url: None,
+      max_proposal_window_days: 90,
+      conditional_fetch: false,
+      fallback_path: None,
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+      signature_key: None,
+      retry_policy: RetryPolicy::default(),
+      validators: Arc::default(),
+    };
+    let devnet = loader.load().await.unwrap();
+    assert!(!devnet.is_empty());
+    assert!(devnet.iter().all(|proposal| proposal.network == Network::Devnet));
+
+    loader.network = Network::Mainnet;
+    loader.release_stage = ReleaseStage::Staging;
+    let mainnet = loader.load().await.unwrap();
+    assert!(!mainnet.is_empty());
+    assert!(mainnet.iter().all(|proposal| proposal.network == Network::Mainnet));
+
+    loader.include_all_networks = true;
+    assert_eq!(loader.load().await.unwrap().len(), devnet.len() + mainnet.len());
+  }
+
+  #[test]
+  fn test_embedded_manifests_match_proposals() {
+    // The per-network manifests split proposals.json; edit all three together.
+    let proposals = |bytes: &[u8]| -> Vec<serde_json::Value> {
+      let manifest: serde_json::Value = serde_json::from_slice(bytes).unwrap();
+      manifest["proposals"].as_array().unwrap().clone()
+    };
+    let mut split = Vec::new();
+    for network in [Network::Mainnet, Network::Devnet] {
+      let (_, bytes) = embedded_manifest(network);
+      let network_proposals = proposals(bytes);
+      assert!(network_proposals.iter().all(|proposal| proposal["network"] == network.to_string()));
+      split.extend(network_proposals);
+    }
+    let mut all = proposals(include_bytes!("../proposals/proposals.json"));
+    let by_id = |proposal: &serde_json::Value| proposal["id"].as_u64();
+    split.sort_by_key(by_id);
+    all.sort_by_key(by_id);
+    assert_eq!(split, all);
+  }
+
+  #[tokio::test]
+  async fn test_load_falls_back_to_last_known_good() {
+    let fallback_path = std::env::temp_dir().join(format!("ocv-proposals-fallback-{}.json", std::process::id()));
+    let loader = ProposalsLoader {
+      network: Network::Mainnet,
+      include_all_networks: false,
+      release_stage: ReleaseStage::Production,
+      // Nothing listens on the discard port, so the fetch fails.
+      maybe_proposals_url: Some("http://127.0.0.1:9/proposals.json".to_string()),
+      max_proposal_window_days: 90,
+      conditional_fetch: false,
+      fallback_path: Some(fallback_path.clone()),
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+      signature_key: None,
+      retry_policy: RetryPolicy { max_attempts: 1, ..Default::default() },
+      validators: Arc::default(),
+    };
+    assert!(loader.load().await.is_err());
+
+    fs::write(&fallback_path, include_bytes!("../proposals/proposals.json")).unwrap();
+    let proposals = loader.load().await;
+    fs::remove_file(&fallback_path).unwrap();
+    let proposals = proposals.unwrap();
+    assert!(!proposals.is_empty());
+    assert!(proposals.iter().all(|proposal| proposal.network == Network::Mainnet));
+  }
+
+  #[test]
+  fn test_write_fallback_replaces_atomically() {
+    let dir = std::env::temp_dir().join(format!("ocv-proposals-write-fallback-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("proposals.last-known-good.json");
+    fs::write(&path, "old").unwrap();
+
+    write_fallback(&path, b"new").unwrap();
+    let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(contents, "new");
+    // The temporary file was renamed into place.
+    assert_eq!(entries, vec![std::ffi::OsString::from("proposals.last-known-good.json")]);
+  }
+
+  /// Answers one connection with each of `responses` in turn, returning the
+  /// URL to request.
+  async fn serve_responses(responses: Vec<String>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/proposals.json", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      for response in responses {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = [0; 4096];
+        let _ = socket.read(&mut request).await;
+        socket.write_all(response.as_bytes()).await.unwrap();
+        let _ = socket.shutdown().await;
+      }
+    });
+    url
+  }
+
+  #[tokio::test]
+  async fn test_load_retries_failed_fetches() {
+    let unavailable =
+      "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    let manifest = std::str::from_utf8(include_bytes!("../proposals/proposals.json")).unwrap();
+    let ok = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{manifest}", manifest.len());
+    let url = serve_responses(vec![unavailable.to_string(), unavailable.to_string(), ok]).await;
+    let mut loader = ProposalsLoader {
+      network: Network::Mainnet,
+      include_all_networks: false,
+      release_stage: ReleaseStage::Production,
+      maybe_proposals_url: Some(url),
+      max_proposal_window_days: 90,
+      conditional_fetch: false,
+      fallback_path: None,
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+      signature_key: None,
+      retry_policy: RetryPolicy { max_attempts: 3, ..Default::default() },
+      validators: Arc::default(),
+    };
+    assert!(!loader.load().await.unwrap().is_empty());
+
+    // Out of retries, the last failure is returned.
+    loader.maybe_proposals_url = Some(serve_responses(vec![unavailable.to_string(); 3]).await);
+    loader.retry_policy.max_attempts = 2;
+    let err = loader.load().await.unwrap_err();
+    let status = err.downcast_ref::<reqwest::Error>().and_then(reqwest::Error::status);
+    assert_eq!(status, Some(StatusCode::SERVICE_UNAVAILABLE));
+  }
+}
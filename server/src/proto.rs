@@ -0,0 +1,3 @@
+// Protocol Buffers messages generated from `proto/tally_result.proto`.
+
+include!(concat!(env!("OUT_DIR"), "/ocv.v1.rs"));
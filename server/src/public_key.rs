@@ -0,0 +1,112 @@
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// Leading bytes of a decoded Mina public key: the base58check version byte
+/// followed by the key and point versions.
+const PUBLIC_KEY_PREFIX: [u8; 3] = [0xcb, 0x01, 0x01];
+/// Length of a decoded public key without its checksum.
+const PUBLIC_KEY_DECODED_LEN: usize = 36;
+
+/// A Mina account address (`B62q...`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PublicKey(String);
+
+impl PublicKey {
+  /// Parses a base58check-encoded Mina public key.
+  pub fn parse(value: &str) -> Result<PublicKey> {
+    let decoded = bs58::decode(value)
+      .with_check(None)
+      .into_vec()
+      .with_context(|| format!("invalid public key {value} - bs58check"))?;
+    if decoded.len() != PUBLIC_KEY_DECODED_LEN || !decoded.starts_with(&PUBLIC_KEY_PREFIX) {
+      bail!("invalid public key {value} - not a Mina address");
+    }
+    Ok(PublicKey(value.to_string()))
+  }
+
+  /// Wraps a key from a trusted source, such as the archive database, without
+  /// validating it.
+  pub fn new_unchecked(value: impl Into<String>) -> PublicKey {
+    PublicKey(value.into())
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl fmt::Display for PublicKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl TryFrom<String> for PublicKey {
+  type Error = anyhow::Error;
+
+  fn try_from(value: String) -> Result<Self> {
+    PublicKey::parse(&value)
+  }
+}
+
+impl From<PublicKey> for String {
+  fn from(value: PublicKey) -> Self {
+    value.0
+  }
+}
+
+impl PartialEq<str> for PublicKey {
+  fn eq(&self, other: &str) -> bool {
+    self.0 == other
+  }
+}
+
+impl PartialEq<&str> for PublicKey {
+  fn eq(&self, other: &&str) -> bool {
+    self.0 == *other
+  }
+}
+
+impl PartialEq<String> for PublicKey {
+  fn eq(&self, other: &String) -> bool {
+    &self.0 == other
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_valid_keys() {
+    for key in [
+      "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM",
+      "B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy",
+    ] {
+      assert_eq!(PublicKey::parse(key).unwrap(), key);
+    }
+  }
+
+  #[test]
+  fn test_parse_corrupt_keys() {
+    // Flipped character - checksum mismatch.
+    assert!(PublicKey::parse("B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSN").is_err());
+    // Truncated.
+    assert!(PublicKey::parse("B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45").is_err());
+    // Not base58.
+    assert!(PublicKey::parse("B62q0000").is_err());
+    assert!(PublicKey::parse("").is_err());
+    // Valid base58check, but not a Mina address (a memo).
+    assert!(PublicKey::parse("E4YjFkHVUXbEAkQcUrAEcS1fqvbncnn9Tuz2Jtb1Uu79zY9UAJRpd").is_err());
+  }
+
+  #[test]
+  fn test_deserialize_validates() {
+    let key: PublicKey = serde_json::from_str("\"B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM\"").unwrap();
+    assert_eq!(serde_json::to_string(&key).unwrap(), "\"B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM\"");
+    assert!(serde_json::from_str::<PublicKey>("\"A\"").is_err());
+  }
+}
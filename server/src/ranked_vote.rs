@@ -4,14 +4,14 @@ use std::{
   ops::{Add, AddAssign},
 };
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use tracing::log::{debug, error, info};
 
 use crate::{
   Ballot, BallotChoice, Builder, Candidate, DuplicateCandidateMode, ElectionResult, ElectionStats,
   EliminationAlgorithm, EliminationStats, MaxSkippedRank, OverVoteRule, RoundStats, TieBreakMode, VoteRules,
-  VotingErrors, VotingResult, Wrapper, archive::FetchTransactionResult, vote::BlockStatus,
+  VotingErrors, VotingResult, Wrapper, archive::FetchTransactionResult, decode_memo_text, vote::BlockStatus,
 };
 
 // **** Private structures ****
@@ -87,14 +87,7 @@ impl RankedVote {
   }
 
   pub(crate) fn decode_memo(&self) -> Result<String> {
-    let decoded =
-      bs58::decode(&self.memo).into_vec().with_context(|| format!("failed to decode memo {} - bs58", &self.memo))?;
-
-    let value = &decoded[3 .. decoded[2] as usize + 3];
-
-    let result =
-      String::from_utf8(value.to_vec()).with_context(|| format!("failed to decode memo {} - from_utf8", &self.memo))?;
-    Ok(result)
+    decode_memo_text(&self.memo)
   }
 
   pub fn parse_decoded_ranked_votes_memo(&mut self, key: &str) -> Option<(String, Vec<String>)> {
@@ -163,7 +156,12 @@ impl From<FetchTransactionResult> for RankedVote {
   }
 }
 impl Wrapper<Vec<RankedVote>> {
-  pub fn process_ranked_vote(self, id: usize, tip: i64) -> Wrapper<BTreeMap<String, RankedVote>> {
+  pub fn process_ranked_vote(
+    self,
+    id: usize,
+    tip: i64,
+    confirmation_depth: i64,
+  ) -> Wrapper<BTreeMap<String, RankedVote>> {
     let mut map = BTreeMap::new();
     let id_str = id.to_string();
 
@@ -174,7 +172,7 @@ impl Wrapper<Vec<RankedVote>> {
         vote.update_memo(format!("Votes: {:?}", proposal_ids));
         vote.proposals = proposal_ids;
         // Update vote status if conditions are met
-        if tip - vote.height >= 10 {
+        if tip - vote.height >= confirmation_depth {
           vote.update_status(BlockStatus::Canonical);
         }
 
@@ -1184,7 +1182,7 @@ mod tests {
   #[test]
   fn test_process_ranked_votes() {
     let votes = get_test_votes();
-    let binding = Wrapper(votes).process_ranked_vote(1, 129);
+    let binding = Wrapper(votes).process_ranked_vote(1, 129, 10);
     let processed: Vec<RankedVote> = binding.0.values().cloned().collect();
 
     assert_eq!(processed.len(), 9);
@@ -0,0 +1,36 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+/// Directory under `ledger_storage_path` holding finalized proposal results.
+const RESULTS_DIR: &str = "results";
+
+/// Path of the archived result of proposal `id`.
+pub fn archived_result_path(dir: &Path, id: usize) -> PathBuf {
+  dir.join(RESULTS_DIR).join(format!("{id}.json"))
+}
+
+/// Reads the archived result of proposal `id`, or `None` if it hasn't been
+/// archived.
+pub fn read_archived_result<T: DeserializeOwned>(dir: &Path, id: usize) -> Result<Option<T>> {
+  let path = archived_result_path(dir, id);
+  if !path.exists() {
+    return Ok(None);
+  }
+  Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+}
+
+/// Writes the serialized result of proposal `id`. The file is written to a
+/// temporary path first so readers never see a partial result.
+pub fn write_archived_result(dir: &Path, id: usize, bytes: &[u8]) -> Result<()> {
+  let path = archived_result_path(dir, id);
+  fs::create_dir_all(dir.join(RESULTS_DIR))?;
+  let tmp = path.with_extension("json.tmp");
+  fs::write(&tmp, bytes)?;
+  fs::rename(tmp, path)?;
+  Ok(())
+}
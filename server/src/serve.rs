@@ -1,18 +1,57 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+  collections::{BTreeSet, HashMap},
+  convert::Infallible,
+  fmt::Write as _,
+  ops::Deref,
+  sync::Arc,
+  time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use axum::{
-  Json, Router, debug_handler,
-  extract::{Path, Query, State},
-  response::IntoResponse,
-  routing::get,
+  Json, Router, async_trait, debug_handler,
+  body::Body,
+  extract::{FromRequestParts, Path, Query, Request, State},
+  http::{
+    HeaderMap, StatusCode,
+    header::{ACCEPT, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER, VARY},
+    request::Parts,
+  },
+  middleware::{Next, from_fn_with_state},
+  response::{IntoResponse, Response},
+  routing::{delete, get, post},
   serve as axum_serve,
 };
-use clap::Parser;
-use tokio::net::TcpListener;
-use tower_http::cors::CorsLayer;
+use clap::{ArgAction, Parser};
+use prost::Message;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{net::TcpListener, sync::Semaphore};
+use tower_http::{compression::CompressionLayer, cors::CorsLayer};
+
+use crate::{
+  GetProposalRawVotesResponse, GetProposalsPageResponse, MAX_BULK_IDS, Ocv, OcvConfig, ProposalPhase, RawVote,
+  RequestDeadline, TimeseriesBucket, Wrapper, decode_cursor, decode_numbered_cursor, encode_cursor, error_response,
+  page_after, render_circuit_state, render_queued_archive_queries, shutdown_signal,
+};
+
+const NONVOTERS_DEFAULT_LIMIT: usize = 100;
+const NONVOTERS_MAX_LIMIT: usize = 1000;
+const RAW_VOTES_DEFAULT_LIMIT: usize = 1000;
+const RAW_VOTES_MAX_LIMIT: usize = 10_000;
+const PROPOSALS_DEFAULT_LIMIT: usize = 100;
+const PROPOSALS_MAX_LIMIT: usize = 1000;
+const VOTES_CURSOR: &str = "votes";
+const PROPOSALS_CURSOR: &str = "proposals";
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// `Retry-After` sent when every tally slot is taken.
+const CONCURRENCY_RETRY_AFTER_SECS: u64 = 5;
 
-use crate::{Ocv, OcvConfig, Wrapper, shutdown_signal};
+/// Offset, limit and cursor position of a raw votes page.
+type RawVotesPage = (usize, Option<usize>, Option<(i64, String)>);
 
 #[derive(Clone, Parser)]
 pub struct ServeArgs {
@@ -22,6 +61,26 @@ pub struct ServeArgs {
   /// API Port.
   #[clap(long, env, default_value = "8080")]
   pub port: u16,
+  /// Compress responses (gzip/br) for clients sending `Accept-Encoding`.
+  #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+  pub response_compression: bool,
+  /// Bearer token required by the `/admin` endpoints. They are disabled when
+  /// neither this nor `admin_tokens` is set.
+  #[clap(long, env)]
+  pub admin_token: Option<String>,
+  /// Additional admin tokens as comma-separated `label:token` pairs. Any of
+  /// them is accepted, which allows rotating tokens without downtime; the
+  /// label of the token used is logged.
+  #[clap(long, env, value_delimiter = ',')]
+  pub admin_tokens: Vec<AdminToken>,
+  /// Path prefix all routes are served under, e.g. `/voting` when running
+  /// behind a reverse proxy. Empty serves from the root.
+  #[clap(long, env, default_value = "")]
+  pub base_path: String,
+  /// Tally requests (results, timeseries, non-voters, MEF) handled at once;
+  /// further ones get a 503.
+  #[clap(long, env, default_value = "16", value_parser = clap::value_parser!(u32).range(1 ..))]
+  pub max_concurrent_requests: u32,
   /// OCV Args.
   #[command(flatten)]
   pub config: OcvConfig,
@@ -35,33 +94,283 @@ impl ServeArgs {
     tracing::info!("Starting server at http://{}.", listener.local_addr()?);
 
     let ocv = self.config.to_ocv().await?;
-    let router = Router::new()
-      .route("/api/info", get(get_info))
-      .route("/api/proposals", get(get_proposals))
-      .route("/api/proposal/:id", get(get_proposal))
+    if self.config.startup_selftest {
+      ocv.startup_selftest().await.context("Startup self-test failed")?;
+    }
+    if self.config.archive_closed_proposals {
+      spawn_results_archiver(ocv.clone(), Duration::from_secs(self.config.archive_interval_secs));
+    }
+    if let Some(interval_secs) = self.config.cache_prune_interval_secs {
+      spawn_ledger_cache_pruner(
+        ocv.clone(),
+        Duration::from_secs(interval_secs),
+        self.config.cache_max_bytes,
+        self.config.cache_max_age_secs.map(Duration::from_secs),
+      );
+    }
+    let router = self.router(ocv)?;
+    axum_serve(listener, router).with_graceful_shutdown(shutdown_signal()).await?;
+    Ok(())
+  }
+
+  /// `admin_tokens`, plus `admin_token` labeled "default".
+  fn all_admin_tokens(&self) -> Vec<AdminToken> {
+    let default =
+      self.admin_token.iter().map(|token| AdminToken { label: "default".to_string(), token: token.clone() });
+    default.chain(self.admin_tokens.iter().cloned()).collect()
+  }
+
+  pub fn router(&self, ocv: Ocv) -> Result<Router> {
+    let admin_router = Router::new()
+      .route("/admin/cache/status", get(get_cache_status))
+      .route("/admin/cache", delete(delete_cache))
+      .route("/admin/proposals/:id/invalidate", post(post_invalidate_tally))
+      .route_layer(from_fn_with_state(Arc::new(self.all_admin_tokens()), require_admin_token));
+    let tally_router = Router::new()
       .route("/api/proposal/:id/results", get(get_proposal_result))
+      .route("/api/results", get(get_results))
+      .route("/api/proposals/:id/timeseries", get(get_proposal_timeseries))
+      .route("/api/proposals/:id/nonvoters", get(get_proposal_nonvoters))
+      .route("/api/proposals/:id/raw-votes", get(get_proposal_raw_votes))
+      .route("/api/proposals/:id/inputs", get(get_proposal_inputs))
       .route(
         "/api/mef_proposal_consideration/:round_id/:proposal_id/:start_time/:end_time",
         get(get_proposal_consideration),
       )
       .route("/api/mef_ranked_vote/:round_id/:start_time/:end_time", get(run_ranked_vote))
-      .layer(CorsLayer::permissive())
-      .with_state(Arc::new(ocv));
-    axum_serve(listener, router).with_graceful_shutdown(shutdown_signal()).await?;
-    Ok(())
+      .route_layer(from_fn_with_state(
+        Arc::new(Semaphore::new(self.max_concurrent_requests as usize)),
+        limit_concurrency,
+      ));
+    let router = Router::new()
+      .route("/version", get(get_version))
+      .route("/metrics", get(get_metrics))
+      .route("/api/info", get(get_info))
+      .route("/api/proposals", get(get_proposals))
+      .route("/api/proposal/:id", get(get_proposal))
+      .route("/api/decode-memo", get(get_decode_memo))
+      .merge(tally_router)
+      .merge(admin_router)
+      .layer(CorsLayer::permissive());
+    let router = if self.response_compression { router.layer(CompressionLayer::new()) } else { router };
+    let router = match normalize_base_path(&self.base_path)? {
+      Some(base_path) => {
+        tracing::info!("Serving routes under {}", base_path);
+        Router::new().nest(&base_path, router)
+      }
+      None => router,
+    };
+    Ok(router.with_state(Arc::new(ocv)))
+  }
+}
+
+/// Runs `Ocv::archive_closed_proposals` every `interval` in the background.
+fn spawn_results_archiver(ocv: Ocv, interval: Duration) {
+  tokio::spawn(async move {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+      ticker.tick().await;
+      match ocv.archive_closed_proposals().await {
+        Ok(archived) if !archived.is_empty() => tracing::info!("Archived results of proposals {:?}", archived),
+        Ok(_) => {}
+        Err(err) => tracing::error!("Archiving closed proposal results failed: {}", err),
+      }
+    }
+  });
+}
+
+/// Runs `Ocv::prune_ledger_cache` every `interval` in the background.
+fn spawn_ledger_cache_pruner(ocv: Ocv, interval: Duration, max_bytes: Option<u64>, max_age: Option<Duration>) {
+  if max_bytes.is_none() && max_age.is_none() {
+    tracing::warn!("CACHE_PRUNE_INTERVAL_SECS is set without CACHE_MAX_BYTES or CACHE_MAX_AGE_SECS; not pruning");
+    return;
+  }
+  tokio::spawn(async move {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+      ticker.tick().await;
+      if let Err(err) = ocv.prune_ledger_cache(max_bytes, max_age).await {
+        tracing::error!("Pruning the ledger cache failed: {}", err);
+      }
+    }
+  });
+}
+
+/// Turns `voting`, `/voting/` etc. into `/voting`; `None` for the root.
+fn normalize_base_path(base_path: &str) -> Result<Option<String>> {
+  let trimmed = base_path.trim().trim_matches('/');
+  if trimmed.is_empty() {
+    return Ok(None);
+  }
+  if trimmed.split('/').any(|segment| segment.is_empty() || segment.starts_with([':', '*'])) {
+    bail!("Invalid base path '{}'", base_path);
+  }
+  Ok(Some(format!("/{trimmed}")))
+}
+
+/// An admin token and the label it is logged under.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdminToken {
+  pub label: String,
+  pub token: String,
+}
+
+impl std::str::FromStr for AdminToken {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.trim().split_once(':') {
+      Some((label, token)) if !label.is_empty() && !token.is_empty() => {
+        Ok(AdminToken { label: label.to_string(), token: token.to_string() })
+      }
+      _ => bail!("Invalid admin token '{s}'; expected 'label:token'"),
+    }
+  }
+}
+
+/// Rejects requests without `Authorization: Bearer <token>` for one of the
+/// admin tokens, and all requests when no admin token is configured.
+async fn require_admin_token(
+  State(admin_tokens): State<Arc<Vec<AdminToken>>>,
+  request: Request,
+  next: Next,
+) -> Response {
+  if admin_tokens.is_empty() {
+    return (StatusCode::FORBIDDEN, "Admin endpoints are disabled").into_response();
+  }
+  let provided = request.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok());
+  let provided = provided.and_then(|value| value.strip_prefix("Bearer "));
+  let Some(provided) = provided else {
+    return StatusCode::UNAUTHORIZED.into_response();
+  };
+  let Some(admin_token) = admin_tokens.iter().find(|admin_token| tokens_match(&admin_token.token, provided)) else {
+    return StatusCode::UNAUTHORIZED.into_response();
+  };
+  tracing::info!("Admin request {} {} authorized by token '{}'", request.method(), request.uri(), admin_token.label);
+  next.run(request).await
+}
+
+/// Compares two tokens in time independent of where they differ, by
+/// comparing every byte of their digests, so responses don't leak a prefix.
+fn tokens_match(expected: &str, provided: &str) -> bool {
+  let (expected, provided) = (Sha256::digest(expected), Sha256::digest(provided));
+  expected.iter().zip(provided.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Build metadata captured at compile time by `build.rs`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionResponse {
+  pub version: &'static str,
+  pub git_hash: &'static str,
+  /// Unix timestamp in seconds.
+  pub build_timestamp: i64,
+}
+
+impl VersionResponse {
+  pub fn current() -> Self {
+    VersionResponse {
+      version: env!("CARGO_PKG_VERSION"),
+      git_hash: env!("OCV_GIT_HASH"),
+      build_timestamp: env!("OCV_BUILD_TIMESTAMP").parse().unwrap_or_default(),
+    }
   }
 }
 
+#[debug_handler]
+async fn get_version() -> impl IntoResponse {
+  Json(VersionResponse::current())
+}
+
+/// Rejects the request with a 503 while all permits of `semaphore` are held
+/// by in-flight requests.
+async fn limit_concurrency(State(semaphore): State<Arc<Semaphore>>, request: Request, next: Next) -> Response {
+  let Ok(_permit) = semaphore.try_acquire() else {
+    return (
+      StatusCode::SERVICE_UNAVAILABLE,
+      [(RETRY_AFTER, CONCURRENCY_RETRY_AFTER_SECS.to_string())],
+      "Too many concurrent tally requests",
+    )
+      .into_response();
+  };
+  next.run(request).await
+}
+
+/// The server's `Ocv`, scoped to one tally request: its storage downloads
+/// are abandoned past `Ocv::request_timeout`, or once the handler is dropped
+/// because the client disconnected.
+struct RequestOcv {
+  ocv: Ocv,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<Ocv>> for RequestOcv {
+  type Rejection = Infallible;
+
+  async fn from_request_parts(_parts: &mut Parts, ocv: &Arc<Ocv>) -> Result<Self, Infallible> {
+    let deadline = RequestDeadline::new(ocv.request_timeout);
+    Ok(RequestOcv { ocv: ocv.with_deadline(deadline) })
+  }
+}
+
+impl Deref for RequestOcv {
+  type Target = Ocv;
+
+  fn deref(&self) -> &Ocv {
+    &self.ocv
+  }
+}
+
+/// Prometheus gauges of the latest tally of each proposal.
+async fn get_metrics(ctx: State<Arc<Ocv>>) -> impl IntoResponse {
+  let mut body = ctx.metrics.render();
+  body.push_str(&render_queued_archive_queries(ctx.archive_queries.queued()));
+  if let Some(state) = ctx.storage_provider.circuit_state() {
+    body.push_str(&render_circuit_state(state));
+  }
+  ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
 #[debug_handler]
 async fn get_info(ctx: State<Arc<Ocv>>) -> impl IntoResponse {
   tracing::info!("get_info");
   Wrapper(ctx.info().await)
 }
 
+/// Lists the proposals, all at once unless `cursor` or `limit` asks for a
+/// page. Pages are ordered by proposal id.
 #[debug_handler]
-async fn get_proposals(ctx: State<Arc<Ocv>>) -> impl IntoResponse {
-  tracing::info!("get_proposals");
-  Json(ctx.proposals.to_owned())
+async fn get_proposals(ctx: State<Arc<Ocv>>, Query(params): Query<Vec<(String, String)>>) -> Response {
+  tracing::info!("get_proposals {:?}", params);
+  let param = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+  let category = param("category");
+  let tags: Vec<String> = params.iter().filter(|(name, _)| name == "tag").map(|(_, value)| value.clone()).collect();
+  let phase = param("status").map(|value| value.parse::<ProposalPhase>());
+  let proposals = match phase.transpose() {
+    Ok(None) => Ok(ctx.proposals_filtered(category, &tags)),
+    Ok(Some(phase)) => ctx.proposals_in_phase(phase, ctx.now()).map(|proposals| {
+      proposals.into_iter().filter(|proposal| proposal.matches_filters(category, &tags)).collect::<Vec<_>>()
+    }),
+    Err(err) => Err(err),
+  };
+  if param("cursor").is_none() && param("limit").is_none() {
+    return Wrapper(proposals).into_response();
+  }
+  let parse_params = || -> Result<(Option<usize>, usize)> {
+    let parse_cursor = |cursor: &str| -> Result<usize> { Ok(decode_cursor(PROPOSALS_CURSOR, cursor)?.parse()?) };
+    let after = param("cursor").map(parse_cursor).transpose()?;
+    let limit = param("limit").map_or(Ok(PROPOSALS_DEFAULT_LIMIT), |limit| limit.parse())?;
+    Ok((after, limit.min(PROPOSALS_MAX_LIMIT)))
+  };
+  let Ok((after, limit)) = parse_params() else {
+    return (StatusCode::BAD_REQUEST, "Invalid 'cursor' or 'limit' parameter").into_response();
+  };
+  Wrapper(proposals.map(|mut proposals| {
+    proposals.sort_by_key(|proposal| proposal.id);
+    let (proposals, next) = page_after(proposals, |proposal| proposal.id, after.as_ref(), limit);
+    let next_cursor = next.map(|id| encode_cursor(PROPOSALS_CURSOR, &id.to_string()));
+    GetProposalsPageResponse { proposals, next_cursor }
+  }))
+  .into_response()
 }
 
 #[debug_handler]
@@ -71,14 +380,231 @@ async fn get_proposal(ctx: State<Arc<Ocv>>, Path(id): Path<usize>) -> impl IntoR
 }
 
 #[debug_handler]
-async fn get_proposal_result(ctx: State<Arc<Ocv>>, Path(id): Path<usize>) -> impl IntoResponse {
+async fn get_decode_memo(ctx: State<Arc<Ocv>>, Query(params): Query<HashMap<String, String>>) -> Response {
+  tracing::info!("get_decode_memo {:?}", params);
+  let Some(memo) = params.get("memo") else {
+    return (StatusCode::BAD_REQUEST, "Missing 'memo' parameter").into_response();
+  };
+  match params.get("proposal").map(|id| id.parse::<usize>()) {
+    Some(Ok(id)) => Wrapper(ctx.check_memo(id, memo)).into_response(),
+    Some(Err(_)) | None => (StatusCode::BAD_REQUEST, "Missing or invalid 'proposal' parameter").into_response(),
+  }
+}
+
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_proposal_result(
+  ctx: RequestOcv,
+  Path(id): Path<usize>,
+  Query(params): Query<HashMap<String, String>>,
+  headers: HeaderMap,
+) -> Response {
   tracing::info!("get_proposal_result {}", id);
-  Wrapper(ctx.proposal_result(id).await)
+  let ledger_hash = params.get("ledger_hash").cloned();
+  let Ok(strict) = params.get("strict").map_or(Ok(false), |strict| strict.parse::<bool>()) else {
+    return (StatusCode::BAD_REQUEST, "Invalid 'strict' parameter").into_response();
+  };
+  let format = params.get("format").map(String::as_str).or_else(|| accepts_protobuf(&headers).then_some("proto"));
+  let format = match format {
+    None | Some("native") => "native",
+    Some(format @ ("mef" | "proto")) => format,
+    Some(format) => return (StatusCode::BAD_REQUEST, format!("Unknown result format '{format}'")).into_response(),
+  };
+  let result = match ctx.tally(id, ledger_hash, strict).await {
+    Ok(result) => result,
+    Err(err) => return error_response(err),
+  };
+
+  let (content_type, body) = match format {
+    "mef" => ("application/json", serde_json::to_vec(&result.to_mef())),
+    "proto" => (PROTOBUF_CONTENT_TYPE, Ok(result.to_proto().encode_to_vec())),
+    _ => ("application/json", serde_json::to_vec(&result)),
+  };
+  let body = match body {
+    Ok(body) => body,
+    Err(err) => return error_response(err.into()),
+  };
+
+  // Finalized results only change if the manifest is corrected, so caches
+  // may keep them for a while; others must be revalidated, which the tag
+  // makes cheap.
+  let etag = body_etag(&body);
+  let cache_control = match result.is_finalized() {
+    true => format!("public, max-age={}", ctx.final_results_max_age.as_secs()),
+    false => "no-cache".to_string(),
+  };
+  let cache_headers = [(ETAG, etag.clone()), (CACHE_CONTROL, cache_control), (VARY, ACCEPT.as_str().to_string())];
+  if if_none_match(&headers, &etag) {
+    return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+  }
+  (cache_headers, [(CONTENT_TYPE, content_type)], body).into_response()
 }
 
-#[debug_handler]
+/// Weak entity tag of a response body: a digest of its bytes, so it changes
+/// with anything the body shows, including the proposal itself.
+fn body_etag(body: &[u8]) -> String {
+  let digest = Sha256::digest(body).iter().take(16).fold(String::new(), |mut digest, byte| {
+    let _ = write!(digest, "{byte:02x}");
+    digest
+  });
+  format!("W/\"{digest}\"")
+}
+
+/// Whether `If-None-Match` lists `etag`, or is `*`. Tags are compared weakly,
+/// ignoring a `W/` prefix.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+  let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+  headers
+    .get_all(IF_NONE_MATCH)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Whether the `Accept` header asks for Protocol Buffers.
+fn accepts_protobuf(headers: &HeaderMap) -> bool {
+  accepts(headers, PROTOBUF_CONTENT_TYPE)
+}
+
+/// Whether the `Accept` header lists `content_type`, whatever its weight.
+fn accepts(headers: &HeaderMap, content_type: &str) -> bool {
+  headers.get_all(ACCEPT).iter().filter_map(|value| value.to_str().ok()).any(|value| {
+    value.split(',').any(|media_type| media_type.split(';').next().unwrap_or_default().trim() == content_type)
+  })
+}
+
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_results(ctx: RequestOcv, Query(params): Query<Vec<(String, String)>>) -> Response {
+  tracing::info!("get_results {:?}", params);
+  let ids: Result<Vec<usize>, _> =
+    params.iter().filter(|(name, _)| name == "id").map(|(_, value)| value.parse::<usize>()).collect();
+  let status = params.iter().find(|(name, _)| name == "status").map(|(_, value)| value.parse::<ProposalPhase>());
+  let ids: Result<Vec<usize>> = match (ids, status) {
+    (Ok(ids), None) if !ids.is_empty() => {
+      let ids: BTreeSet<usize> = ids.into_iter().collect();
+      if ids.len() > MAX_BULK_IDS {
+        let message = format!("At most {MAX_BULK_IDS} distinct 'id' parameters are allowed");
+        return (StatusCode::BAD_REQUEST, message).into_response();
+      }
+      Ok(ids.into_iter().collect())
+    }
+    (Ok(ids), Some(Ok(phase))) if ids.is_empty() => {
+      ctx.proposals_in_phase(phase, ctx.now()).map(|proposals| proposals.iter().map(|proposal| proposal.id).collect())
+    }
+    (Err(_), _) => return (StatusCode::BAD_REQUEST, "Invalid 'id' parameter").into_response(),
+    (Ok(_), Some(Err(err))) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    _ => return (StatusCode::BAD_REQUEST, "Pass either 'status' or one or more 'id' parameters").into_response(),
+  };
+  let weighted = params.iter().find(|(name, _)| name == "weighted").map(|(_, value)| value.parse::<bool>());
+  let Ok(weighted) = weighted.unwrap_or(Ok(true)) else {
+    return (StatusCode::BAD_REQUEST, "Invalid 'weighted' parameter").into_response();
+  };
+  let ids = match ids {
+    Ok(ids) => ids,
+    Err(err) => return error_response(err),
+  };
+  match weighted {
+    true => Wrapper(anyhow::Ok(ctx.proposal_results(&ids).await)).into_response(),
+    false => Wrapper(anyhow::Ok(ctx.proposal_votes(&ids).await)).into_response(),
+  }
+}
+
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_proposal_timeseries(
+  ctx: RequestOcv,
+  Path(id): Path<usize>,
+  Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+  tracing::info!("get_proposal_timeseries {}", id);
+  let bucket = params.get("bucket").map_or(Ok(TimeseriesBucket::Hour), |bucket| bucket.parse());
+  Wrapper(match bucket {
+    Ok(bucket) => ctx.proposal_timeseries(id, bucket).await,
+    Err(err) => Err(err),
+  })
+}
+
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_proposal_nonvoters(
+  ctx: RequestOcv,
+  Path(id): Path<usize>,
+  Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+  tracing::info!("get_proposal_nonvoters {}", id);
+  let parse_params = || -> Result<(Option<Decimal>, usize, usize)> {
+    let min_stake = params.get("min_stake").map(|min_stake| min_stake.parse()).transpose()?;
+    let offset = params.get("offset").map_or(Ok(0), |offset| offset.parse())?;
+    let limit = params.get("limit").map_or(Ok(NONVOTERS_DEFAULT_LIMIT), |limit| limit.parse())?;
+    Ok((min_stake, offset, limit.min(NONVOTERS_MAX_LIMIT)))
+  };
+  Wrapper(match parse_params() {
+    Ok((min_stake, offset, limit)) => ctx.proposal_nonvoters(id, min_stake, offset, limit).await,
+    Err(err) => Err(err),
+  })
+}
+
+/// Pages through the raw votes as JSON, or streams them as NDJSON when asked
+/// for with `format=ndjson` or the `Accept` header. A stream has no default
+/// limit. Votes are ordered by block height, then transaction hash; a
+/// `cursor` from a previous page resumes after its last vote, which unlike
+/// `offset` is stable while new votes land.
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_proposal_raw_votes(
+  ctx: RequestOcv,
+  Path(id): Path<usize>,
+  Query(params): Query<HashMap<String, String>>,
+  headers: HeaderMap,
+) -> Response {
+  tracing::info!("get_proposal_raw_votes {}", id);
+  let ndjson = match params.get("format").map(String::as_str) {
+    None => accepts(&headers, NDJSON_CONTENT_TYPE),
+    Some("ndjson") => true,
+    Some("json") => false,
+    Some(format) => return (StatusCode::BAD_REQUEST, format!("Unknown raw votes format '{format}'")).into_response(),
+  };
+  let parse_params = || -> Result<RawVotesPage> {
+    let offset = params.get("offset").map_or(Ok(0), |offset| offset.parse())?;
+    let limit = params.get("limit").map(|limit| limit.parse()).transpose()?;
+    let after = params.get("cursor").map(|cursor| decode_numbered_cursor(VOTES_CURSOR, cursor)).transpose()?;
+    Ok((offset, limit, after))
+  };
+  let Ok((offset, limit, after)) = parse_params() else {
+    return (StatusCode::BAD_REQUEST, "Invalid 'offset', 'limit' or 'cursor' parameter").into_response();
+  };
+  if after.is_some() && offset > 0 {
+    return (StatusCode::BAD_REQUEST, "Pass either 'offset' or 'cursor', not both").into_response();
+  }
+  let vote_key = |vote: &RawVote| (vote.block_height, vote.tx_hash.clone());
+  let votes = match ctx.raw_votes(id).await {
+    Ok(votes) => votes,
+    Err(err) => return error_response(err),
+  };
+  let total = votes.len();
+  let votes: Vec<RawVote> = votes.into_iter().skip(offset).collect();
+  if ndjson {
+    let (page, _) = page_after(votes, vote_key, after.as_ref(), limit.unwrap_or(usize::MAX));
+    let lines = futures_util::stream::iter(page.into_iter().map(|vote| {
+      serde_json::to_vec(&vote).map(|mut line| {
+        line.push(b'\n');
+        line
+      })
+    }));
+    return ([(CONTENT_TYPE, NDJSON_CONTENT_TYPE)], Body::from_stream(lines)).into_response();
+  }
+  let limit = limit.unwrap_or(RAW_VOTES_DEFAULT_LIMIT).min(RAW_VOTES_MAX_LIMIT);
+  let (votes, next) = page_after(votes, vote_key, after.as_ref(), limit);
+  let next_cursor = next.map(|(height, hash)| encode_cursor(VOTES_CURSOR, &format!("{height}:{hash}")));
+  Json(GetProposalRawVotesResponse { proposal_id: id, total, offset, limit, votes, next_cursor }).into_response()
+}
+
+#[debug_handler(state = Arc<Ocv>)]
+async fn get_proposal_inputs(ctx: RequestOcv, Path(id): Path<usize>) -> impl IntoResponse {
+  tracing::info!("get_proposal_inputs {}", id);
+  Wrapper(ctx.proposal_inputs(id).await)
+}
+
+#[debug_handler(state = Arc<Ocv>)]
 async fn get_proposal_consideration(
-  ctx: State<Arc<Ocv>>,
+  ctx: RequestOcv,
   Path((round_id, proposal_id, start_time, end_time)): Path<(usize, usize, i64, i64)>,
   Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
@@ -87,9 +613,9 @@ async fn get_proposal_consideration(
   Wrapper(ctx.proposal_consideration(round_id, proposal_id, start_time, end_time, ledger_hash).await)
 }
 
-#[debug_handler]
+#[debug_handler(state = Arc<Ocv>)]
 async fn run_ranked_vote(
-  ctx: State<Arc<Ocv>>,
+  ctx: RequestOcv,
   Path((round_id, start_time, end_time)): Path<(usize, i64, i64)>,
   Query(params): Query<HashMap<String, String>>,
 ) -> impl IntoResponse {
@@ -97,3 +623,314 @@ async fn run_ranked_vote(
   tracing::info!("run_ranked_vote {} {} {}", round_id, start_time, end_time);
   Wrapper(ctx.run_ranked_vote(round_id, start_time, end_time, ledger_hash).await)
 }
+
+#[debug_handler]
+async fn get_cache_status(ctx: State<Arc<Ocv>>) -> impl IntoResponse {
+  tracing::info!("get_cache_status");
+  Wrapper(ctx.ledger_cache_status().await)
+}
+
+#[debug_handler]
+async fn delete_cache(ctx: State<Arc<Ocv>>, Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+  let hash = params.get("hash");
+  tracing::info!("delete_cache {:?}", hash);
+  let dry_run = params.get("dry_run").map_or(Ok(false), |dry_run| dry_run.parse());
+  Wrapper(match dry_run {
+    Ok(dry_run) => ctx.clear_ledger_cache(hash.map(String::as_str), dry_run).await,
+    Err(err) => Err(err.into()),
+  })
+}
+
+#[debug_handler]
+async fn post_invalidate_tally(ctx: State<Arc<Ocv>>, Path(id): Path<usize>) -> impl IntoResponse {
+  tracing::info!("post_invalidate_tally {}", id);
+  Wrapper(ctx.invalidate_tally(id).await)
+}
+
+#[cfg(test)]
+mod tests {
+  use tower::ServiceExt;
+
+  use super::*;
+  use crate::{BlockStatus, MemoryProvider, MockArchive, Proposal, StubArchive};
+
+  fn serve_args(base_path: &str) -> ServeArgs {
+    ServeArgs::parse_from([
+      "mina_ocv",
+      "--network=mainnet",
+      "--release-stage=development",
+      "--archive-database-url=postgres://localhost/archive",
+      "--bucket-name=test-ledgers",
+      &format!("--base-path={base_path}"),
+    ])
+  }
+
+  fn test_proposal(id: usize, ledger_hash: Option<&str>) -> Proposal {
+    serde_json::from_value(serde_json::json!({
+      "id": id, "key": "cftest-2", "start_time": 1000, "end_time": 9000, "epoch": 1, "ledger_hash": ledger_hash,
+      "category": "Core", "version": "V2", "title": "", "description": "", "url": "", "network": "mainnet",
+      "is_complete": false
+    }))
+    .unwrap()
+  }
+
+  async fn status(router: &Router, uri: &str) -> StatusCode {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    router.clone().oneshot(request).await.unwrap().status()
+  }
+
+  #[test]
+  fn test_normalize_base_path() {
+    assert_eq!(normalize_base_path("").unwrap(), None);
+    assert_eq!(normalize_base_path("/").unwrap(), None);
+    assert_eq!(normalize_base_path("voting").unwrap().as_deref(), Some("/voting"));
+    assert_eq!(normalize_base_path("/ocv/voting/").unwrap().as_deref(), Some("/ocv/voting"));
+    assert!(normalize_base_path("/a//b").is_err());
+    assert!(normalize_base_path("/:id").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_router_base_path() {
+    let ocv = Ocv::new_for_test(MockArchive, MemoryProvider::new(), vec![]);
+
+    let root = serve_args("").router(ocv.clone()).unwrap();
+    assert_eq!(status(&root, "/api/proposals").await, StatusCode::OK);
+    assert_eq!(status(&root, "/voting/api/proposals").await, StatusCode::NOT_FOUND);
+    assert_eq!(status(&root, "/metrics").await, StatusCode::OK);
+
+    let nested = serve_args("/voting/").router(ocv.clone()).unwrap();
+    assert_eq!(status(&nested, "/voting/api/proposals").await, StatusCode::OK);
+    assert_eq!(status(&nested, "/voting/admin/cache/status").await, StatusCode::FORBIDDEN);
+    assert_eq!(status(&nested, "/api/proposals").await, StatusCode::NOT_FOUND);
+  }
+
+  #[tokio::test]
+  async fn test_admin_tokens() {
+    let mut args = serve_args("");
+    args.admin_token = Some("old".to_string());
+    args.admin_tokens = vec!["rotated:new".parse().unwrap()];
+    let ocv = Ocv::new_for_test(MockArchive, MemoryProvider::new(), vec![]);
+    let router = args.router(ocv.clone()).unwrap();
+
+    let with_token = |token: &str| {
+      let request = Request::builder()
+        .uri("/admin/cache/status")
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+      router.clone().oneshot(request)
+    };
+    assert_eq!(with_token("old").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(with_token("new").await.unwrap().status(), StatusCode::OK);
+    assert_eq!(with_token("rotated").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(with_token("ol").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(with_token("older").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(status(&router, "/admin/cache/status").await, StatusCode::UNAUTHORIZED);
+
+    let invalidate = |id: usize, token: &str| {
+      let request = Request::builder()
+        .method("POST")
+        .uri(format!("/admin/proposals/{id}/invalidate"))
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+      router.clone().oneshot(request)
+    };
+    assert_eq!(invalidate(1, "old").await.unwrap().status(), StatusCode::NOT_FOUND);
+    assert_eq!(invalidate(1, "wrong").await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    assert_eq!(
+      "ci:abc:def".parse::<AdminToken>().unwrap(),
+      AdminToken { label: "ci".to_string(), token: "abc:def".to_string() }
+    );
+    assert!("abc".parse::<AdminToken>().is_err());
+    assert!(":abc".parse::<AdminToken>().is_err());
+  }
+
+  #[tokio::test]
+  async fn test_limit_concurrency() {
+    let limited = |permits| {
+      Router::new()
+        .route("/", get(|| async { "ok" }))
+        .route_layer(from_fn_with_state(Arc::new(Semaphore::new(permits)), limit_concurrency))
+    };
+
+    assert_eq!(status(&limited(1), "/").await, StatusCode::OK);
+
+    let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let response = limited(0).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], CONCURRENCY_RETRY_AFTER_SECS.to_string());
+  }
+
+  #[tokio::test]
+  async fn test_version() {
+    let ocv = Ocv::new_for_test(MockArchive, MemoryProvider::new(), vec![]);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    assert_eq!(status(&router, "/version").await, StatusCode::OK);
+
+    let version = VersionResponse::current();
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert!(!version.git_hash.is_empty());
+    assert!(version.build_timestamp > 0);
+  }
+
+  #[tokio::test]
+  async fn test_decode_memo_params() {
+    let ocv = Ocv::new_for_test(MockArchive, MemoryProvider::new(), vec![]);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    assert_eq!(status(&router, "/api/decode-memo?proposal=1").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/decode-memo?memo=abc").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/decode-memo?memo=abc&proposal=x").await, StatusCode::BAD_REQUEST);
+  }
+
+  #[test]
+  fn test_accepts_protobuf() {
+    let accept = |value: &str| HeaderMap::from_iter([(ACCEPT, value.parse().unwrap())]);
+    assert!(accepts_protobuf(&accept("application/x-protobuf")));
+    assert!(accepts_protobuf(&accept("application/json;q=0.5, application/x-protobuf; q=1")));
+    assert!(!accepts_protobuf(&accept("application/json")));
+    assert!(!accepts_protobuf(&HeaderMap::new()));
+  }
+
+  #[tokio::test]
+  async fn test_results_params() {
+    let ocv = Ocv::new_for_test(MockArchive, MemoryProvider::new(), vec![]);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    assert_eq!(status(&router, "/api/results").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/results?status=finished").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/results?id=x").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/results?id=1&status=open").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/results?status=open").await, StatusCode::OK);
+    assert_eq!(status(&router, "/api/results?id=1").await, StatusCode::OK);
+    assert_eq!(status(&router, "/api/results?id=1&id=1&weighted=false").await, StatusCode::OK);
+    assert_eq!(status(&router, "/api/results?id=1&weighted=no").await, StatusCode::BAD_REQUEST);
+    let too_many: Vec<String> = (0 ..= MAX_BULK_IDS).map(|id| format!("id={id}")).collect();
+    assert_eq!(status(&router, &format!("/api/results?{}", too_many.join("&"))).await, StatusCode::BAD_REQUEST);
+  }
+
+  #[tokio::test]
+  async fn test_raw_votes() {
+    let account = "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM";
+    let archive = StubArchive::new(200, 300).with_vote(account, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let ocv = Ocv::new_for_test(archive, MemoryProvider::new(), vec![test_proposal(1, None)]);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    assert_eq!(status(&router, "/api/proposals/1/raw-votes").await, StatusCode::OK);
+    assert_eq!(status(&router, "/api/proposals/1/raw-votes?limit=x").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/proposals/1/raw-votes?format=csv").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/proposals/1/inputs").await, StatusCode::OK);
+    // The proposal's ledger isn't known yet.
+    assert_eq!(status(&router, "/api/proposal/1/results").await, StatusCode::OK);
+    assert_eq!(status(&router, "/api/proposal/1/results?strict=true").await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(status(&router, "/api/proposal/1/results?strict=yes").await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/proposals/2/inputs").await, StatusCode::NOT_FOUND);
+
+    let request = Request::builder()
+      .uri("/api/proposals/1/raw-votes")
+      .header(ACCEPT, NDJSON_CONTENT_TYPE)
+      .body(Body::empty())
+      .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], NDJSON_CONTENT_TYPE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+      .split(|byte| *byte == b'\n')
+      .filter(|line| !line.is_empty())
+      .map(|line| serde_json::from_slice(line).unwrap())
+      .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["choice"], "yes");
+    assert_eq!(lines[0]["decoded_memo"], "cftest-2");
+  }
+
+  #[tokio::test]
+  async fn test_result_cache_headers() {
+    let account = "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM";
+    let ledger = serde_json::json!([{ "pk": account, "balance": "10", "delegate": null }]);
+    let provider = MemoryProvider::new().with_object("test-ledgers", "staking-1-jxTest.json", ledger.to_string());
+    let archive = StubArchive::new(200, 300).with_vote(account, "cftest-2", 100, BlockStatus::Canonical, 2000);
+    let proposals = vec![test_proposal(1, Some("jxTest")), test_proposal(2, None)];
+    let ocv = Ocv::new_for_test(archive, provider, proposals);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    let get = |uri: &str, etag: Option<&str>| {
+      let mut request = Request::builder().uri(uri);
+      if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+      }
+      router.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    // Closed, with every vote confirmed.
+    let response = get("/api/proposal/1/results", None).await.unwrap();
+    assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=300");
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+    let response = get("/api/proposal/1/results", Some(&etag)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+    // Each format has its own tag.
+    let response = get("/api/proposal/1/results?format=mef", Some(&etag)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[ETAG], etag.as_str());
+
+    // The ledger isn't known yet, so the result may still change.
+    let response = get("/api/proposal/2/results", None).await.unwrap();
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+    assert_eq!(get("/api/proposal/2/results", Some(&etag)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(get("/api/proposal/2/results", Some("\"other\"")).await.unwrap().status(), StatusCode::OK);
+  }
+
+  #[tokio::test]
+  async fn test_cursor_pagination() {
+    let accounts = [
+      "B62qjYanmV7y9njVeH5UHkz3GYBm7xKir1rAnoY4KsEYUGLMiU45FSM",
+      "B62qre3erTHfzQckNuibViWQGyyKwZseztqrjPZBv6SQF384Rg6ESAy",
+      "B62qn9Yd4nBzkGVNegaAdAhELysTc5E25C2wBU3eK3ZcvEMrZ3v34ef",
+    ];
+    let archive = StubArchive::new(200, 300)
+      .with_vote(accounts[0], "cftest-2", 120, BlockStatus::Canonical, 2000)
+      .with_vote(accounts[1], "cftest-2", 100, BlockStatus::Canonical, 2000)
+      .with_vote(accounts[2], "cftest-2", 110, BlockStatus::Canonical, 2000);
+    let proposals = vec![test_proposal(3, None), test_proposal(1, None), test_proposal(2, None)];
+    let ocv = Ocv::new_for_test(archive, MemoryProvider::new(), proposals);
+    let router = serve_args("").router(ocv.clone()).unwrap();
+    let get = |uri: String| {
+      let router = router.clone();
+      async move {
+        let response = router.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+      }
+    };
+
+    let mut heights = Vec::new();
+    let mut uri = "/api/proposals/1/raw-votes?limit=2".to_string();
+    loop {
+      let page = get(uri).await;
+      heights.extend(page["votes"].as_array().unwrap().iter().map(|vote| vote["block_height"].as_i64().unwrap()));
+      match page["next_cursor"].as_str() {
+        Some(cursor) => uri = format!("/api/proposals/1/raw-votes?limit=2&cursor={cursor}"),
+        None => break,
+      }
+    }
+    assert_eq!(heights, [100, 110, 120]);
+
+    let first = get("/api/proposals?limit=2".to_string()).await;
+    let ids = |page: &serde_json::Value| -> Vec<i64> {
+      page["proposals"].as_array().unwrap().iter().map(|proposal| proposal["id"].as_i64().unwrap()).collect()
+    };
+    assert_eq!(ids(&first), [1, 2]);
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = get(format!("/api/proposals?limit=2&cursor={cursor}")).await;
+    assert_eq!(ids(&second), [3]);
+    assert!(second["next_cursor"].is_null());
+    // Without `cursor` or `limit`, every proposal is listed as before.
+    assert_eq!(get("/api/proposals".to_string()).await.as_array().unwrap().len(), 3);
+
+    assert_eq!(status(&router, &format!("/api/proposals/1/raw-votes?cursor={cursor}")).await, StatusCode::BAD_REQUEST);
+    assert_eq!(status(&router, "/api/proposals?cursor=bogus").await, StatusCode::BAD_REQUEST);
+    let votes_cursor = encode_cursor(VOTES_CURSOR, "100:hash");
+    let both = format!("/api/proposals/1/raw-votes?offset=1&cursor={votes_cursor}");
+    assert_eq!(status(&router, &both).await, StatusCode::BAD_REQUEST);
+  }
+}
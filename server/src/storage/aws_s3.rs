@@ -2,49 +2,282 @@ use anyhow::Result;
 use async_trait::async_trait;
 use aws_sdk_s3::{
   Client,
-  config::{Builder, Region},
+  config::{AppName, Builder, Region, retry::RetryConfig},
+  error::SdkError,
+  operation::{
+    copy_object::builders::CopyObjectFluentBuilder, get_object::GetObjectError, head_object::HeadObjectError,
+    put_object::builders::PutObjectFluentBuilder,
+  },
+  primitives::ByteStream,
+  types::ServerSideEncryption,
 };
 use bytes::Bytes;
+use futures_util::{TryStreamExt, stream::BoxStream};
 
-use super::StorageProvider;
+use super::{
+  ObjectMetadata, ObjectNotFound, RetryPolicy, StorageCapabilities, StorageProvider, dedup_keys, flatten_pages,
+  paginate, range_header,
+};
+
+/// `ObjectNotFound` if a GetObject request failed because `key` doesn't
+/// exist, otherwise `err` itself.
+fn get_error(err: SdkError<GetObjectError>, bucket: &str, key: &str) -> anyhow::Error {
+  match err.as_service_error() {
+    Some(service_error) if service_error.is_no_such_key() => ObjectNotFound::new(bucket, key).into(),
+    _ => err.into(),
+  }
+}
+
+/// Header S3 sets on HeadBucket responses, including redirects and access
+/// denials, naming the region the bucket lives in.
+const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+
+/// Identifies our requests in the SDK's user agent.
+const APP_NAME: &str = "mina-on-chain-voting";
 
 pub struct AwsS3Provider {
   client: Client,
+  sse_kms_key_id: Option<String>,
 }
 
 impl AwsS3Provider {
   pub fn new(region: &str) -> Result<Self> {
     let region = Region::new(region.to_string());
-    let config = Builder::new().region(region).behavior_version_latest().build();
+    // The SDK builds its own user agent; the app name is appended to it.
+    let config = Builder::new()
+      .region(region)
+      .app_name(AppName::new(APP_NAME).expect("valid app name"))
+      .behavior_version_latest()
+      .build();
     let client = Client::from_conf(config);
 
-    Ok(AwsS3Provider { client })
+    Ok(AwsS3Provider { client, sse_kms_key_id: None })
   }
-}
 
-#[async_trait]
-impl StorageProvider for AwsS3Provider {
-  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
-    let mut request = self.client.list_objects_v2().bucket(bucket);
+  /// Encrypts uploaded objects with this KMS key (SSE-KMS). Reading
+  /// SSE-KMS objects needs no setting here, but the credentials must be
+  /// allowed `kms:Decrypt` on the key.
+  pub fn with_sse_kms_key_id(mut self, sse_kms_key_id: Option<String>) -> Self {
+    self.sse_kms_key_id = sse_kms_key_id;
+    self
+  }
+
+  /// Sets how failed requests are retried. The SDK's standard mode backs off
+  /// on throttling (503 SlowDown) as well as on transient errors. It bounds
+  /// each backoff rather than their total, so `max_total_wait` caps each.
+  pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+    let retry_config = RetryConfig::standard()
+      .with_max_attempts(retry_policy.max_attempts)
+      .with_initial_backoff(retry_policy.base_delay)
+      .with_max_backoff(retry_policy.max_total_wait);
+    self.client = Client::from_conf(self.client.config().to_builder().retry_config(retry_config).build());
+    self
+  }
 
-    if let Some(prefix) = prefix {
-      request = request.prefix(prefix);
+  fn put_request(&self, bucket: &str, key: &str, bytes: Bytes) -> PutObjectFluentBuilder {
+    let request = self.client.put_object().bucket(bucket).key(key).body(ByteStream::from(bytes));
+    match &self.sse_kms_key_id {
+      Some(kms_key_id) => request.server_side_encryption(ServerSideEncryption::AwsKms).ssekms_key_id(kms_key_id),
+      None => request,
     }
+  }
 
-    let response = request.send().await?;
+  /// Server-side copy of `src_key` to `dst_key` within `bucket`. The copy is
+  /// encrypted with the configured KMS key, like uploads.
+  fn copy_request(&self, bucket: &str, src_key: &str, dst_key: &str) -> CopyObjectFluentBuilder {
+    let copy_source = format!("{}/{}", bucket, urlencoding::encode(src_key));
+    let request = self.client.copy_object().bucket(bucket).key(dst_key).copy_source(copy_source);
+    match &self.sse_kms_key_id {
+      Some(kms_key_id) => request.server_side_encryption(ServerSideEncryption::AwsKms).ssekms_key_id(kms_key_id),
+      None => request,
+    }
+  }
+
+  /// Builds a client for `bucket`, switching to the bucket's actual region
+  /// when it differs from `region`. Used when no region was configured, as
+  /// the lookup costs a request at startup.
+  pub async fn for_bucket(region: &str, bucket: &str) -> Result<Self> {
+    let provider = Self::new(region)?;
+    match provider.bucket_region(bucket).await {
+      Ok(Some(detected)) if detected != region => {
+        tracing::warn!(
+          "Bucket {} is in region {}, not the configured {}; using {}. Set AWS_REGION={} to skip this check.",
+          bucket,
+          detected,
+          region,
+          detected,
+          detected
+        );
+        Self::new(&detected)
+      }
+      Ok(detected) => {
+        tracing::info!("Detected region {} for bucket {}", detected.as_deref().unwrap_or(region), bucket);
+        Ok(provider)
+      }
+      Err(err) => {
+        tracing::warn!("Could not detect the region of bucket {}, keeping {}: {}", bucket, region, err);
+        Ok(provider)
+      }
+    }
+  }
+
+  /// Asks S3 which region `bucket` is in. A client in the wrong region gets a
+  /// 301 carrying the correct region in `x-amz-bucket-region`.
+  pub async fn bucket_region(&self, bucket: &str) -> Result<Option<String>> {
+    match self.client.head_bucket().bucket(bucket).send().await {
+      Ok(output) => Ok(output.bucket_region),
+      Err(err) => match err.raw_response().and_then(|response| response.headers().get(BUCKET_REGION_HEADER)) {
+        Some(region) => Ok(Some(region.to_string())),
+        None => Err(err.into()),
+      },
+    }
+  }
+
+  /// Fetches a page of the listing under `prefix`, and the token of the next
+  /// page if the listing is truncated.
+  async fn list_page(
+    &self,
+    bucket: &str,
+    prefix: Option<&str>,
+    continuation_token: Option<String>,
+  ) -> Result<(Vec<String>, Option<String>)> {
+    let response = self
+      .client
+      .list_objects_v2()
+      .bucket(bucket)
+      .set_prefix(prefix.map(str::to_string))
+      .set_continuation_token(continuation_token)
+      .send()
+      .await?;
+
+    let next_continuation_token = match response.next_continuation_token {
+      Some(token) if response.is_truncated.unwrap_or(false) => Some(token),
+      _ => None,
+    };
     let objects = response.contents.unwrap_or_default().into_iter().filter_map(|obj| obj.key).collect();
+    Ok((objects, next_continuation_token))
+  }
+}
+
+#[async_trait]
+impl StorageProvider for AwsS3Provider {
+  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    let objects = self.list_objects_stream(bucket, prefix).try_collect().await?;
+    let mut objects = dedup_keys(self.provider_name(), bucket, objects);
+    objects.sort();
 
     Ok(objects)
   }
 
+  fn list_objects_stream<'a>(&'a self, bucket: &'a str, prefix: Option<&'a str>) -> BoxStream<'a, Result<String>> {
+    flatten_pages(paginate(move |continuation_token| self.list_page(bucket, prefix, continuation_token)))
+  }
+
   async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
-    let response = self.client.get_object().bucket(bucket).key(key).send().await?;
+    let response =
+      self.client.get_object().bucket(bucket).key(key).send().await.map_err(|err| get_error(err, bucket, key))?;
+
+    let bytes = response.body.collect().await?.into_bytes();
+    Ok(bytes)
+  }
+
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    let request = self.client.get_object().bucket(bucket).key(key).range(range_header(start, end));
+    let response = request.send().await.map_err(|err| get_error(err, bucket, key))?;
 
     let bytes = response.body.collect().await?.into_bytes();
     Ok(bytes)
   }
 
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    let response = self.client.head_object().bucket(bucket).key(key).send().await.map_err(|err| {
+      let not_found = err.as_service_error().is_some_and(HeadObjectError::is_not_found);
+      if not_found { ObjectNotFound::new(bucket, key).into() } else { anyhow::Error::from(err) }
+    })?;
+
+    Ok(ObjectMetadata {
+      size: response.content_length.unwrap_or_default().max(0) as u64,
+      etag: response.e_tag.map(|etag| etag.trim_matches('"').to_string()),
+    })
+  }
+
+  async fn put_object(&self, bucket: &str, key: &str, bytes: Bytes) -> Result<()> {
+    self.put_request(bucket, key, bytes).send().await?;
+    Ok(())
+  }
+
+  async fn copy_object(&self, bucket: &str, src_key: &str, dst_key: &str) -> Result<()> {
+    self.copy_request(bucket, src_key, dst_key).send().await?;
+    Ok(())
+  }
+
   fn provider_name(&self) -> &'static str {
     "AWS S3"
   }
+
+  fn capabilities(&self) -> StorageCapabilities {
+    StorageCapabilities { can_write: true, can_head: true, can_range: true, can_stream: true }
+  }
+
+  async fn count_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    let mut count = 0;
+    let mut continuation_token: Option<String> = None;
+
+    loop {
+      let (objects, next_continuation_token) = self.list_page(bucket, prefix, continuation_token.take()).await?;
+      count += objects.len();
+
+      match next_continuation_token {
+        Some(token) => continuation_token = Some(token),
+        None => break,
+      }
+    }
+
+    Ok(count)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_put_request_sse_kms() {
+    let provider = AwsS3Provider::new("us-east-1").unwrap();
+    let request = provider.put_request("ledgers", "results/1.json", Bytes::from_static(b"{}"));
+    assert_eq!(request.get_server_side_encryption(), &None);
+    assert_eq!(request.get_ssekms_key_id(), &None);
+
+    let provider = provider.with_sse_kms_key_id(Some("alias/ocv".to_string()));
+    let request = provider.put_request("ledgers", "results/1.json", Bytes::from_static(b"{}"));
+    assert_eq!(request.get_server_side_encryption(), &Some(ServerSideEncryption::AwsKms));
+    assert_eq!(request.get_ssekms_key_id().as_deref(), Some("alias/ocv"));
+    assert_eq!(request.get_key().as_deref(), Some("results/1.json"));
+  }
+
+  #[test]
+  fn test_with_retry_policy() {
+    let policy = RetryPolicy { max_attempts: 7, ..Default::default() };
+    let provider = AwsS3Provider::new("us-east-1").unwrap().with_retry_policy(policy);
+    let retry_config = provider.client.config().retry_config().unwrap();
+    assert_eq!(retry_config.max_attempts(), 7);
+    assert_eq!(retry_config.initial_backoff(), policy.base_delay);
+    assert_eq!(retry_config.max_backoff(), policy.max_total_wait);
+    assert_eq!(provider.client.config().region().map(|region| region.as_ref()), Some("us-east-1"));
+  }
+
+  #[test]
+  fn test_copy_request() {
+    let provider = AwsS3Provider::new("us-east-1").unwrap();
+    let request = provider.copy_request("ledgers", "pending/1 a.json", "final/1.json");
+    assert_eq!(request.get_bucket().as_deref(), Some("ledgers"));
+    assert_eq!(request.get_key().as_deref(), Some("final/1.json"));
+    assert_eq!(request.get_copy_source().as_deref(), Some("ledgers/pending%2F1%20a.json"));
+    assert_eq!(request.get_server_side_encryption(), &None);
+
+    let provider = provider.with_sse_kms_key_id(Some("alias/ocv".to_string()));
+    let request = provider.copy_request("ledgers", "pending/1.json", "final/1.json");
+    assert_eq!(request.get_server_side_encryption(), &Some(ServerSideEncryption::AwsKms));
+    assert_eq!(request.get_ssekms_key_id().as_deref(), Some("alias/ocv"));
+  }
 }
@@ -0,0 +1,284 @@
+use std::{
+  future::Future,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{
+  StreamExt,
+  stream::{self, BoxStream},
+};
+
+use super::{ObjectMetadata, StorageCapabilities, StorageProvider, is_not_found};
+use crate::TallyError;
+
+/// State of a `CircuitBreaker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+  /// Requests go through.
+  Closed,
+  /// The cooldown is over and one request is probing whether the provider
+  /// recovered; others still fail fast.
+  HalfOpen,
+  /// Requests fail fast until the cooldown is over.
+  Open,
+}
+
+/// Wraps a provider so that requests fail fast while it keeps failing.
+///
+/// After `failure_threshold` consecutive failures, requests fail with
+/// `TallyError::StorageUnavailable` for `cooldown` instead of each waiting
+/// through retries. A single request then probes the provider: success
+/// closes the breaker, failure reopens it. A probe dropped before it
+/// completes lets the next request probe instead.
+///
+/// Missing objects count as successes: the provider answered.
+pub struct CircuitBreaker {
+  inner: Arc<dyn StorageProvider + Send + Sync>,
+  failure_threshold: u32,
+  cooldown: Duration,
+  state: Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+  consecutive_failures: u32,
+  opened_at: Option<Instant>,
+  probing: bool,
+}
+
+impl CircuitBreaker {
+  pub fn new(inner: Arc<dyn StorageProvider + Send + Sync>, failure_threshold: u32, cooldown: Duration) -> Self {
+    CircuitBreaker { inner, failure_threshold, cooldown, state: Mutex::default() }
+  }
+
+  pub fn state(&self) -> CircuitState {
+    let state = self.state.lock().expect("circuit breaker lock poisoned");
+    match state.opened_at {
+      None => CircuitState::Closed,
+      Some(opened_at) if opened_at.elapsed() < self.cooldown => CircuitState::Open,
+      Some(_) => CircuitState::HalfOpen,
+    }
+  }
+
+  /// Lets a request through, or fails fast while the breaker is open or
+  /// another request is probing. The request's outcome is recorded through
+  /// the returned `Admission`.
+  fn admit(&self) -> Result<Admission<'_>> {
+    let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+    let Some(opened_at) = state.opened_at else {
+      return Ok(Admission { breaker: self, probe: false, recorded: false });
+    };
+    let elapsed = opened_at.elapsed();
+    if elapsed >= self.cooldown && !state.probing {
+      state.probing = true;
+      return Ok(Admission { breaker: self, probe: true, recorded: false });
+    }
+    let retry_after_secs = self.cooldown.saturating_sub(elapsed).as_secs_f64().ceil().max(1.0) as u64;
+    Err(TallyError::StorageUnavailable { provider: self.inner.provider_name(), retry_after_secs }.into())
+  }
+
+  fn record<T>(&self, result: &Result<T>) {
+    let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+    if result.as_ref().map_or_else(is_not_found, |_| true) {
+      if state.opened_at.is_some() {
+        tracing::info!("{} recovered, closing the storage circuit breaker", self.inner.provider_name());
+      }
+      *state = BreakerState::default();
+      return;
+    }
+    state.consecutive_failures += 1;
+    if state.probing || (state.opened_at.is_none() && state.consecutive_failures >= self.failure_threshold) {
+      tracing::warn!(
+        "{} failed {} times in a row, failing storage requests fast for {:?}",
+        self.inner.provider_name(),
+        state.consecutive_failures,
+        self.cooldown
+      );
+      state.opened_at = Some(Instant::now());
+      state.probing = false;
+    }
+  }
+
+  async fn call<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+    let mut admission = self.admit()?;
+    let result = request.await;
+    admission.record(&result);
+    result
+  }
+}
+
+/// A request `CircuitBreaker::admit` let through. Dropping a probe without
+/// recording its outcome, as when its future is cancelled, frees the breaker
+/// for another probe.
+struct Admission<'a> {
+  breaker: &'a CircuitBreaker,
+  probe: bool,
+  recorded: bool,
+}
+
+impl Admission<'_> {
+  fn record<T>(&mut self, result: &Result<T>) {
+    self.breaker.record(result);
+    self.recorded = true;
+  }
+}
+
+impl Drop for Admission<'_> {
+  fn drop(&mut self) {
+    if self.probe && !self.recorded {
+      self.breaker.state.lock().expect("circuit breaker lock poisoned").probing = false;
+    }
+  }
+}
+
+#[async_trait]
+impl StorageProvider for CircuitBreaker {
+  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    self.call(self.inner.list_objects(bucket, prefix)).await
+  }
+
+  /// Records the outcome of each key as it is listed, so a listing the caller
+  /// stops reading early still counts as a success, and the end of the
+  /// listing as a success, so an empty one does too. A listing dropped before
+  /// either is like a dropped request.
+  fn list_objects_stream<'a>(&'a self, bucket: &'a str, prefix: Option<&'a str>) -> BoxStream<'a, Result<String>> {
+    let admission = match self.admit() {
+      Ok(admission) => admission,
+      Err(err) => return stream::once(async { Err(err) }).boxed(),
+    };
+    let keys = self.inner.list_objects_stream(bucket, prefix);
+    stream::unfold((keys, admission), |(mut keys, mut admission)| async move {
+      match keys.next().await {
+        Some(result) => {
+          admission.record(&result);
+          Some((result, (keys, admission)))
+        }
+        None => {
+          admission.record(&Ok::<_, anyhow::Error>(()));
+          None
+        }
+      }
+    })
+    .boxed()
+  }
+
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    self.call(self.inner.get_object(bucket, key)).await
+  }
+
+  fn provider_name(&self) -> &'static str {
+    self.inner.provider_name()
+  }
+
+  fn capabilities(&self) -> StorageCapabilities {
+    self.inner.capabilities()
+  }
+
+  async fn count_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    self.call(self.inner.count_objects(bucket, prefix)).await
+  }
+
+  async fn list_prefixes(&self, bucket: &str, prefix: Option<&str>, delimiter: &str) -> Result<Vec<String>> {
+    self.call(self.inner.list_prefixes(bucket, prefix, delimiter)).await
+  }
+
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    self.call(self.inner.get_object_range(bucket, key, start, end)).await
+  }
+
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    self.call(self.inner.head_object(bucket, key)).await
+  }
+
+  async fn put_object(&self, bucket: &str, key: &str, bytes: Bytes) -> Result<()> {
+    self.call(self.inner.put_object(bucket, key, bytes)).await
+  }
+
+  async fn copy_object(&self, bucket: &str, src_key: &str, dst_key: &str) -> Result<()> {
+    self.call(self.inner.copy_object(bucket, src_key, dst_key)).await
+  }
+
+  fn circuit_state(&self) -> Option<CircuitState> {
+    Some(self.state())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use futures_util::TryStreamExt;
+
+  use super::*;
+  use crate::MemoryProvider;
+
+  #[tokio::test]
+  async fn test_circuit_breaker() {
+    let memory = MemoryProvider::new().with_object("ledgers", "a.json", "[]");
+    let breaker = CircuitBreaker::new(Arc::new(memory), 2, Duration::from_millis(50));
+    // Reading past the end of the object fails like a provider error would.
+    let fail = || breaker.get_object_range("ledgers", "a.json", 100, None);
+
+    assert!(fail().await.is_err());
+    assert!(breaker.get_object("ledgers", "a.json").await.is_ok());
+    assert!(fail().await.is_err());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    // A missing object is an answer, not a failure.
+    let missing = breaker.get_object("ledgers", "missing.json").await.unwrap_err();
+    assert!(is_not_found(&missing));
+    assert!(fail().await.is_err());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    assert!(fail().await.is_err());
+    assert_eq!(breaker.circuit_state(), Some(CircuitState::Open));
+
+    // Open: even requests that would succeed fail fast.
+    let err = breaker.get_object("ledgers", "a.json").await.unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::StorageUnavailable { retry_after_secs: 1, .. })));
+
+    // A failed probe reopens the breaker; a successful one closes it.
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    assert!(fail().await.is_err());
+    assert_eq!(breaker.state(), CircuitState::Open);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(breaker.get_object("ledgers", "a.json").await.is_ok());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+  }
+
+  #[tokio::test]
+  async fn test_listing_probe() {
+    let memory = MemoryProvider::new().with_object("ledgers", "a.json", "[]");
+    let breaker = CircuitBreaker::new(Arc::new(memory), 1, Duration::from_millis(50));
+    let open = || async {
+      assert!(breaker.get_object_range("ledgers", "a.json", 100, None).await.is_err());
+      tokio::time::sleep(Duration::from_millis(60)).await;
+    };
+
+    // A probe listing dropped before its first key frees the probe.
+    open().await;
+    drop(breaker.list_objects_stream("ledgers", None));
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+    // An empty listing is a successful probe.
+    let keys: Vec<String> = breaker.list_objects_stream("ledgers", Some("none/")).try_collect().await.unwrap();
+    assert!(keys.is_empty());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+  }
+
+  #[tokio::test]
+  async fn test_dropped_probe() {
+    let memory = MemoryProvider::new().with_object("ledgers", "a.json", "[]");
+    let breaker = CircuitBreaker::new(Arc::new(memory), 1, Duration::from_millis(50));
+    assert!(breaker.get_object_range("ledgers", "a.json", 100, None).await.is_err());
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    // A probe cancelled before it completes, as on a client disconnect.
+    let probe = breaker.call(std::future::pending::<Result<()>>());
+    assert!(futures_util::poll!(Box::pin(probe)).is_pending());
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    assert!(breaker.get_object("ledgers", "a.json").await.is_ok());
+    assert_eq!(breaker.state(), CircuitState::Closed);
+  }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt, future, stream::BoxStream};
+
+use super::{CircuitState, ObjectMetadata, StorageCapabilities, StorageProvider};
+
+/// Wraps a provider so listings leave out directory markers: the zero-byte
+/// objects ending in `/` that some tools create as folder placeholders.
+pub struct SkipDirectoryMarkers {
+  inner: Arc<dyn StorageProvider + Send + Sync>,
+}
+
+impl SkipDirectoryMarkers {
+  pub fn new(inner: Arc<dyn StorageProvider + Send + Sync>) -> Self {
+    SkipDirectoryMarkers { inner }
+  }
+}
+
+fn is_directory_marker(key: &str) -> bool {
+  key.ends_with('/')
+}
+
+#[async_trait]
+impl StorageProvider for SkipDirectoryMarkers {
+  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    let mut objects = self.inner.list_objects(bucket, prefix).await?;
+    objects.retain(|key| !is_directory_marker(key));
+    Ok(objects)
+  }
+
+  fn list_objects_stream<'a>(&'a self, bucket: &'a str, prefix: Option<&'a str>) -> BoxStream<'a, Result<String>> {
+    self.inner.list_objects_stream(bucket, prefix).try_filter(|key| future::ready(!is_directory_marker(key))).boxed()
+  }
+
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    self.inner.get_object(bucket, key).await
+  }
+
+  fn provider_name(&self) -> &'static str {
+    self.inner.provider_name()
+  }
+
+  fn capabilities(&self) -> StorageCapabilities {
+    self.inner.capabilities()
+  }
+
+  /// Counts the filtered listing, as the wrapped provider's count includes
+  /// markers.
+  async fn count_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    Ok(self.list_objects(bucket, prefix).await?.len())
+  }
+
+  async fn list_prefixes(&self, bucket: &str, prefix: Option<&str>, delimiter: &str) -> Result<Vec<String>> {
+    self.inner.list_prefixes(bucket, prefix, delimiter).await
+  }
+
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    self.inner.get_object_range(bucket, key, start, end).await
+  }
+
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    self.inner.head_object(bucket, key).await
+  }
+
+  async fn put_object(&self, bucket: &str, key: &str, bytes: Bytes) -> Result<()> {
+    self.inner.put_object(bucket, key, bytes).await
+  }
+
+  async fn copy_object(&self, bucket: &str, src_key: &str, dst_key: &str) -> Result<()> {
+    self.inner.copy_object(bucket, src_key, dst_key).await
+  }
+
+  fn circuit_state(&self) -> Option<CircuitState> {
+    self.inner.circuit_state()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::MemoryProvider;
+
+  #[tokio::test]
+  async fn test_skip_directory_markers() {
+    let memory = MemoryProvider::new()
+      .with_object("ledgers", "mainnet/", "")
+      .with_object("ledgers", "mainnet/staking-epoch-1-jxA.json", "[]")
+      .with_object("ledgers", "mainnet/epoch-2/", "")
+      .with_object("ledgers", "mainnet/staking-epoch-2-jxB.json", "[]");
+    let provider = SkipDirectoryMarkers::new(Arc::new(memory));
+
+    let objects = provider.list_objects("ledgers", Some("mainnet/")).await.unwrap();
+    assert_eq!(objects, ["mainnet/staking-epoch-1-jxA.json", "mainnet/staking-epoch-2-jxB.json"]);
+    assert_eq!(provider.count_objects("ledgers", None).await.unwrap(), 2);
+    let streamed: Vec<String> = provider.list_objects_stream("ledgers", Some("mainnet/")).try_collect().await.unwrap();
+    assert_eq!(streamed, objects);
+    assert_eq!(provider.get_object("ledgers", "mainnet/").await.unwrap(), "");
+  }
+}
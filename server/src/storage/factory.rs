@@ -1,22 +1,76 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::{Result, anyhow};
 
-use super::{AwsS3Provider, GcsProvider, StorageProvider};
-use crate::config::OcvConfig;
+use super::{
+  AwsS3Provider, CircuitBreaker, GcsProvider, HttpClientConfig, HttpProvider, RecordingMode, RecordingProvider,
+  RetryPolicy, SkipDirectoryMarkers, StorageProvider,
+};
+use crate::config::{DEFAULT_AWS_REGION, OcvConfig};
 
 pub async fn create_storage_provider(config: &OcvConfig) -> Result<Arc<dyn StorageProvider + Send + Sync>> {
+  let dir = &config.storage_fixtures_dir;
+  let mut provider: Arc<dyn StorageProvider + Send + Sync> = match config.storage_recording {
+    Some(RecordingMode::Record) => {
+      tracing::warn!("Recording storage responses to {}", dir.display());
+      Arc::new(RecordingProvider::record(create_base_provider(config).await?, dir))
+    }
+    Some(RecordingMode::Replay) => {
+      tracing::warn!("Replaying recorded storage responses from {}", dir.display());
+      Arc::new(RecordingProvider::replay(dir))
+    }
+    None => create_base_provider(config).await?,
+  };
+  if config.storage_breaker_failure_threshold > 0 {
+    let cooldown = Duration::from_secs(config.storage_breaker_cooldown_secs);
+    provider = Arc::new(CircuitBreaker::new(provider, config.storage_breaker_failure_threshold, cooldown));
+  }
+  match config.skip_directory_markers {
+    true => Ok(Arc::new(SkipDirectoryMarkers::new(provider))),
+    false => Ok(provider),
+  }
+}
+
+async fn create_base_provider(config: &OcvConfig) -> Result<Arc<dyn StorageProvider + Send + Sync>> {
   match config.storage_provider.as_str() {
     "aws" => {
       tracing::info!("Initializing AWS S3 storage provider with region: {}", config.aws_region);
-      Ok(Arc::new(AwsS3Provider::new(&config.aws_region)?))
+      // Only the default region is checked against the bucket's; an explicit
+      // AWS_REGION is used as is.
+      let provider = match config.aws_region == DEFAULT_AWS_REGION {
+        true => AwsS3Provider::for_bucket(&config.aws_region, &config.bucket_name).await?,
+        false => AwsS3Provider::new(&config.aws_region)?,
+      };
+      let provider = provider.with_retry_policy(retry_policy(config));
+      Ok(Arc::new(provider.with_sse_kms_key_id(config.aws_sse_kms_key_id.clone())))
     }
     "gcs" => {
       let project_id =
         config.gcs_project_id.as_ref().ok_or_else(|| anyhow!("GCS_PROJECT_ID required when using GCS provider"))?;
       tracing::info!("Initializing GCS storage provider with project: {}", project_id);
-      Ok(Arc::new(GcsProvider::new(project_id, config.gcs_service_account_key_path.as_deref()).await?))
+      let provider = GcsProvider::new(project_id, config.gcs_service_account_key_path.as_deref()).await?;
+      Ok(Arc::new(provider.with_retry_policy(retry_policy(config)).with_http_client(http_client(config)?)))
     }
-    provider => Err(anyhow!("Unsupported storage provider: {}. Supported providers: aws, gcs", provider)),
+    "http" => {
+      let base_url =
+        config.http_base_url.as_ref().ok_or_else(|| anyhow!("HTTP_BASE_URL required when using HTTP provider"))?;
+      tracing::info!("Initializing HTTP storage provider with base URL: {}", base_url);
+      let provider = HttpProvider::new(http_client(config)?, base_url, config.http_index_url.as_deref());
+      Ok(Arc::new(provider.with_retry_policy(retry_policy(config))))
+    }
+    provider => Err(anyhow!("Unsupported storage provider: {}. Supported providers: aws, gcs, http", provider)),
+  }
+}
+
+fn retry_policy(config: &OcvConfig) -> RetryPolicy {
+  RetryPolicy { max_total_wait: Duration::from_secs(config.storage_max_retry_wait_secs), ..Default::default() }
+}
+
+fn http_client(config: &OcvConfig) -> Result<reqwest::Client> {
+  HttpClientConfig {
+    pool_idle_timeout: Duration::from_secs(config.http_pool_idle_timeout_secs),
+    pool_max_idle_per_host: config.http_pool_max_idle,
+    user_agent: config.user_agent(),
   }
+  .build_client()
 }
@@ -1,21 +1,37 @@
+use std::future::Future;
+
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt, stream::BoxStream};
 use google_cloud_storage::{
   client::{Client, ClientConfig},
-  http::objects::{download::Range, get::GetObjectRequest, list::ListObjectsRequest},
+  http::objects::{
+    download::Range,
+    get::GetObjectRequest,
+    list::{ListObjectsRequest, ListObjectsResponse},
+    rewrite::RewriteObjectRequest,
+    upload::{Media, UploadObjectRequest, UploadType},
+  },
 };
 use serde::Deserialize;
 
-use super::StorageProvider;
+use super::{
+  ObjectMetadata, ObjectNotFound, RetryPolicy, StorageCapabilities, StorageProvider, dedup_keys, ensure_writable,
+  flatten_pages, paginate, range_header,
+};
 
 enum GcsClient {
   Authenticated(Client),
-  Anonymous(reqwest::Client),
+  Anonymous,
 }
 
 pub struct GcsProvider {
   client: GcsClient,
+  /// Client of anonymous requests: all of them without credentials, and
+  /// retries of authenticated requests rejected with 401/403.
+  http_client: reqwest::Client,
+  retry_policy: RetryPolicy,
   #[allow(dead_code)] // May be used for future GCS operations that require project_id
   project_id: String,
 }
@@ -23,6 +39,8 @@ pub struct GcsProvider {
 #[derive(Deserialize)]
 struct GcsListResponse {
   items: Option<Vec<GcsObject>>,
+  /// Common prefixes, only returned by delimited listings.
+  prefixes: Option<Vec<String>>,
   #[serde(rename = "nextPageToken")]
   next_page_token: Option<String>,
 }
@@ -32,6 +50,13 @@ struct GcsObject {
   name: String,
 }
 
+/// Object resource returned by the JSON API; `size` is a decimal string.
+#[derive(Deserialize)]
+struct GcsObjectMetadata {
+  size: String,
+  etag: Option<String>,
+}
+
 impl GcsProvider {
   pub async fn new(project_id: &str, service_account_key_path: Option<&str>) -> Result<Self> {
     // Try to create authenticated client first, but fall back to anonymous HTTP
@@ -49,7 +74,7 @@ impl GcsProvider {
             "Failed to initialize GCS with service account authentication, using anonymous HTTP access for public buckets: {}",
             err
           );
-          GcsClient::Anonymous(reqwest::Client::new())
+          GcsClient::Anonymous
         }
       }
     } else {
@@ -61,175 +86,673 @@ impl GcsProvider {
         }
         Err(err) => {
           tracing::warn!("No GCS credentials found, using anonymous HTTP access for public buckets: {}", err);
-          GcsClient::Anonymous(reqwest::Client::new())
+          GcsClient::Anonymous
         }
       }
     };
 
-    Ok(GcsProvider { client, project_id: project_id.to_string() })
+    Ok(GcsProvider {
+      client,
+      http_client: reqwest::Client::new(),
+      retry_policy: RetryPolicy::default(),
+      project_id: project_id.to_string(),
+    })
+  }
+
+  /// Sets how throttled anonymous requests are retried. The authenticated
+  /// client applies its own retry policy.
+  pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+    self.retry_policy = retry_policy;
+    self
+  }
+
+  /// Replaces the client used for anonymous access and anonymous retries,
+  /// e.g. with one built from an `HttpClientConfig`. Has no effect on the
+  /// authenticated client.
+  pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+    self.http_client = http_client;
+    self
+  }
+
+  /// Logs that a request the credentials were rejected for is retried
+  /// anonymously. Later requests still try the credentials first.
+  fn log_downgrade(&self, bucket: &str, operation: &str, err: &dyn std::fmt::Display) {
+    tracing::warn!(
+      "GCS rejected the credentials for bucket '{}' ({}); retrying {} anonymously, as for a public bucket",
+      bucket,
+      err,
+      operation
+    );
+  }
+
+  /// Downloads an object, or only the inclusive byte span `range` of it.
+  async fn download(&self, bucket: &str, key: &str, range: Option<(u64, Option<u64>)>) -> Result<Bytes> {
+    let GcsClient::Authenticated(client) = &self.client else {
+      return self.download_anonymous(bucket, key, range).await;
+    };
+    let request = GetObjectRequest { bucket: bucket.to_string(), object: key.to_string(), ..Default::default() };
+    let byte_range = range.map_or_else(Range::default, |(start, end)| Range(Some(start), end));
+    match client.download_object(&request, &byte_range).await {
+      Ok(response) => Ok(Bytes::from(response)),
+      Err(err) if is_auth_failure(&err) => {
+        self.log_downgrade(bucket, &format!("download of '{key}'"), &err);
+        self.download_anonymous(bucket, key, range).await
+      }
+      Err(err) if is_not_found_failure(&err) => Err(ObjectNotFound::new(bucket, key).into()),
+      Err(err) => Err(anyhow!("Failed to download object '{}' from GCS bucket '{}': {}", key, bucket, err)),
+    }
+  }
+
+  async fn download_anonymous(&self, bucket: &str, key: &str, range: Option<(u64, Option<u64>)>) -> Result<Bytes> {
+    // Use GCS JSON API for anonymous access
+    let url = format!("{}?alt=media", object_url(bucket, key));
+
+    let response = self
+      .retry_policy
+      .send(|| {
+        let request = self.http_client.get(&url);
+        match range {
+          Some((start, end)) => request.header(reqwest::header::RANGE, range_header(start, end)),
+          None => request,
+        }
+      })
+      .await
+      .map_err(|err| anyhow!("Failed to download object '{}' from GCS bucket '{}': {}", key, bucket, err))?;
+
+    if response.status().is_client_error() {
+      if response.status() == 401 || response.status() == 403 {
+        return Err(anyhow!(
+          "GCS object '{}' in bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables.",
+          key,
+          bucket
+        ));
+      }
+      if response.status() == 404 {
+        return Err(ObjectNotFound::new(bucket, key).into());
+      }
+      return Err(anyhow!("Failed to access GCS object '{}' in bucket '{}': HTTP {}", key, bucket, response.status()));
+    }
+
+    let bytes = response
+      .bytes()
+      .await
+      .map_err(|err| anyhow!("Failed to read object '{}' from GCS bucket '{}': {}", key, bucket, err))?;
+
+    Ok(bytes)
+  }
+
+  /// Fetches a page of object names, with the credentials if there are any.
+  /// A page the credentials are rejected for is fetched anonymously.
+  async fn object_page(&self, bucket: &str, prefix: Option<&str>, page_token: Option<String>) -> Result<ListPage> {
+    if let GcsClient::Authenticated(client) = &self.client {
+      let request = ListObjectsRequest {
+        bucket: bucket.to_string(),
+        prefix: prefix.map(str::to_string),
+        page_token: page_token.clone(),
+        ..Default::default()
+      };
+      match client.list_objects(&request).await {
+        Ok(response) => {
+          let page_objects = response.items.unwrap_or_default().into_iter().map(|obj| obj.name).collect();
+          return Ok((page_objects, response.next_page_token));
+        }
+        Err(err) if is_auth_failure(&err) => self.log_downgrade(bucket, "listing", &err),
+        Err(err) => return Err(authenticated_list_error(bucket, err)),
+      }
+    }
+    let list_response =
+      list_anonymous_page(&self.http_client, &self.retry_policy, bucket, prefix, None, page_token.as_deref()).await?;
+    let page_objects = list_response.items.unwrap_or_default().into_iter().map(|obj| obj.name).collect();
+    Ok((page_objects, list_response.next_page_token))
+  }
+
+  async fn list_prefixes_anonymous(&self, bucket: &str, prefix: Option<&str>, delimiter: &str) -> Result<Vec<String>> {
+    let (http_client, retry_policy) = (&self.http_client, &self.retry_policy);
+    let (prefixes, page_count) = collect_pages(bucket, Some(MAX_ANONYMOUS_PAGES), |page_token| async move {
+      let list_response =
+        list_anonymous_page(http_client, retry_policy, bucket, prefix, Some(delimiter), page_token.as_deref()).await?;
+      Ok((list_response.prefixes.unwrap_or_default(), list_response.next_page_token))
+    })
+    .await?;
+    tracing::info!(
+      "GCS anonymous client found {} prefixes across {} pages in bucket '{}'",
+      prefixes.len(),
+      page_count,
+      bucket
+    );
+    Ok(prefixes)
+  }
+
+  async fn head_anonymous(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    let url = object_url(bucket, key);
+    let metadata: GcsObjectMetadata = self
+      .retry_policy
+      .send(|| self.http_client.get(&url))
+      .await
+      .and_then(reqwest::Response::error_for_status)
+      .map_err(|err| anyhow!("Failed to fetch metadata of '{}' from GCS bucket '{}': {}", key, bucket, err))?
+      .json()
+      .await?;
+    Ok(ObjectMetadata { size: metadata.size.parse()?, etag: metadata.etag })
+  }
+
+  /// Counts objects page by page, with `client` or anonymously.
+  async fn count_pages(&self, client: Option<&Client>, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    let mut count = 0;
+    let mut page_token: Option<String> = None;
+
+    loop {
+      let (page_count, next_page_token) = match client {
+        Some(client) => {
+          let request = ListObjectsRequest {
+            bucket: bucket.to_string(),
+            prefix: prefix.map(str::to_string),
+            page_token: page_token.take(),
+            ..Default::default()
+          };
+          let response = client.list_objects(&request).await.map_err(|err| authenticated_list_error(bucket, err))?;
+          (response.items.map_or(0, |items| items.len()), response.next_page_token)
+        }
+        None => {
+          let response =
+            list_anonymous_page(&self.http_client, &self.retry_policy, bucket, prefix, None, page_token.as_deref())
+              .await?;
+          (response.items.map_or(0, |items| items.len()), response.next_page_token)
+        }
+      };
+
+      count += page_count;
+      match next_page_token {
+        Some(token) => page_token = Some(token),
+        None => break,
+      }
+    }
+
+    Ok(count)
+  }
+}
+
+/// Whether an authenticated request failed because its credentials were
+/// rejected (HTTP 401 or 403).
+fn is_auth_failure(err: &dyn std::fmt::Display) -> bool {
+  let message = err.to_string();
+  message.contains("401") || message.contains("403")
+}
+
+/// Whether an authenticated request failed because the object doesn't exist
+/// (HTTP 404).
+fn is_not_found_failure(err: &dyn std::fmt::Display) -> bool {
+  err.to_string().contains("404")
+}
+
+/// Base URL of the GCS JSON API.
+const JSON_API_URL: &str = "https://storage.googleapis.com/storage/v1";
+
+/// JSON API URL of object `key`. The object name is a single path segment,
+/// so it is fully percent-encoded, slashes included
+/// (`staking/epoch=55/ledger.json` becomes `staking%2Fepoch%3D55%2Fledger.json`).
+fn object_url(bucket: &str, key: &str) -> String {
+  format!("{}/b/{}/o/{}", JSON_API_URL, urlencoding::encode(bucket), urlencoding::encode(key))
+}
+
+/// JSON API URL listing the objects of `bucket` under `prefix`. With a
+/// `delimiter`, the listing also returns the common prefixes.
+fn list_url(bucket: &str, prefix: Option<&str>, delimiter: Option<&str>, page_token: Option<&str>) -> String {
+  let mut url = format!("{}/b/{}/o?maxResults=1000", JSON_API_URL, urlencoding::encode(bucket));
+  if let Some(prefix) = prefix {
+    url.push_str(&format!("&prefix={}", urlencoding::encode(prefix)));
+  }
+  if let Some(delimiter) = delimiter {
+    url.push_str(&format!("&delimiter={}", urlencoding::encode(delimiter)));
+  }
+  if let Some(token) = page_token {
+    url.push_str(&format!("&pageToken={}", urlencoding::encode(token)));
+  }
+  url
+}
+
+/// Maximum number of pages fetched by an anonymous listing.
+const MAX_ANONYMOUS_PAGES: usize = 10;
+
+/// A page of object names and the token of the next page, if any.
+type ListPage = (Vec<String>, Option<String>);
+
+/// Follows page tokens until the listing is exhausted, or until `max_pages`
+/// pages have been fetched. Returns the accumulated names of `bucket`, sorted
+/// and without duplicates, and the number of pages fetched.
+async fn collect_pages<F, Fut>(
+  bucket: &str,
+  max_pages: Option<usize>,
+  mut fetch_page: F,
+) -> Result<(Vec<String>, usize)>
+where
+  F: FnMut(Option<String>) -> Fut,
+  Fut: Future<Output = Result<ListPage>>,
+{
+  let mut all_objects = Vec::new();
+  let mut page_token: Option<String> = None;
+  let mut page_count = 0;
+
+  loop {
+    let (page_objects, next_page_token) = fetch_page(page_token.take()).await?;
+    tracing::debug!("GCS page {} returned {} objects", page_count + 1, page_objects.len());
+    all_objects.extend(page_objects);
+
+    page_count += 1;
+    page_token = next_page_token;
+
+    if page_token.is_none() {
+      break;
+    }
+    if max_pages.is_some_and(|max_pages| page_count >= max_pages) {
+      tracing::warn!(
+        "Stopped fetching GCS objects after {} pages ({} objects) to avoid excessive API calls",
+        page_count,
+        all_objects.len()
+      );
+      break;
+    }
+  }
+
+  let mut all_objects = dedup_keys("GCS", bucket, all_objects);
+  all_objects.sort();
+  Ok((all_objects, page_count))
+}
+
+/// The common prefixes of an authenticated delimited listing page, and the
+/// token of the next page.
+fn prefix_page(response: ListObjectsResponse) -> ListPage {
+  (response.prefixes.unwrap_or_default(), response.next_page_token)
+}
+
+fn authenticated_list_error(bucket: &str, err: impl std::fmt::Display) -> anyhow::Error {
+  if is_auth_failure(&err) {
+    anyhow!(
+      "GCS bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables. Error: {}",
+      bucket,
+      err
+    )
+  } else {
+    anyhow!("Failed to list objects in GCS bucket '{}': {}", bucket, err)
+  }
+}
+
+/// Fetches a single page of the GCS JSON API object listing without
+/// credentials.
+async fn list_anonymous_page(
+  http_client: &reqwest::Client,
+  retry_policy: &RetryPolicy,
+  bucket: &str,
+  prefix: Option<&str>,
+  delimiter: Option<&str>,
+  page_token: Option<&str>,
+) -> Result<GcsListResponse> {
+  let url = list_url(bucket, prefix, delimiter, page_token);
+  tracing::debug!("Fetching GCS listing from: {}", url);
+
+  let response = retry_policy
+    .send(|| http_client.get(&url))
+    .await
+    .map_err(|err| anyhow!("Failed to list objects in GCS bucket '{}': {}", bucket, err))?;
+
+  if response.status().is_client_error() {
+    if response.status() == 401 || response.status() == 403 {
+      return Err(anyhow!(
+        "GCS bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables.",
+        bucket
+      ));
+    }
+    return Err(anyhow!("Failed to access GCS bucket '{}': HTTP {}", bucket, response.status()));
   }
+
+  response.json().await.map_err(|err| anyhow!("Failed to parse GCS response for bucket '{}': {}", bucket, err))
 }
 
 #[async_trait]
 impl StorageProvider for GcsProvider {
   async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
-    match &self.client {
-      GcsClient::Authenticated(client) => {
-        let mut request = ListObjectsRequest { bucket: bucket.to_string(), ..Default::default() };
-
-        if let Some(prefix) = prefix {
-          request.prefix = Some(prefix.to_string());
-        }
+    let objects = self.list_objects_stream(bucket, prefix).try_collect().await?;
+    let mut objects = dedup_keys("GCS", bucket, objects);
+    objects.sort();
+
+    tracing::info!(
+      "GCS found {} objects in bucket '{}': {:?}",
+      objects.len(),
+      bucket,
+      objects.iter().take(5).collect::<Vec<_>>()
+    );
+
+    Ok(objects)
+  }
 
-        let response = client.list_objects(&request).await
-                    .map_err(|err| {
-                        if err.to_string().contains("401") || err.to_string().contains("403") {
-                            anyhow!("GCS bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables. Error: {}", bucket, err)
-                        } else {
-                            anyhow!("Failed to list objects in GCS bucket '{}': {}", bucket, err)
-                        }
-                    })?;
+  fn list_objects_stream<'a>(&'a self, bucket: &'a str, prefix: Option<&'a str>) -> BoxStream<'a, Result<String>> {
+    let pages = paginate(move |page_token| self.object_page(bucket, prefix, page_token));
+    match self.client {
+      GcsClient::Authenticated(_) => flatten_pages(pages),
+      // Stop after a reasonable amount of pages without credentials
+      GcsClient::Anonymous => flatten_pages(pages.take(MAX_ANONYMOUS_PAGES)),
+    }
+  }
 
-        let objects = response.items.unwrap_or_default().into_iter().map(|obj| obj.name).collect::<Vec<String>>();
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    self.download(bucket, key, None).await
+  }
 
-        tracing::info!(
-          "GCS authenticated client found {} objects in bucket '{}': {:?}",
-          objects.len(),
-          bucket,
-          objects.iter().take(5).collect::<Vec<_>>()
-        );
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    self.download(bucket, key, Some((start, end))).await
+  }
 
-        Ok(objects)
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    let GcsClient::Authenticated(client) = &self.client else {
+      return self.head_anonymous(bucket, key).await;
+    };
+    let request = GetObjectRequest { bucket: bucket.to_string(), object: key.to_string(), ..Default::default() };
+    match client.get_object(&request).await {
+      Ok(object) => Ok(ObjectMetadata { size: object.size.max(0) as u64, etag: Some(object.etag) }),
+      Err(err) if is_auth_failure(&err) => {
+        self.log_downgrade(bucket, &format!("metadata of '{key}'"), &err);
+        self.head_anonymous(bucket, key).await
       }
-      GcsClient::Anonymous(http_client) => {
-        // Use GCS JSON API for anonymous access with pagination support
-        let mut all_objects = Vec::new();
-        let mut page_token: Option<String> = None;
-        let mut page_count = 0;
-
-        loop {
-          let mut url = format!("https://storage.googleapis.com/storage/v1/b/{}/o?maxResults=1000", bucket);
-
-          if let Some(prefix) = prefix {
-            url.push_str(&format!("&prefix={}", urlencoding::encode(prefix)));
-          }
-
-          if let Some(token) = &page_token {
-            url.push_str(&format!("&pageToken={}", urlencoding::encode(token)));
-          }
-
-          tracing::debug!("Fetching GCS page {} from: {}", page_count + 1, url);
-
-          let response = http_client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|err| anyhow!("Failed to list objects in GCS bucket '{}': {}", bucket, err))?;
-
-          if response.status().is_client_error() {
-            if response.status() == 401 || response.status() == 403 {
-              return Err(anyhow!(
-                "GCS bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables.",
-                bucket
-              ));
-            }
-            return Err(anyhow!("Failed to access GCS bucket '{}': HTTP {}", bucket, response.status()));
-          }
-
-          let list_response: GcsListResponse = response
-            .json()
-            .await
-            .map_err(|err| anyhow!("Failed to parse GCS response for bucket '{}': {}", bucket, err))?;
-
-          if let Some(items) = list_response.items {
-            let page_objects: Vec<String> = items.into_iter().map(|obj| obj.name).collect();
-            tracing::debug!("GCS page {} returned {} objects", page_count + 1, page_objects.len());
-            all_objects.extend(page_objects);
-          }
-
-          page_count += 1;
-          page_token = list_response.next_page_token;
-
-          // Break if no more pages or if we've fetched a reasonable amount
-          if page_token.is_none() || page_count >= 10 {
-            if page_count >= 10 {
-              tracing::warn!(
-                "Stopped fetching GCS objects after {} pages ({} objects) to avoid excessive API calls",
-                page_count,
-                all_objects.len()
-              );
-            }
-            break;
-          }
-        }
+      Err(err) => Err(anyhow!("Failed to fetch metadata of '{}' from GCS bucket '{}': {}", key, bucket, err)),
+    }
+  }
 
-        tracing::info!(
-          "GCS anonymous client found {} objects across {} pages in bucket '{}': {:?}",
-          all_objects.len(),
-          page_count,
-          bucket,
-          all_objects.iter().take(5).collect::<Vec<_>>()
-        );
+  async fn put_object(&self, bucket: &str, key: &str, bytes: Bytes) -> Result<()> {
+    let GcsClient::Authenticated(client) = &self.client else {
+      return Err(anyhow!("Writing '{}' to GCS bucket '{}' requires authentication", key, bucket));
+    };
+    let request = UploadObjectRequest { bucket: bucket.to_string(), ..Default::default() };
+    client
+      .upload_object(&request, bytes.to_vec(), &UploadType::Simple(Media::new(key.to_string())))
+      .await
+      .map_err(|err| anyhow!("Failed to upload '{}' to GCS bucket '{}': {}", key, bucket, err))?;
+    Ok(())
+  }
 
-        Ok(all_objects)
+  /// Copies with the rewrite endpoint, which unlike `copyTo` handles large
+  /// objects by returning a token to resume from until the copy is done.
+  async fn copy_object(&self, bucket: &str, src_key: &str, dst_key: &str) -> Result<()> {
+    ensure_writable(self, bucket, dst_key)?;
+    let GcsClient::Authenticated(client) = &self.client else {
+      unreachable!("anonymous GCS access is read-only");
+    };
+    let mut rewrite_token = None;
+    loop {
+      let request = RewriteObjectRequest {
+        source_bucket: bucket.to_string(),
+        source_object: src_key.to_string(),
+        destination_bucket: bucket.to_string(),
+        destination_object: dst_key.to_string(),
+        rewrite_token: rewrite_token.take(),
+        ..Default::default()
+      };
+      let response = client.rewrite_object(&request).await.map_err(|err| {
+        anyhow!("Failed to copy '{}' to '{}' in GCS bucket '{}': {}", src_key, dst_key, bucket, err)
+      })?;
+      if response.done {
+        return Ok(());
       }
+      rewrite_token = response.rewrite_token;
     }
   }
 
-  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+  fn provider_name(&self) -> &'static str {
+    "Google Cloud Storage"
+  }
+
+  fn capabilities(&self) -> StorageCapabilities {
     match &self.client {
-      GcsClient::Authenticated(client) => {
-        let request = GetObjectRequest { bucket: bucket.to_string(), object: key.to_string(), ..Default::default() };
-
-        let response = client.download_object(&request, &Range::default()).await
-                    .map_err(|err| {
-                        if err.to_string().contains("401") || err.to_string().contains("403") {
-                            anyhow!("GCS object '{}' in bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables. Error: {}", key, bucket, err)
-                        } else {
-                            anyhow!("Failed to download object '{}' from GCS bucket '{}': {}", key, bucket, err)
-                        }
-                    })?;
-
-        Ok(Bytes::from(response))
+      GcsClient::Authenticated(_) => {
+        StorageCapabilities { can_write: true, can_head: true, can_range: true, can_stream: true }
       }
-      GcsClient::Anonymous(http_client) => {
-        // Use GCS JSON API for anonymous access
-        let url =
-          format!("https://storage.googleapis.com/storage/v1/b/{}/o/{}?alt=media", bucket, urlencoding::encode(key));
-
-        let response = http_client
-          .get(&url)
-          .send()
-          .await
-          .map_err(|err| anyhow!("Failed to download object '{}' from GCS bucket '{}': {}", key, bucket, err))?;
-
-        if response.status().is_client_error() {
-          if response.status() == 401 || response.status() == 403 {
-            return Err(anyhow!(
-              "GCS object '{}' in bucket '{}' requires authentication. Please set GCS_PROJECT_ID and optionally GCS_SERVICE_ACCOUNT_KEY_PATH environment variables.",
-              key,
-              bucket
-            ));
-          }
-          return Err(anyhow!(
-            "Failed to access GCS object '{}' in bucket '{}': HTTP {}",
-            key,
-            bucket,
-            response.status()
-          ));
-        }
+      // Public buckets can be read but never written without credentials.
+      GcsClient::Anonymous => {
+        StorageCapabilities { can_write: false, can_head: true, can_range: true, can_stream: true }
+      }
+    }
+  }
 
-        let bytes = response
-          .bytes()
-          .await
-          .map_err(|err| anyhow!("Failed to read object '{}' from GCS bucket '{}': {}", key, bucket, err))?;
+  async fn count_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    let GcsClient::Authenticated(client) = &self.client else {
+      return self.count_pages(None, bucket, prefix).await;
+    };
+    match self.count_pages(Some(client), bucket, prefix).await {
+      Err(err) if is_auth_failure(&err) => {
+        self.log_downgrade(bucket, "count", &err);
+        self.count_pages(None, bucket, prefix).await
+      }
+      result => result,
+    }
+  }
 
-        Ok(bytes)
+  async fn list_prefixes(&self, bucket: &str, prefix: Option<&str>, delimiter: &str) -> Result<Vec<String>> {
+    let GcsClient::Authenticated(client) = &self.client else {
+      return self.list_prefixes_anonymous(bucket, prefix, delimiter).await;
+    };
+    let listing = collect_pages(bucket, None, |page_token| async move {
+      let request = ListObjectsRequest {
+        bucket: bucket.to_string(),
+        prefix: prefix.map(str::to_string),
+        delimiter: Some(delimiter.to_string()),
+        page_token,
+        ..Default::default()
+      };
+      let response = client.list_objects(&request).await.map_err(|err| authenticated_list_error(bucket, err))?;
+      Ok(prefix_page(response))
+    })
+    .await;
+    match listing {
+      Ok((prefixes, page_count)) => {
+        tracing::info!(
+          "GCS authenticated client found {} prefixes across {} pages in bucket '{}'",
+          prefixes.len(),
+          page_count,
+          bucket
+        );
+        Ok(prefixes)
+      }
+      Err(err) if is_auth_failure(&err) => {
+        self.log_downgrade(bucket, "prefix listing", &err);
+        self.list_prefixes_anonymous(bucket, prefix, delimiter).await
       }
+      Err(err) => Err(err),
     }
   }
+}
 
-  fn provider_name(&self) -> &'static str {
-    "Google Cloud Storage"
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn mock_pages() -> Vec<ListPage> {
+    vec![
+      (vec!["a.json".to_string(), "b.json".to_string()], Some("page-2".to_string())),
+      (vec!["c.json".to_string()], Some("page-3".to_string())),
+      (vec!["d.json".to_string()], None),
+    ]
+  }
+
+  #[tokio::test]
+  async fn test_collect_pages_follows_tokens() {
+    let pages = mock_pages();
+    let mut requested_tokens = Vec::new();
+
+    let (objects, page_count) = collect_pages("ledgers", None, |page_token| {
+      let page = pages[requested_tokens.len()].clone();
+      requested_tokens.push(page_token);
+      async move { Ok(page) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(objects, vec!["a.json", "b.json", "c.json", "d.json"]);
+    assert_eq!(page_count, 3);
+    assert_eq!(requested_tokens, vec![None, Some("page-2".to_string()), Some("page-3".to_string())]);
+  }
+
+  #[tokio::test]
+  async fn test_collect_pages_stops_at_max_pages() {
+    let pages = mock_pages();
+    let mut fetched = 0;
+
+    let (objects, page_count) = collect_pages("ledgers", Some(2), |_| {
+      let page = pages[fetched].clone();
+      fetched += 1;
+      async move { Ok(page) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(objects, vec!["a.json", "b.json", "c.json"]);
+    assert_eq!(page_count, 2);
+  }
+
+  #[tokio::test]
+  async fn test_paginated_listing_streams_lazily() {
+    let pages = mock_pages();
+    let mut requested_tokens = Vec::new();
+
+    let mut keys = flatten_pages(paginate(|page_token| {
+      let page = pages[requested_tokens.len()].clone();
+      requested_tokens.push(page_token);
+      async move { Ok(page) }
+    }));
+    assert_eq!(keys.try_next().await.unwrap().as_deref(), Some("a.json"));
+    assert_eq!(keys.try_next().await.unwrap().as_deref(), Some("b.json"));
+    // Stopping after the first page's keys never requests the second page.
+    drop(keys);
+    assert_eq!(requested_tokens, vec![None]);
+
+    requested_tokens.clear();
+    let keys: Vec<String> = flatten_pages(paginate(|page_token| {
+      let page = pages[requested_tokens.len()].clone();
+      requested_tokens.push(page_token);
+      async move { Ok(page) }
+    }))
+    .try_collect()
+    .await
+    .unwrap();
+    assert_eq!(keys, vec!["a.json", "b.json", "c.json", "d.json"]);
+    assert_eq!(requested_tokens, vec![None, Some("page-2".to_string()), Some("page-3".to_string())]);
+  }
+
+  #[test]
+  fn test_is_auth_failure() {
+    assert!(is_auth_failure(&"HTTP status client error (401 Unauthorized)"));
+    assert!(is_auth_failure(&anyhow!("GCS bucket 'ledgers' requires authentication. Error: 403 Forbidden")));
+    assert!(!is_auth_failure(&"HTTP status client error (404 Not Found)"));
+    assert!(is_not_found_failure(&"HTTP status client error (404 Not Found)"));
+  }
+
+  #[test]
+  fn test_object_url_encodes_name() {
+    let url = object_url("mina-staking-ledgers", "staking/epoch=55/ledger.json");
+    assert_eq!(url, format!("{JSON_API_URL}/b/mina-staking-ledgers/o/staking%2Fepoch%3D55%2Fledger.json"));
+    // The encoded name must survive URL parsing untouched.
+    let parsed = reqwest::Url::parse(&url).unwrap();
+    assert_eq!(parsed.path(), "/storage/v1/b/mina-staking-ledgers/o/staking%2Fepoch%3D55%2Fledger.json");
+
+    // Names that look encoded are encoded again, since '%' is part of the name.
+    assert!(object_url("b", "a%2Fb c+d.json").ends_with("/o/a%252Fb%20c%2Bd.json"));
+  }
+
+  #[test]
+  fn test_list_url_prefix_round_trip() {
+    let url = list_url("ledgers", Some("staking/epoch=55/"), None, Some("tok/en=="));
+    assert_eq!(
+      url,
+      format!("{JSON_API_URL}/b/ledgers/o?maxResults=1000&prefix=staking%2Fepoch%3D55%2F&pageToken=tok%2Fen%3D%3D")
+    );
+    let parsed = reqwest::Url::parse(&url).unwrap();
+    let query: Vec<(String, String)> = parsed.query_pairs().into_owned().collect();
+    assert!(query.contains(&("prefix".to_string(), "staking/epoch=55/".to_string())));
+    assert!(query.contains(&("pageToken".to_string(), "tok/en==".to_string())));
+
+    // Listed names come back unencoded and can be used as keys as-is.
+    let response: GcsListResponse =
+      serde_json::from_str(r#"{"items": [{"name": "staking/epoch=55/ledger.json"}]}"#).unwrap();
+    let name = &response.items.unwrap()[0].name;
+    assert_eq!(name, "staking/epoch=55/ledger.json");
+    assert!(object_url("ledgers", name).ends_with("/o/staking%2Fepoch%3D55%2Fledger.json"));
+  }
+
+  #[test]
+  fn test_list_url_delimiter() {
+    let url = list_url("ledgers", Some("staking/"), Some("/"), None);
+    assert_eq!(url, format!("{JSON_API_URL}/b/ledgers/o?maxResults=1000&prefix=staking%2F&delimiter=%2F"));
+
+    let response: GcsListResponse =
+      serde_json::from_str(r#"{"prefixes": ["staking/epoch=55/", "staking/epoch=56/"]}"#).unwrap();
+    assert!(response.items.is_none());
+    assert_eq!(response.prefixes.unwrap(), ["staking/epoch=55/", "staking/epoch=56/"]);
+  }
+
+  #[tokio::test]
+  async fn test_collect_pages_authenticated_prefixes() {
+    // Delimited listings may repeat a prefix on the next page.
+    let responses = [
+      ListObjectsResponse {
+        prefixes: Some(vec!["staking/epoch=56/".to_string(), "staking/epoch=55/".to_string()]),
+        items: None,
+        next_page_token: Some("page-2".to_string()),
+      },
+      ListObjectsResponse {
+        prefixes: Some(vec!["staking/epoch=56/".to_string(), "staking/epoch=57/".to_string()]),
+        items: None,
+        next_page_token: None,
+      },
+    ];
+    let mut requested = Vec::new();
+
+    let (prefixes, page_count) = collect_pages("ledgers", None, |page_token| {
+      let response = responses[requested.len()].clone();
+      requested.push(page_token);
+      async move { Ok(prefix_page(response)) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(prefixes, ["staking/epoch=55/", "staking/epoch=56/", "staking/epoch=57/"]);
+    assert_eq!(page_count, 2);
+    assert_eq!(requested, [None, Some("page-2".to_string())]);
+  }
+
+  #[tokio::test]
+  async fn test_collect_pages_sorts_objects() {
+    let pages = [
+      (vec!["staking-2-b.json".to_string(), "staking-10-c.json".to_string()], Some("page-2".to_string())),
+      (vec!["staking-1-a.json".to_string()], None),
+    ];
+    let mut fetched = 0;
+
+    let (objects, _) = collect_pages("ledgers", None, |_| {
+      let page = pages[fetched].clone();
+      fetched += 1;
+      async move { Ok(page) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(objects, vec!["staking-1-a.json", "staking-10-c.json", "staking-2-b.json"]);
+  }
+
+  #[tokio::test]
+  async fn test_collect_pages_dedups_boundary_objects() {
+    let pages = [
+      (vec!["a.json".to_string(), "b.json".to_string()], Some("page-2".to_string())),
+      (vec!["b.json".to_string(), "c.json".to_string()], None),
+    ];
+    let mut fetched = 0;
+
+    let (objects, page_count) = collect_pages("ledgers", None, |_| {
+      let page = pages[fetched].clone();
+      fetched += 1;
+      async move { Ok(page) }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(objects, vec!["a.json", "b.json", "c.json"]);
+    assert_eq!(page_count, 2);
   }
 }
@@ -0,0 +1,163 @@
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{Response, StatusCode, header::RANGE};
+
+use super::{ObjectNotFound, RetryPolicy, StorageCapabilities, StorageProvider, range_header, slice_range};
+
+/// Read-only provider for ledgers served by a plain static web server. Objects
+/// are fetched from `<base_url>/<key>`; the bucket name is ignored.
+///
+/// Listing needs an index: a JSON array of object keys served at `index_url`.
+pub struct HttpProvider {
+  client: reqwest::Client,
+  base_url: String,
+  index_url: Option<String>,
+  retry_policy: RetryPolicy,
+}
+
+impl HttpProvider {
+  pub fn new(client: reqwest::Client, base_url: &str, index_url: Option<&str>) -> Self {
+    HttpProvider {
+      client,
+      base_url: base_url.trim_end_matches('/').to_string(),
+      index_url: index_url.map(str::to_string),
+      retry_policy: RetryPolicy::default(),
+    }
+  }
+
+  pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+    self.retry_policy = retry_policy;
+    self
+  }
+
+  fn object_url(&self, key: &str) -> String {
+    let path = key.split('/').map(|segment| urlencoding::encode(segment).into_owned()).collect::<Vec<_>>().join("/");
+    format!("{}/{}", self.base_url, path)
+  }
+
+  async fn download(&self, bucket: &str, key: &str, range: Option<(u64, Option<u64>)>) -> Result<Bytes> {
+    let url = self.object_url(key);
+    let response = self
+      .retry_policy
+      .send(|| {
+        let request = self.client.get(&url);
+        match range {
+          Some((start, end)) => request.header(RANGE, range_header(start, end)),
+          None => request,
+        }
+      })
+      .await
+      .map_err(|err| anyhow!("Failed to download '{}': {}", url, err))?;
+    if response.status() == StatusCode::NOT_FOUND {
+      return Err(ObjectNotFound::new(bucket, key).into());
+    }
+    let response = check_status(response, &url)?;
+    let status = response.status();
+    ranged_body(status, response.bytes().await?, range)
+  }
+}
+
+/// The requested range of a downloaded body. A server that ignores `Range`
+/// answers 200 with the whole object, which is cut down here.
+fn ranged_body(status: StatusCode, body: Bytes, range: Option<(u64, Option<u64>)>) -> Result<Bytes> {
+  match range {
+    Some((start, end)) if status != StatusCode::PARTIAL_CONTENT => slice_range(body, start, end),
+    _ => Ok(body),
+  }
+}
+
+fn check_status(response: Response, url: &str) -> Result<Response> {
+  if !response.status().is_success() {
+    bail!("Failed to download '{}': HTTP {}", url, response.status());
+  }
+  Ok(response)
+}
+
+/// Keys from an index document starting with `prefix`, sorted.
+fn parse_index(bytes: &[u8], prefix: Option<&str>) -> Result<Vec<String>> {
+  let keys: Vec<String> =
+    serde_json::from_slice(bytes).map_err(|err| anyhow!("Index must be a JSON array of object keys: {}", err))?;
+  let mut keys: Vec<String> =
+    keys.into_iter().filter(|key| prefix.map_or(true, |prefix| key.starts_with(prefix))).collect();
+  keys.sort();
+  Ok(keys)
+}
+
+#[async_trait]
+impl StorageProvider for HttpProvider {
+  async fn list_objects(&self, _bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    let Some(index_url) = &self.index_url else {
+      bail!("HTTP storage can't list objects without an index; set HTTP_INDEX_URL");
+    };
+    let response = self
+      .retry_policy
+      .send(|| self.client.get(index_url))
+      .await
+      .map_err(|err| anyhow!("Failed to fetch index '{}': {}", index_url, err))?;
+    parse_index(&check_status(response, index_url)?.bytes().await?, prefix)
+  }
+
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    self.download(bucket, key, None).await
+  }
+
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    self.download(bucket, key, Some((start, end))).await
+  }
+
+  fn provider_name(&self) -> &'static str {
+    "HTTP"
+  }
+
+  fn capabilities(&self) -> StorageCapabilities {
+    StorageCapabilities { can_write: false, can_head: false, can_range: true, can_stream: false }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_object_url() {
+    let provider = HttpProvider::new(reqwest::Client::new(), "https://ledgers.example.com/mainnet/", None);
+    assert_eq!(
+      provider.object_url("staking-epoch-1-jxA.json"),
+      "https://ledgers.example.com/mainnet/staking-epoch-1-jxA.json"
+    );
+    assert_eq!(provider.object_url("ledgers/a b.json"), "https://ledgers.example.com/mainnet/ledgers/a%20b.json");
+  }
+
+  #[tokio::test]
+  async fn test_copy_object_read_only() {
+    let provider = HttpProvider::new(reqwest::Client::new(), "http://127.0.0.1:9/", None);
+    let err = provider.copy_object("ledgers", "pending/1.json", "final/1.json").await.unwrap_err();
+    assert!(err.to_string().contains("read-only"), "{err}");
+  }
+
+  #[test]
+  fn test_ranged_body() {
+    let body = || Bytes::from_static(b"0123456789");
+    assert_eq!(ranged_body(StatusCode::OK, body(), Some((2, Some(4)))).unwrap(), "234");
+    assert_eq!(ranged_body(StatusCode::OK, body(), Some((8, None))).unwrap(), "89");
+    let partial = Bytes::from_static(b"234");
+    assert_eq!(ranged_body(StatusCode::PARTIAL_CONTENT, partial, Some((2, Some(4)))).unwrap(), "234");
+    assert_eq!(ranged_body(StatusCode::OK, body(), None).unwrap(), body());
+    assert!(ranged_body(StatusCode::OK, body(), Some((10, None))).is_err());
+  }
+
+  #[test]
+  fn test_parse_index() {
+    let index = br#"["staking-2-b.json", "other.txt", "staking-1-a.json"]"#;
+    assert_eq!(parse_index(index, Some("staking-")).unwrap(), ["staking-1-a.json", "staking-2-b.json"]);
+    assert_eq!(parse_index(index, None).unwrap().len(), 3);
+    assert!(parse_index(br#"{"keys": []}"#, None).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_list_objects_requires_index() {
+    let provider = HttpProvider::new(reqwest::Client::new(), "https://ledgers.example.com", None);
+    assert!(provider.list_objects("ledgers", None).await.unwrap_err().to_string().contains("HTTP_INDEX_URL"));
+  }
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// User agent sent to storage and manifest hosts unless overridden.
+pub const DEFAULT_USER_AGENT: &str = concat!("mina-on-chain-voting/", env!("CARGO_PKG_VERSION"));
+
+/// TCP keepalive interval for pooled connections, so idle connections survive
+/// NAT and load balancer timeouts between bursts of downloads.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Connection pooling and identification for the `reqwest` clients used by the
+/// storage providers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientConfig {
+  /// How long an idle connection is kept for reuse.
+  pub pool_idle_timeout: Duration,
+  /// Idle connections kept per host.
+  pub pool_max_idle_per_host: usize,
+  pub user_agent: String,
+}
+
+impl Default for HttpClientConfig {
+  fn default() -> Self {
+    HttpClientConfig {
+      pool_idle_timeout: Duration::from_secs(90),
+      pool_max_idle_per_host: 16,
+      user_agent: DEFAULT_USER_AGENT.to_string(),
+    }
+  }
+}
+
+impl HttpClientConfig {
+  pub fn build_client(&self) -> Result<reqwest::Client> {
+    let client = reqwest::Client::builder()
+      .pool_idle_timeout(self.pool_idle_timeout)
+      .pool_max_idle_per_host(self.pool_max_idle_per_host)
+      .tcp_keepalive(TCP_KEEPALIVE)
+      .user_agent(&self.user_agent)
+      .build()?;
+    Ok(client)
+  }
+}
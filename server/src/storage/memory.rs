@@ -0,0 +1,159 @@
+use std::{
+  collections::{BTreeMap, HashMap, hash_map::DefaultHasher},
+  hash::{Hash, Hasher},
+  sync::RwLock,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+
+use super::{ObjectMetadata, ObjectNotFound, StorageCapabilities, StorageProvider};
+
+/// In-memory storage provider holding objects per bucket. Used in tests and
+/// for local experiments that shouldn't touch a real bucket.
+#[derive(Default)]
+pub struct MemoryProvider {
+  buckets: RwLock<HashMap<String, BTreeMap<String, Bytes>>>,
+}
+
+impl MemoryProvider {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_object(self, bucket: &str, key: &str, bytes: impl Into<Bytes>) -> Self {
+    self.insert(bucket, key, bytes);
+    self
+  }
+
+  pub fn insert(&self, bucket: &str, key: &str, bytes: impl Into<Bytes>) {
+    let mut buckets = self.buckets.write().expect("memory provider lock poisoned");
+    buckets.entry(bucket.to_string()).or_default().insert(key.to_string(), bytes.into());
+  }
+}
+
+#[async_trait]
+impl StorageProvider for MemoryProvider {
+  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    let buckets = self.buckets.read().expect("memory provider lock poisoned");
+    let objects = buckets
+      .get(bucket)
+      .map(|objects| {
+        objects.keys().filter(|key| prefix.map_or(true, |prefix| key.starts_with(prefix))).cloned().collect()
+      })
+      .unwrap_or_default();
+    Ok(objects)
+  }
+
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    let buckets = self.buckets.read().expect("memory provider lock poisoned");
+    buckets
+      .get(bucket)
+      .and_then(|objects| objects.get(key))
+      .cloned()
+      .ok_or_else(|| ObjectNotFound::new(bucket, key).into())
+  }
+
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    let bytes = self.get_object(bucket, key).await?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(ObjectMetadata { size: bytes.len() as u64, etag: Some(format!("{:016x}", hasher.finish())) })
+  }
+
+  async fn put_object(&self, bucket: &str, key: &str, bytes: Bytes) -> Result<()> {
+    self.insert(bucket, key, bytes);
+    Ok(())
+  }
+
+  fn provider_name(&self) -> &'static str {
+    "Memory"
+  }
+
+  fn capabilities(&self) -> StorageCapabilities {
+    StorageCapabilities { can_write: true, can_head: true, can_range: true, can_stream: false }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_count_objects_default() {
+    let provider = MemoryProvider::new()
+      .with_object("ledgers", "staking-1-a.json", "[]")
+      .with_object("ledgers", "staking-2-b.json", "[]")
+      .with_object("ledgers", "other.txt", "");
+
+    assert_eq!(provider.count_objects("ledgers", None).await.unwrap(), 3);
+    assert_eq!(provider.count_objects("ledgers", Some("staking-")).await.unwrap(), 2);
+    assert_eq!(provider.count_objects("missing", None).await.unwrap(), 0);
+  }
+
+  #[tokio::test]
+  async fn test_list_objects_sorted() {
+    let provider = MemoryProvider::new()
+      .with_object("ledgers", "staking-2-b.json", "[]")
+      .with_object("ledgers", "staking-10-c.json", "[]")
+      .with_object("ledgers", "staking-1-a.json", "[]");
+
+    let objects = provider.list_objects("ledgers", Some("staking-")).await.unwrap();
+    assert_eq!(objects, ["staking-1-a.json", "staking-10-c.json", "staking-2-b.json"]);
+  }
+
+  #[tokio::test]
+  async fn test_get_object_range_default() {
+    let provider = MemoryProvider::new().with_object("ledgers", "ledger.json", "0123456789");
+
+    assert_eq!(provider.get_object_range("ledgers", "ledger.json", 2, Some(4)).await.unwrap(), "234");
+    assert_eq!(provider.get_object_range("ledgers", "ledger.json", 7, None).await.unwrap(), "789");
+    assert_eq!(provider.get_object_range("ledgers", "ledger.json", 8, Some(100)).await.unwrap(), "89");
+    assert!(provider.get_object_range("ledgers", "ledger.json", 10, None).await.is_err());
+    assert!(provider.get_object_range("ledgers", "ledger.json", 5, Some(4)).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_head_object() {
+    let provider = MemoryProvider::new().with_object("ledgers", "a.json", "[]").with_object("ledgers", "b.json", "[1]");
+
+    let a = provider.head_object("ledgers", "a.json").await.unwrap();
+    let b = provider.head_object("ledgers", "b.json").await.unwrap();
+    assert_eq!(a.size, 2);
+    assert_ne!(a.etag, b.etag);
+    assert_eq!(a, provider.head_object("ledgers", "a.json").await.unwrap());
+    assert!(provider.head_object("ledgers", "c.json").await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_put_object() {
+    let provider = MemoryProvider::new();
+    provider.put_object("results", "1.json", Bytes::from_static(b"{}")).await.unwrap();
+    assert_eq!(provider.get_object("results", "1.json").await.unwrap(), "{}");
+  }
+
+  #[tokio::test]
+  async fn test_list_prefixes_default() {
+    let provider = MemoryProvider::new()
+      .with_object("ledgers", "mainnet/epoch=55/a.json", "[]")
+      .with_object("ledgers", "mainnet/epoch=55/b.json", "[]")
+      .with_object("ledgers", "mainnet/epoch=56/a.json", "[]")
+      .with_object("ledgers", "mainnet/index.json", "{}");
+    assert_eq!(provider.list_prefixes("ledgers", Some("mainnet/"), "/").await.unwrap(), [
+      "mainnet/epoch=55/",
+      "mainnet/epoch=56/"
+    ]);
+    assert_eq!(provider.list_prefixes("ledgers", None, "/").await.unwrap(), ["mainnet/"]);
+    assert!(provider.list_prefixes("ledgers", Some("devnet/"), "/").await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_copy_object_default() {
+    let provider = MemoryProvider::new().with_object("results", "pending/1.json", "{}");
+    provider.copy_object("results", "pending/1.json", "final/1.json").await.unwrap();
+    assert_eq!(provider.list_objects("results", None).await.unwrap(), ["final/1.json", "pending/1.json"]);
+    assert_eq!(provider.get_object("results", "final/1.json").await.unwrap(), "{}");
+    assert!(provider.copy_object("results", "pending/2.json", "final/2.json").await.is_err());
+  }
+}
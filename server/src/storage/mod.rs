@@ -1,17 +1,225 @@
-use anyhow::Result;
+use std::{
+  collections::{BTreeSet, HashSet},
+  future::Future,
+};
+
+use anyhow::{Result, bail};
 use bytes::Bytes;
+use futures_util::{
+  Stream, StreamExt, TryStreamExt,
+  stream::{self, BoxStream},
+};
+use thiserror::Error;
 
 pub mod aws_s3;
+pub mod circuit_breaker;
+pub mod directory_markers;
 pub mod factory;
 pub mod gcs;
+pub mod http;
+pub mod http_client;
+pub mod memory;
+pub mod recording;
+pub mod retry;
 
 #[async_trait::async_trait]
 pub trait StorageProvider {
+  /// Lists the object keys under `prefix`, in lexicographic order.
   async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>>;
   async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes>;
   fn provider_name(&self) -> &'static str;
+  fn capabilities(&self) -> StorageCapabilities;
+
+  /// Streams the object keys under `prefix`, fetching the listing page by
+  /// page as the stream is polled, so a caller looking for one object can
+  /// stop once it turns up. Keys come in listing order, which is
+  /// lexicographic for S3 and GCS, and may repeat across pages.
+  ///
+  /// The default implementation yields the full listing at once; providers
+  /// that can paginate should override it.
+  fn list_objects_stream<'a>(&'a self, bucket: &'a str, prefix: Option<&'a str>) -> BoxStream<'a, Result<String>> {
+    flatten_pages(stream::once(self.list_objects(bucket, prefix)))
+  }
+
+  /// Counts the objects under `prefix` without keeping their names around.
+  ///
+  /// The default implementation collects the full listing; providers that can
+  /// paginate should override it to count page by page.
+  async fn count_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<usize> {
+    Ok(self.list_objects(bucket, prefix).await?.len())
+  }
+
+  /// Lists the distinct key prefixes under `prefix` that end at the next
+  /// `delimiter`, like the common prefixes of a delimited S3 or GCS listing,
+  /// in lexicographic order. With `/`, these are the "directories" under
+  /// `prefix`.
+  ///
+  /// The default implementation derives them from the full listing;
+  /// providers whose API takes a delimiter should override it.
+  async fn list_prefixes(&self, bucket: &str, prefix: Option<&str>, delimiter: &str) -> Result<Vec<String>> {
+    let keys = self.list_objects(bucket, prefix).await?;
+    Ok(common_prefixes(&keys, prefix.unwrap_or_default(), delimiter))
+  }
+
+  /// Fetches the bytes from `start` to `end` (inclusive, as in an HTTP
+  /// `Range` header), or to the end of the object when `end` is `None`.
+  ///
+  /// The default implementation downloads the whole object and slices it.
+  async fn get_object_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>) -> Result<Bytes> {
+    slice_range(self.get_object(bucket, key).await?, start, end)
+  }
+
+  /// Fetches an object's metadata without downloading it. Only available
+  /// when `capabilities().can_head` is set.
+  async fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectMetadata> {
+    bail!("{} does not support head_object (object '{}' in bucket '{}')", self.provider_name(), key, bucket)
+  }
+
+  /// Uploads `bytes` as `key`, replacing any existing object. Only available
+  /// when `capabilities().can_write` is set.
+  async fn put_object(&self, bucket: &str, key: &str, _bytes: Bytes) -> Result<()> {
+    bail!("{} does not support put_object (object '{}' in bucket '{}')", self.provider_name(), key, bucket)
+  }
+
+  /// State of the circuit breaker guarding this provider, if there is one.
+  fn circuit_state(&self) -> Option<CircuitState> {
+    None
+  }
+
+  /// Copies `src_key` to `dst_key` within `bucket`, replacing any existing
+  /// object. Only available when `capabilities().can_write` is set.
+  ///
+  /// The default implementation downloads the object and uploads it again;
+  /// providers with a server-side copy should override it.
+  async fn copy_object(&self, bucket: &str, src_key: &str, dst_key: &str) -> Result<()> {
+    ensure_writable(self, bucket, dst_key)?;
+    let bytes = self.get_object(bucket, src_key).await?;
+    self.put_object(bucket, dst_key, bytes).await
+  }
+}
+
+/// Fails unless `provider` can write, naming the object that was to be
+/// written.
+pub(crate) fn ensure_writable<P: StorageProvider + ?Sized>(provider: &P, bucket: &str, key: &str) -> Result<()> {
+  if !provider.capabilities().can_write {
+    bail!("{} is read-only, cannot write '{}' to bucket '{}'", provider.provider_name(), key, bucket);
+  }
+  Ok(())
+}
+
+/// A requested object doesn't exist. Providers fail with it so callers can
+/// tell a missing object from a failing provider.
+#[derive(Debug, Error)]
+#[error("Object '{key}' not found in bucket '{bucket}'")]
+pub struct ObjectNotFound {
+  pub bucket: String,
+  pub key: String,
+}
+
+impl ObjectNotFound {
+  pub fn new(bucket: &str, key: &str) -> Self {
+    ObjectNotFound { bucket: bucket.to_string(), key: key.to_string() }
+  }
+}
+
+/// Whether `err` is, or was caused by, an `ObjectNotFound`.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+  err.chain().any(|cause| cause.is::<ObjectNotFound>())
+}
+
+/// Streams the pages of a paginated listing, fetching each only once the
+/// previous one is consumed. `fetch_page` takes the token of the page to
+/// fetch, `None` for the first, and returns its names and the token of the
+/// next page, if any.
+pub(crate) fn paginate<'a, F, Fut>(fetch_page: F) -> impl Stream<Item = Result<Vec<String>>> + Send + 'a
+where
+  F: FnMut(Option<String>) -> Fut + Send + 'a,
+  Fut: Future<Output = Result<(Vec<String>, Option<String>)>> + Send + 'a,
+{
+  stream::try_unfold((fetch_page, Some(None)), |(mut fetch_page, page_token)| async move {
+    let Some(page_token) = page_token else {
+      return Ok(None);
+    };
+    let (names, next_page_token) = fetch_page(page_token).await?;
+    Ok::<_, anyhow::Error>(Some((names, (fetch_page, next_page_token.map(Some)))))
+  })
+}
+
+/// Flattens a stream of listing pages into a stream of keys.
+pub(crate) fn flatten_pages<'a>(
+  pages: impl Stream<Item = Result<Vec<String>>> + Send + 'a,
+) -> BoxStream<'a, Result<String>> {
+  pages.map_ok(|keys| stream::iter(keys.into_iter().map(Ok))).try_flatten().boxed()
+}
+
+/// Drops repeated keys from a listing, keeping the first occurrence of each.
+/// A key can show up on two pages of an eventually consistent listing.
+pub(crate) fn dedup_keys(provider: &str, bucket: &str, keys: Vec<String>) -> Vec<String> {
+  let total = keys.len();
+  let mut seen = HashSet::with_capacity(total);
+  let keys: Vec<String> = keys.into_iter().filter(|key| seen.insert(key.clone())).collect();
+  if keys.len() < total {
+    tracing::warn!("{} listing of bucket '{}' repeated {} object keys", provider, bucket, total - keys.len());
+  }
+  keys
+}
+
+/// The prefixes of `keys` that extend `prefix` up to and including the next
+/// `delimiter`, sorted and without duplicates. Keys without a further
+/// delimiter aren't under a common prefix.
+pub(crate) fn common_prefixes(keys: &[String], prefix: &str, delimiter: &str) -> Vec<String> {
+  let prefixes: BTreeSet<String> = keys
+    .iter()
+    .filter_map(|key| {
+      let rest = key.strip_prefix(prefix)?;
+      let end = rest.find(delimiter)? + delimiter.len();
+      Some(format!("{prefix}{}", &rest[.. end]))
+    })
+    .collect();
+  prefixes.into_iter().collect()
+}
+
+/// Formats an HTTP `Range` header value for an inclusive byte span.
+pub(crate) fn range_header(start: u64, end: Option<u64>) -> String {
+  match end {
+    Some(end) => format!("bytes={start}-{end}"),
+    None => format!("bytes={start}-"),
+  }
+}
+
+fn slice_range(bytes: Bytes, start: u64, end: Option<u64>) -> Result<Bytes> {
+  let len = bytes.len() as u64;
+  if start >= len || end.is_some_and(|end| end < start) {
+    bail!("Invalid range {} for object of {} bytes", range_header(start, end), len);
+  }
+  let end = end.map_or(len, |end| (end + 1).min(len));
+  Ok(bytes.slice(start as usize .. end as usize))
+}
+
+/// Metadata returned by `StorageProvider::head_object`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMetadata {
+  pub size: u64,
+  pub etag: Option<String>,
+}
+
+/// Operations a provider supports in its current mode, so callers can skip
+/// features instead of failing at runtime (e.g. anonymous access can't write).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageCapabilities {
+  pub can_write: bool,
+  pub can_head: bool,
+  pub can_range: bool,
+  pub can_stream: bool,
 }
 
 pub use aws_s3::AwsS3Provider;
+pub use circuit_breaker::{CircuitBreaker, CircuitState};
+pub use directory_markers::SkipDirectoryMarkers;
 pub use factory::create_storage_provider;
 pub use gcs::GcsProvider;
+pub use http::HttpProvider;
+pub use http_client::{DEFAULT_USER_AGENT, HttpClientConfig};
+pub use memory::MemoryProvider;
+pub use recording::{RecordingMode, RecordingProvider};
+pub use retry::RetryPolicy;
@@ -0,0 +1,165 @@
+use std::{
+  fmt::Write as _,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{StorageCapabilities, StorageProvider};
+
+/// Whether a `RecordingProvider` captures a real bucket or plays a capture
+/// back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RecordingMode {
+  /// Forward to the real provider and write what it returns to fixtures.
+  Record,
+  /// Serve from fixtures only, failing on requests that weren't recorded.
+  Replay,
+}
+
+/// Records the listings and objects a real provider returns to a fixtures
+/// directory, or replays them from it, so a problematic bucket state can be
+/// captured once and tested against deterministically.
+///
+/// Fixtures live under `<dir>/<bucket>/`: each listing as
+/// `list-<digest>.json` and each object's bytes as `object-<digest>.bin`,
+/// with the digest taken over the prefix or key. Only successful responses
+/// are recorded.
+pub struct RecordingProvider {
+  inner: Option<Arc<dyn StorageProvider + Send + Sync>>,
+  dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListingFixture {
+  prefix: Option<String>,
+  keys: Vec<String>,
+}
+
+impl RecordingProvider {
+  pub fn record(inner: Arc<dyn StorageProvider + Send + Sync>, dir: impl Into<PathBuf>) -> Self {
+    RecordingProvider { inner: Some(inner), dir: dir.into() }
+  }
+
+  pub fn replay(dir: impl Into<PathBuf>) -> Self {
+    RecordingProvider { inner: None, dir: dir.into() }
+  }
+
+  pub fn mode(&self) -> RecordingMode {
+    match self.inner {
+      Some(_) => RecordingMode::Record,
+      None => RecordingMode::Replay,
+    }
+  }
+
+  fn fixture_path(&self, bucket: &str, kind: &str, name: &str, extension: &str) -> PathBuf {
+    let digest = Sha256::digest(name).iter().take(16).fold(String::new(), |mut digest, byte| {
+      let _ = write!(digest, "{byte:02x}");
+      digest
+    });
+    self.dir.join(bucket).join(format!("{kind}-{digest}.{extension}"))
+  }
+
+  fn listing_path(&self, bucket: &str, prefix: Option<&str>) -> PathBuf {
+    // Distinguishes no prefix from an empty one, which some providers treat
+    // differently.
+    let name = prefix.map_or_else(|| "\0".to_string(), |prefix| format!("/{prefix}"));
+    self.fixture_path(bucket, "list", &name, "json")
+  }
+
+  fn object_path(&self, bucket: &str, key: &str) -> PathBuf {
+    self.fixture_path(bucket, "object", key, "bin")
+  }
+}
+
+async fn write_fixture(path: &Path, contents: &[u8]) -> Result<()> {
+  if let Some(parent) = path.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+  tokio::fs::write(path, contents).await.with_context(|| format!("Failed to write fixture {}", path.display()))
+}
+
+async fn read_fixture(path: &Path, request: &str) -> Result<Vec<u8>> {
+  tokio::fs::read(path).await.with_context(|| format!("No recorded response for {} at {}", request, path.display()))
+}
+
+#[async_trait]
+impl StorageProvider for RecordingProvider {
+  async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+    let path = self.listing_path(bucket, prefix);
+    match &self.inner {
+      Some(inner) => {
+        let keys = inner.list_objects(bucket, prefix).await?;
+        let fixture = ListingFixture { prefix: prefix.map(str::to_string), keys };
+        write_fixture(&path, &serde_json::to_vec_pretty(&fixture)?).await?;
+        Ok(fixture.keys)
+      }
+      None => {
+        let request = format!("listing of bucket '{}' under {:?}", bucket, prefix);
+        let fixture: ListingFixture = serde_json::from_slice(&read_fixture(&path, &request).await?)?;
+        Ok(fixture.keys)
+      }
+    }
+  }
+
+  async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+    let path = self.object_path(bucket, key);
+    match &self.inner {
+      Some(inner) => {
+        let bytes = inner.get_object(bucket, key).await?;
+        write_fixture(&path, &bytes).await?;
+        Ok(bytes)
+      }
+      None => Ok(read_fixture(&path, &format!("object '{}' in bucket '{}'", key, bucket)).await?.into()),
+    }
+  }
+
+  fn provider_name(&self) -> &'static str {
+    "recording"
+  }
+
+  /// The same in both modes, so a replay takes the code paths the recording
+  /// did: ranges, counts and delimited listings are derived from recorded
+  /// objects and listings, and nothing is written to the bucket.
+  fn capabilities(&self) -> StorageCapabilities {
+    StorageCapabilities { can_write: false, can_head: false, can_range: true, can_stream: false }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::MemoryProvider;
+
+  #[tokio::test]
+  async fn test_record_and_replay() {
+    let dir = std::env::temp_dir().join(format!("ocv-recording-{}", std::process::id()));
+    let memory = MemoryProvider::new()
+      .with_object("ledgers", "mainnet/staking-epoch-1-jxA.json", "[1]")
+      .with_object("ledgers", "mainnet/epoch-2/staking-epoch-2-jxB.json", "[2]")
+      .with_object("ledgers", "devnet/staking-epoch-1-jxC.json", "[3]");
+    let recorder = RecordingProvider::record(Arc::new(memory), &dir);
+    assert_eq!(recorder.mode(), RecordingMode::Record);
+    let listing = recorder.list_objects("ledgers", Some("mainnet/")).await.unwrap();
+    let everything = recorder.list_objects("ledgers", None).await.unwrap();
+    recorder.get_object("ledgers", "mainnet/staking-epoch-1-jxA.json").await.unwrap();
+
+    let replay = RecordingProvider::replay(&dir);
+    assert_eq!(replay.list_objects("ledgers", Some("mainnet/")).await.unwrap(), listing);
+    assert_eq!(replay.list_objects("ledgers", None).await.unwrap(), everything);
+    assert_eq!(replay.get_object("ledgers", "mainnet/staking-epoch-1-jxA.json").await.unwrap(), "[1]");
+    assert_eq!(replay.list_prefixes("ledgers", Some("mainnet/"), "/").await.unwrap(), ["mainnet/epoch-2/"]);
+    assert_eq!(replay.get_object_range("ledgers", "mainnet/staking-epoch-1-jxA.json", 1, None).await.unwrap(), "1]");
+
+    let err = replay.get_object("ledgers", "devnet/staking-epoch-1-jxC.json").await.unwrap_err();
+    assert!(err.to_string().starts_with("No recorded response for object 'devnet/staking-epoch-1-jxC.json'"));
+    assert!(replay.list_objects("ledgers", Some("devnet/")).await.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode, header::RETRY_AFTER};
+
+/// How throttled (429/503) storage requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+  pub max_attempts: u32,
+  /// First delay of the exponential backoff, used when the server doesn't send
+  /// `Retry-After`.
+  pub base_delay: Duration,
+  /// Total time spent sleeping across retries of one request.
+  pub max_total_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    RetryPolicy { max_attempts: 5, base_delay: Duration::from_millis(500), max_total_wait: Duration::from_secs(60) }
+  }
+}
+
+impl RetryPolicy {
+  /// Delay before retry number `attempt` (starting at 0), preferring the
+  /// server-directed `retry_after`. `None` once the attempts or the total wait
+  /// budget are exhausted.
+  pub fn next_delay(&self, attempt: u32, retry_after: Option<Duration>, waited: Duration) -> Option<Duration> {
+    if attempt + 1 >= self.max_attempts {
+      return None;
+    }
+    let delay = retry_after.unwrap_or_else(|| self.base_delay.saturating_mul(2u32.saturating_pow(attempt)));
+    (waited + delay <= self.max_total_wait).then_some(delay)
+  }
+
+  /// Sends the request built by `build`, retrying while the server throttles.
+  /// The last throttled response is returned once retries are exhausted.
+  pub async fn send(&self, build: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+    self.send_with(build, false).await
+  }
+
+  /// Like `send`, but also retries server errors and requests that failed
+  /// to get a response, such as on a dropped connection.
+  pub async fn send_retrying_failures(&self, build: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+    self.send_with(build, true).await
+  }
+
+  async fn send_with(&self, build: impl Fn() -> RequestBuilder, retry_failures: bool) -> reqwest::Result<Response> {
+    let mut waited = Duration::ZERO;
+    let mut attempt = 0;
+    loop {
+      let response = match build().send().await {
+        Ok(response) => response,
+        Err(err) if retry_failures => match self.next_delay(attempt, None, waited) {
+          Some(delay) => {
+            tracing::info!("Request failed ({}), retrying in {:?}", err, delay);
+            tokio::time::sleep(delay).await;
+            waited += delay;
+            attempt += 1;
+            continue;
+          }
+          None => return Err(err),
+        },
+        Err(err) => return Err(err),
+      };
+      let status = response.status();
+      let throttled = status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+      let retryable = throttled || (retry_failures && status.is_server_error());
+      if !retryable {
+        return Ok(response);
+      }
+
+      let retry_after =
+        response.headers().get(RETRY_AFTER).and_then(|value| value.to_str().ok()).and_then(parse_retry_after);
+      let Some(delay) = self.next_delay(attempt, retry_after, waited) else {
+        return Ok(response);
+      };
+      if retry_after.is_some() {
+        tracing::info!("Request got HTTP {}, retrying in {:?} as directed by Retry-After", status, delay);
+      } else {
+        tracing::debug!("Request got HTTP {}, retrying in {:?}", status, delay);
+      }
+      tokio::time::sleep(delay).await;
+      waited += delay;
+      attempt += 1;
+    }
+  }
+}
+
+/// Parses a `Retry-After` value, given either in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+  if let Ok(secs) = value.trim().parse::<u64>() {
+    return Some(Duration::from_secs(secs));
+  }
+  let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+  Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_retry_after() {
+    assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+    assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+    let future = (Utc::now() + chrono::Duration::hours(1)).to_rfc2822();
+    assert!(parse_retry_after(&future).is_some_and(|delay| delay > Duration::from_secs(3500)));
+    assert_eq!(parse_retry_after("soon"), None);
+  }
+
+  #[test]
+  fn test_next_delay() {
+    let policy =
+      RetryPolicy { max_attempts: 4, base_delay: Duration::from_secs(1), max_total_wait: Duration::from_secs(10) };
+
+    assert_eq!(policy.next_delay(0, None, Duration::ZERO), Some(Duration::from_secs(1)));
+    assert_eq!(policy.next_delay(2, None, Duration::from_secs(3)), Some(Duration::from_secs(4)));
+    assert_eq!(policy.next_delay(0, Some(Duration::from_secs(7)), Duration::ZERO), Some(Duration::from_secs(7)));
+    // Server-directed delay past the total wait budget.
+    assert_eq!(policy.next_delay(1, Some(Duration::from_secs(9)), Duration::from_secs(7)), None);
+    // Out of attempts.
+    assert_eq!(policy.next_delay(3, None, Duration::ZERO), None);
+  }
+}
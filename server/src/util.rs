@@ -1,7 +1,15 @@
 mod caches;
+mod clock;
+mod cursor;
+mod query_limiter;
+mod request_deadline;
 mod shutdown_signal;
 mod wrapper;
 
-pub use caches::Caches;
+pub use caches::{CachedTally, Caches, LedgerCacheStats};
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use cursor::{decode_cursor, decode_numbered_cursor, encode_cursor, page_after};
+pub use query_limiter::QueryLimiter;
+pub use request_deadline::RequestDeadline;
 pub use shutdown_signal::shutdown_signal;
-pub use wrapper::Wrapper;
+pub use wrapper::{Wrapper, error_response};
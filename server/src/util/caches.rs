@@ -1,24 +1,351 @@
-use std::sync::Arc;
+use std::{
+  collections::HashMap,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+  },
+  time::{Duration, Instant},
+};
 
-use moka::future::Cache as MokaCache;
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use futures_util::{
+  FutureExt,
+  future::{BoxFuture, Shared},
+};
+use moka::{Expiry, future::Cache as MokaCache, policy::EvictionPolicy};
+use serde::Serialize;
 
-use crate::{RankedVote, Vote, VoteWithWeight, ledger::LedgerAccount};
+use crate::{Ledger, RankedVote, RequestDeadline, TallyResult, Vote, VoteWithWeight, storage::StorageProvider};
+
+/// A computed tally and how long it may be served.
+#[derive(Clone)]
+pub struct CachedTally {
+  pub result: Arc<TallyResult>,
+  /// Archive chain tip the tally was computed at.
+  pub chain_tip: i64,
+  /// Expiry of a live tally. Final tallies have none; they stay valid until
+  /// the chain tip moves.
+  pub ttl: Option<Duration>,
+}
+
+struct TallyExpiry;
+
+impl Expiry<usize, CachedTally> for TallyExpiry {
+  fn expire_after_create(&self, _id: &usize, tally: &CachedTally, _created_at: Instant) -> Option<Duration> {
+    tally.ttl
+  }
+}
+
+type ObjectDownload = Shared<BoxFuture<'static, Result<Bytes, Arc<anyhow::Error>>>>;
+
+/// A download in progress and how many calls wait on it.
+struct Flight {
+  id: u64,
+  download: ObjectDownload,
+  waiters: usize,
+}
+
+type Flights = Mutex<HashMap<(String, String), Flight>>;
+
+/// One call's wait on a `Flight`. Dropped, even along with a cancelled call,
+/// it retires the flight if the download finished or nobody else waits on
+/// it, which drops the provider request.
+struct FlightWaiter<'a> {
+  flights: &'a Flights,
+  key: (String, String),
+  id: u64,
+  finished: bool,
+}
+
+impl Drop for FlightWaiter<'_> {
+  fn drop(&mut self) {
+    let mut flights = self.flights.lock().expect("object downloads lock poisoned");
+    // A newer flight may have replaced this one after it finished.
+    let Some(flight) = flights.get_mut(&self.key).filter(|flight| flight.id == self.id) else {
+      return;
+    };
+    flight.waiters -= 1;
+    if self.finished || flight.waiters == 0 {
+      flights.remove(&self.key);
+    }
+  }
+}
 
 #[derive(Clone)]
 pub struct Caches {
   pub votes: MokaCache<String, Arc<Vec<Vote>>>,
   pub votes_weighted: MokaCache<String, Arc<Vec<VoteWithWeight>>>,
-  pub ledger: MokaCache<String, Arc<Vec<LedgerAccount>>>,
+  pub ledger: MokaCache<String, Arc<Ledger>>,
   pub ranked_votes: MokaCache<String, Arc<Vec<RankedVote>>>,
+  /// Bucket object each ledger downloaded by this process came from, by hash.
+  pub ledger_object_keys: MokaCache<String, String>,
+  /// Computed proposal tallies, by proposal id.
+  pub tallies: MokaCache<usize, CachedTally>,
+  /// Bucket object downloads in progress, by bucket and key.
+  object_downloads: Arc<Flights>,
+  next_flight_id: Arc<AtomicU64>,
+  ledger_hits: Arc<AtomicU64>,
+  ledger_misses: Arc<AtomicU64>,
 }
 
 impl Caches {
-  pub fn build() -> Self {
+  pub fn build(ledger_cache_entries: u64) -> Self {
     Self {
       votes: MokaCache::builder().time_to_live(std::time::Duration::from_secs(60 * 5)).build(),
       votes_weighted: MokaCache::builder().time_to_live(std::time::Duration::from_secs(60 * 5)).build(),
-      ledger: MokaCache::builder().time_to_live(std::time::Duration::from_secs(60 * 60 * 12)).build(),
+      ledger: MokaCache::builder()
+        .max_capacity(ledger_cache_entries)
+        .eviction_policy(EvictionPolicy::lru())
+        .eviction_listener(|hash, _, cause| tracing::debug!("Evicted parsed ledger {} from cache: {:?}", hash, cause))
+        .time_to_live(std::time::Duration::from_secs(60 * 60 * 12))
+        .build(),
       ranked_votes: MokaCache::builder().time_to_live(std::time::Duration::from_secs(60 * 5)).build(),
+      ledger_object_keys: MokaCache::builder().max_capacity(1000).build(),
+      tallies: MokaCache::builder().max_capacity(1000).expire_after(TallyExpiry).build(),
+      object_downloads: Arc::new(Mutex::new(HashMap::new())),
+      next_flight_id: Arc::new(AtomicU64::new(0)),
+      ledger_hits: Arc::new(AtomicU64::new(0)),
+      ledger_misses: Arc::new(AtomicU64::new(0)),
+    }
+  }
+
+  /// Looks up a parsed ledger by hash, recording a cache hit or miss.
+  pub async fn get_ledger(&self, hash: &str) -> Option<Arc<Ledger>> {
+    let ledger = self.ledger.get(hash).await;
+    let counter = if ledger.is_some() { &self.ledger_hits } else { &self.ledger_misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+    ledger
+  }
+
+  /// Downloads `key` from `bucket`. Concurrent calls for the same object
+  /// share a single request to the provider. A call gives up once `deadline`
+  /// expires or its future is dropped, and the request is aborted if no other
+  /// call still waits on it.
+  ///
+  /// With `max_bytes`, at most `max_bytes + 1` bytes are read, with a range
+  /// request: enough to tell an object over the limit apart without
+  /// downloading all of it.
+  pub async fn get_object_once(
+    &self,
+    storage: Arc<dyn StorageProvider + Send + Sync>,
+    bucket: &str,
+    key: &str,
+    max_bytes: Option<u64>,
+    deadline: &RequestDeadline,
+  ) -> Result<Bytes> {
+    let flight_key = (bucket.to_string(), key.to_string());
+    let (download, mut waiter) = {
+      let mut flights = self.object_downloads.lock().expect("object downloads lock poisoned");
+      let flight = flights.entry(flight_key.clone()).or_insert_with(|| {
+        let (bucket, key) = flight_key.clone();
+        let download = async move {
+          let bytes = match max_bytes {
+            Some(max_bytes) => storage.get_object_range(&bucket, &key, 0, Some(max_bytes)).await,
+            None => storage.get_object(&bucket, &key).await,
+          };
+          bytes.map_err(Arc::new)
+        };
+        let download = download.boxed().shared();
+        Flight { id: self.next_flight_id.fetch_add(1, Ordering::Relaxed), download, waiters: 0 }
+      });
+      flight.waiters += 1;
+      let waiter = FlightWaiter { flights: &self.object_downloads, key: flight_key, id: flight.id, finished: false };
+      (flight.download.clone(), waiter)
+    };
+    let result = tokio::select! {
+      result = download => Ok(result),
+      err = deadline.expired() => Err(err),
+    };
+    // Whoever finishes first retires the download.
+    waiter.finished = result.is_ok();
+    drop(waiter);
+    match result {
+      Ok(result) => result.map_err(|err| anyhow!("{:#}", err)),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn ledger_stats(&self) -> LedgerCacheStats {
+    LedgerCacheStats {
+      entries: self.ledger.entry_count(),
+      hits: self.ledger_hits.load(Ordering::Relaxed),
+      misses: self.ledger_misses.load(Ordering::Relaxed),
+    }
+  }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedgerCacheStats {
+  pub entries: u64,
+  pub hits: u64,
+  pub misses: u64,
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{sync::atomic::AtomicUsize, time::Duration};
+
+  use async_trait::async_trait;
+
+  use super::*;
+  use crate::{MemoryProvider, StorageCapabilities, TallyError};
+
+  /// Counts `get_object` calls, holding each one long enough to overlap.
+  struct CountingProvider {
+    inner: MemoryProvider,
+    gets: AtomicUsize,
+  }
+
+  #[async_trait]
+  impl StorageProvider for CountingProvider {
+    async fn list_objects(&self, bucket: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+      self.inner.list_objects(bucket, prefix).await
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Bytes> {
+      self.gets.fetch_add(1, Ordering::SeqCst);
+      tokio::time::sleep(Duration::from_millis(50)).await;
+      self.inner.get_object(bucket, key).await
     }
+
+    fn provider_name(&self) -> &'static str {
+      "Counting"
+    }
+
+    fn capabilities(&self) -> StorageCapabilities {
+      self.inner.capabilities()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_get_object_once() {
+    let provider = Arc::new(CountingProvider {
+      inner: MemoryProvider::new().with_object("ledgers", "jxA.json", "[]"),
+      gets: AtomicUsize::new(0),
+    });
+    let caches = Caches::build(1);
+    let unbounded = RequestDeadline::default();
+
+    let (a, b) = tokio::join!(
+      caches.get_object_once(provider.clone(), "ledgers", "jxA.json", None, &unbounded),
+      caches.get_object_once(provider.clone(), "ledgers", "jxA.json", None, &unbounded),
+    );
+    assert_eq!((a.unwrap(), b.unwrap()), (Bytes::from("[]"), Bytes::from("[]")));
+    assert_eq!(provider.gets.load(Ordering::SeqCst), 1);
+
+    // Finished downloads aren't reused.
+    caches.get_object_once(provider.clone(), "ledgers", "jxA.json", None, &unbounded).await.unwrap();
+    assert_eq!(provider.gets.load(Ordering::SeqCst), 2);
+    assert!(caches.get_object_once(provider.clone(), "ledgers", "jxB.json", None, &unbounded).await.is_err());
+    assert_eq!(provider.gets.load(Ordering::SeqCst), 3);
+
+    // A call giving up doesn't abort the download others wait on.
+    let cancelled = RequestDeadline::default();
+    cancelled.cancel();
+    let (a, b) = tokio::join!(
+      caches.get_object_once(provider.clone(), "ledgers", "jxA.json", None, &unbounded),
+      caches.get_object_once(provider.clone(), "ledgers", "jxA.json", None, &cancelled),
+    );
+    assert_eq!(a.unwrap(), Bytes::from("[]"));
+    assert!(matches!(b.unwrap_err().downcast_ref(), Some(TallyError::RequestCancelled)));
+    assert_eq!(provider.gets.load(Ordering::SeqCst), 4);
+  }
+
+  /// Never finishes a download, and counts the downloads dropped.
+  struct HangingProvider {
+    dropped: Arc<AtomicUsize>,
+  }
+
+  struct CountDrop(Arc<AtomicUsize>);
+
+  impl Drop for CountDrop {
+    fn drop(&mut self) {
+      self.0.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  #[async_trait]
+  impl StorageProvider for HangingProvider {
+    async fn list_objects(&self, _bucket: &str, _prefix: Option<&str>) -> Result<Vec<String>> {
+      Ok(Vec::new())
+    }
+
+    async fn get_object(&self, _bucket: &str, _key: &str) -> Result<Bytes> {
+      let _count_drop = CountDrop(self.dropped.clone());
+      std::future::pending().await
+    }
+
+    fn provider_name(&self) -> &'static str {
+      "Hanging"
+    }
+
+    fn capabilities(&self) -> StorageCapabilities {
+      MemoryProvider::new().capabilities()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_get_object_once_cancelled() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let provider = Arc::new(HangingProvider { dropped: dropped.clone() });
+    let caches = Caches::build(1);
+    let deadline = RequestDeadline::default();
+
+    let download = tokio::spawn({
+      let (caches, deadline) = (caches.clone(), deadline.clone());
+      async move { caches.get_object_once(provider, "ledgers", "jxA.json", None, &deadline).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+    deadline.cancel();
+    let err = download.await.unwrap().unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::RequestCancelled)));
+    // The in-flight provider request was dropped along with the download.
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    assert!(caches.object_downloads.lock().unwrap().is_empty());
+
+    let timed_out = RequestDeadline::new(Some(Duration::from_millis(10)));
+    let provider = Arc::new(HangingProvider { dropped: dropped.clone() });
+    let err = caches.get_object_once(provider, "ledgers", "jxA.json", None, &timed_out).await.unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(TallyError::DeadlineExceeded)));
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+  }
+
+  #[tokio::test]
+  async fn test_get_object_once_dropped() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let provider = Arc::new(HangingProvider { dropped: dropped.clone() });
+    let caches = Caches::build(1);
+
+    // Dropping the call's future, as axum does when a client disconnects,
+    // aborts the download it alone waited on.
+    let download = tokio::spawn({
+      let caches = caches.clone();
+      async move { caches.get_object_once(provider, "ledgers", "jxA.json", None, &RequestDeadline::default()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(caches.object_downloads.lock().unwrap().len(), 1);
+    download.abort();
+    assert!(download.await.unwrap_err().is_cancelled());
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    assert!(caches.object_downloads.lock().unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_ledger_cache_hits_and_eviction() {
+    let caches = Caches::build(1);
+
+    assert!(caches.get_ledger("a").await.is_none());
+    caches.ledger.insert("a".to_string(), Arc::new(Ledger(Vec::new()))).await;
+    assert!(caches.get_ledger("a").await.is_some());
+
+    caches.ledger.insert("b".to_string(), Arc::new(Ledger(Vec::new()))).await;
+    caches.ledger.run_pending_tasks().await;
+    assert_eq!(caches.ledger.entry_count(), 1);
+
+    let stats = caches.ledger_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
   }
 }
@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time for proposal status and window checks, so it
+/// can be pinned in tests.
+pub trait Clock {
+  fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> DateTime<Utc> {
+    Utc::now()
+  }
+}
+
+/// A clock that always reads the same time.
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+  fn now(&self) -> DateTime<Utc> {
+    self.0
+  }
+}
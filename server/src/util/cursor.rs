@@ -0,0 +1,73 @@
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+
+/// Encodes the position of the last item on a page as a cursor.
+///
+/// Clients should treat cursors as opaque: their format may change between
+/// releases. `kind` names the listing, so a cursor from one isn't accepted by
+/// another.
+pub fn encode_cursor(kind: &str, position: &str) -> String {
+  URL_SAFE_NO_PAD.encode(format!("{kind}:{position}"))
+}
+
+/// The position encoded in a cursor from the `kind` listing.
+pub fn decode_cursor(kind: &str, cursor: &str) -> Result<String> {
+  let decoded = URL_SAFE_NO_PAD.decode(cursor).ok().and_then(|bytes| String::from_utf8(bytes).ok());
+  match decoded.as_deref().and_then(|decoded| decoded.strip_prefix(kind)?.strip_prefix(':')) {
+    Some(position) => Ok(position.to_string()),
+    None => bail!("Invalid {} cursor", kind),
+  }
+}
+
+/// Like `decode_cursor`, for positions made of a number and a string, such
+/// as a block height and transaction hash.
+pub fn decode_numbered_cursor(kind: &str, cursor: &str) -> Result<(i64, String)> {
+  let position = decode_cursor(kind, cursor)?;
+  let (number, rest) = position.split_once(':').with_context(|| format!("Invalid {kind} cursor"))?;
+  Ok((number.parse().with_context(|| format!("Invalid {kind} cursor"))?, rest.to_string()))
+}
+
+/// The first `limit` of `items` whose key comes after `after`.
+///
+/// Also returns the key of the last of them if more items follow. `items`
+/// must be sorted by `key`, so pages stay put when items are added elsewhere
+/// in the listing.
+pub fn page_after<T, K: Ord>(
+  mut items: Vec<T>,
+  key: impl Fn(&T) -> K,
+  after: Option<&K>,
+  limit: usize,
+) -> (Vec<T>, Option<K>) {
+  let start = after.map_or(0, |after| items.partition_point(|item| key(item) <= *after));
+  let more = items.len() - start > limit;
+  let page: Vec<T> = items.drain(start ..).take(limit).collect();
+  let next = match more {
+    true => page.last().map(&key),
+    false => None,
+  };
+  (page, next)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cursor_round_trip() {
+    let cursor = encode_cursor("votes", "100:CkpA");
+    assert_eq!(decode_numbered_cursor("votes", &cursor).unwrap(), (100, "CkpA".to_string()));
+    assert!(decode_cursor("proposals", &cursor).is_err());
+    assert!(decode_cursor("votes", "not a cursor").is_err());
+    assert!(decode_numbered_cursor("votes", &encode_cursor("votes", "x:CkpA")).is_err());
+  }
+
+  #[test]
+  fn test_page_after() {
+    let items = vec![1, 3, 5, 7, 9];
+    assert_eq!(page_after(items.clone(), |item| *item, None, 2), (vec![1, 3], Some(3)));
+    assert_eq!(page_after(items.clone(), |item| *item, Some(&3), 2), (vec![5, 7], Some(7)));
+    // A key that's no longer listed still resumes after its position.
+    assert_eq!(page_after(items.clone(), |item| *item, Some(&6), 2), (vec![7, 9], None));
+    assert_eq!(page_after(items, |item| *item, Some(&9), 2), (vec![], None));
+  }
+}
@@ -0,0 +1,74 @@
+use std::sync::{
+  Arc,
+  atomic::{AtomicUsize, Ordering},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds the archive queries in flight across all tally work. Further
+/// queries queue for a permit instead of failing, so bulk tallies don't
+/// exhaust the connection pool.
+#[derive(Clone)]
+pub struct QueryLimiter {
+  permits: Arc<Semaphore>,
+  queued: Arc<AtomicUsize>,
+}
+
+impl QueryLimiter {
+  pub fn new(max_in_flight: usize) -> Self {
+    QueryLimiter { permits: Arc::new(Semaphore::new(max_in_flight)), queued: Arc::default() }
+  }
+
+  /// Waits for a permit; the query may run while it is held.
+  pub async fn acquire(&self) -> OwnedSemaphorePermit {
+    let _queued = QueuedGuard::new(&self.queued);
+    self.permits.clone().acquire_owned().await.expect("archive query semaphore closed")
+  }
+
+  /// Queries waiting for a permit.
+  pub fn queued(&self) -> usize {
+    self.queued.load(Ordering::Relaxed)
+  }
+}
+
+/// Counts a waiting query for as long as it waits, even if the wait is
+/// cancelled.
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl<'a> QueuedGuard<'a> {
+  fn new(queued: &'a AtomicUsize) -> Self {
+    queued.fetch_add(1, Ordering::Relaxed);
+    QueuedGuard(queued)
+  }
+}
+
+impl Drop for QueuedGuard<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_query_limiter() {
+    let limiter = QueryLimiter::new(1);
+    let permit = limiter.acquire().await;
+    assert_eq!(limiter.queued(), 0);
+
+    let waiting = tokio::spawn({
+      let limiter = limiter.clone();
+      async move { limiter.acquire().await }
+    });
+    while limiter.queued() == 0 {
+      tokio::task::yield_now().await;
+    }
+    assert!(!waiting.is_finished());
+
+    drop(permit);
+    let _permit = waiting.await.unwrap();
+    assert_eq!(limiter.queued(), 0);
+  }
+}
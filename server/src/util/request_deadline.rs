@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+use tokio_util::sync::CancellationToken;
+
+use crate::TallyError;
+
+/// When the storage work of one HTTP request should be abandoned.
+///
+/// That is once the request is cancelled, or once its deadline passes. The
+/// default never expires. A client disconnecting drops the request's futures
+/// instead, which abandons their downloads as well.
+#[derive(Clone, Default)]
+pub struct RequestDeadline {
+  cancel: CancellationToken,
+  deadline: Option<Instant>,
+}
+
+impl RequestDeadline {
+  /// Expires `timeout` from now, if given, or once cancelled.
+  pub fn new(timeout: Option<Duration>) -> Self {
+    RequestDeadline { cancel: CancellationToken::new(), deadline: timeout.map(|timeout| Instant::now() + timeout) }
+  }
+
+  pub fn cancel(&self) {
+    self.cancel.cancel();
+  }
+
+  /// Resolves once the request is cancelled or past its deadline, to why.
+  pub async fn expired(&self) -> TallyError {
+    let timeout = async {
+      match self.deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+      }
+    };
+    tokio::select! {
+      _ = self.cancel.cancelled() => TallyError::RequestCancelled,
+      _ = timeout => TallyError::DeadlineExceeded,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_request_deadline() {
+    let unbounded = RequestDeadline::default();
+    let pending = tokio::time::timeout(Duration::from_millis(10), unbounded.expired()).await;
+    assert!(pending.is_err());
+    unbounded.cancel();
+    assert!(matches!(unbounded.expired().await, TallyError::RequestCancelled));
+
+    let bounded = RequestDeadline::new(Some(Duration::from_millis(10)));
+    assert!(matches!(bounded.expired().await, TallyError::DeadlineExceeded));
+  }
+}
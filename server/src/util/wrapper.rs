@@ -1,18 +1,32 @@
 use anyhow::Result;
 use axum::{
   extract::Json,
-  http::StatusCode,
+  http::{StatusCode, header::RETRY_AFTER},
   response::{IntoResponse, Response},
 };
 use serde::Serialize;
 
+use crate::TallyError;
+
 pub struct Wrapper<T>(pub T);
 
-impl<T: Serialize, E: ToString> IntoResponse for Wrapper<Result<T, E>> {
+impl<T: Serialize> IntoResponse for Wrapper<Result<T>> {
   fn into_response(self) -> Response {
     match self.0 {
       Ok(v) => Json(v).into_response(),
-      Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+      Err(e) => error_response(e),
     }
   }
 }
+
+/// Responds with the error message, and the status of a `TallyError`; other
+/// errors are internal server errors.
+pub fn error_response(e: anyhow::Error) -> Response {
+  match e.downcast_ref::<TallyError>() {
+    Some(tally_error) => match tally_error.retry_after_secs() {
+      Some(secs) => (tally_error.status_code(), [(RETRY_AFTER, secs.to_string())], e.to_string()).into_response(),
+      None => (tally_error.status_code(), e.to_string()).into_response(),
+    },
+    None => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+  }
+}
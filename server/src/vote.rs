@@ -1,12 +1,15 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::{
+  collections::{HashMap, hash_map::Entry},
+  fmt::Write as _,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use diesel::SqlType;
 use diesel_derive_enum::DbEnum;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
-use crate::{Proposal, Wrapper, archive::FetchTransactionResult, ledger::Ledger};
+use crate::{Proposal, PublicKey, Wrapper, archive::FetchTransactionResult, ledger::Ledger};
 
 #[derive(SqlType)]
 #[diesel(postgres_type(name = "chain_status_type"))]
@@ -22,7 +25,7 @@ pub enum BlockStatus {
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct VoteWithWeight {
-  pub account: String,
+  pub account: PublicKey,
   pub hash: String,
   pub memo: String,
   pub height: i64,
@@ -34,16 +37,21 @@ pub struct VoteWithWeight {
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Vote {
-  pub account: String,
+  pub account: PublicKey,
   pub hash: String,
   pub memo: String,
   pub height: i64,
   pub status: BlockStatus,
   pub timestamp: i64,
   pub nonce: i64,
+  /// Position of the transaction within its block.
+  #[serde(default)]
+  pub sequence_no: i64,
 }
 
 impl Vote {
+  /// Votes come from the archive, which only holds valid keys, so `account`
+  /// isn't re-validated here.
   pub fn new(
     account: impl Into<String>,
     hash: impl Into<String>,
@@ -53,7 +61,21 @@ impl Vote {
     timestamp: i64,
     nonce: i64,
   ) -> Self {
-    Self { account: account.into(), hash: hash.into(), memo: memo.into(), height, status, timestamp, nonce }
+    Self {
+      account: PublicKey::new_unchecked(account),
+      hash: hash.into(),
+      memo: memo.into(),
+      height,
+      status,
+      timestamp,
+      nonce,
+      sequence_no: 0,
+    }
+  }
+
+  pub fn with_sequence_no(mut self, sequence_no: i64) -> Self {
+    self.sequence_no = sequence_no;
+    self
   }
 
   pub fn to_weighted(&self, weight: Decimal) -> VoteWithWeight {
@@ -78,17 +100,35 @@ impl Vote {
     self.status = status;
   }
 
+  /// Whether this vote supersedes `other`, from the same account. The vote in
+  /// the higher block wins. At the same height the later transaction wins:
+  /// the one with the higher nonce, as an account's transactions are applied
+  /// in nonce order, then the one further into its block (`sequence_no`),
+  /// then the one with the greater hash, so the winner never depends on the
+  /// order the archive returned the votes in.
   pub fn is_newer_than(&self, other: &Vote) -> bool {
-    self.height > other.height || (self.height == other.height && self.nonce > other.nonce)
+    self.recency() > other.recency()
+  }
+
+  /// What `is_newer_than` orders an account's votes by.
+  fn recency(&self) -> (i64, i64, i64, &str) {
+    (self.height, self.nonce, self.sequence_no, &self.hash)
   }
 
   pub fn match_decoded_memo(&mut self, key: &str) -> Option<String> {
-    if let Ok(decoded) = self.decode_memo() {
-      if decoded == key || decoded == format!("no {}", key) {
-        return Some(decoded);
+    self.match_decoded_keywords(&VoteKeywords::from_key(key))
+  }
+
+  /// Returns the decoded memo if it is one of `keywords`.
+  pub fn match_decoded_keywords(&mut self, keywords: &VoteKeywords) -> Option<String> {
+    let decoded = match self.decode_memo() {
+      Ok(decoded) => decoded,
+      Err(err) => {
+        tracing::debug!("Ignoring transaction {} with an invalid memo: {:#}", self.hash, err);
+        return None;
       }
-    }
-    None
+    };
+    keywords.choice(&decoded).map(|_| decoded)
   }
 
   pub fn match_decoded_mef_memo(&mut self, round_id: &str, proposal_id: &str) -> Option<String> {
@@ -103,54 +143,141 @@ impl Vote {
   }
 
   pub(crate) fn decode_memo(&self) -> Result<String> {
-    let decoded =
-      bs58::decode(&self.memo).into_vec().with_context(|| format!("failed to decode memo {} - bs58", &self.memo))?;
+    decode_memo_text(&self.memo)
+  }
+}
+
+/// Decodes a base58 transaction memo into its payload bytes.
+pub fn decode_memo_bytes(memo: &str) -> Result<Vec<u8>> {
+  let decoded = bs58::decode(memo).into_vec().with_context(|| format!("failed to decode memo {} - bs58", memo))?;
+
+  let value = decoded
+    .get(2)
+    .and_then(|&len| decoded.get(3 .. len as usize + 3))
+    .ok_or_else(|| anyhow!("failed to decode memo {} - truncated", memo))?;
+  Ok(value.to_vec())
+}
+
+/// Decodes a base58 transaction memo into its text. Payloads that aren't
+/// valid UTF-8 are rejected; the error shows them lossily decoded.
+pub fn decode_memo_text(memo: &str) -> Result<String> {
+  let bytes = decode_memo_bytes(memo)?;
+  String::from_utf8(bytes).map_err(|err| {
+    let lossy = String::from_utf8_lossy(err.as_bytes()).into_owned();
+    anyhow!("failed to decode memo {} - not valid UTF-8 ({:?}): {}", memo, lossy, err.utf8_error())
+  })
+}
 
-    let value = &decoded[3 .. decoded[2] as usize + 3];
+/// Whether a memo would be counted as a vote on a proposal, and why.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemoCheck {
+  pub memo: String,
+  /// Payload bytes, hex-encoded.
+  pub raw_hex: Option<String>,
+  /// Payload text, only set when it is valid UTF-8.
+  pub decoded: Option<String>,
+  /// Payload text with invalid UTF-8 sequences replaced, for diagnostics.
+  pub decoded_lossy: Option<String>,
+  pub choice: Option<VoteChoice>,
+  pub counted: bool,
+  pub reason: String,
+}
 
-    let result =
-      String::from_utf8(value.to_vec()).with_context(|| format!("failed to decode memo {} - from_utf8", &self.memo))?;
-    Ok(result)
+impl MemoCheck {
+  pub fn new(memo: &str, keywords: &VoteKeywords) -> Self {
+    let mut check = MemoCheck {
+      memo: memo.to_string(),
+      raw_hex: None,
+      decoded: None,
+      decoded_lossy: None,
+      choice: None,
+      counted: false,
+      reason: String::new(),
+    };
+    let bytes = match decode_memo_bytes(memo) {
+      Ok(bytes) => bytes,
+      Err(err) => {
+        check.reason = format!("Not a valid base58 transaction memo: {err:#}");
+        return check;
+      }
+    };
+    check.raw_hex = Some(bytes.iter().fold(String::new(), |mut hex, byte| {
+      let _ = write!(hex, "{byte:02x}");
+      hex
+    }));
+    check.decoded_lossy = Some(String::from_utf8_lossy(&bytes).into_owned());
+    let decoded = match String::from_utf8(bytes) {
+      Ok(decoded) => decoded,
+      Err(err) => {
+        check.reason = format!("Memo text is not valid UTF-8 ({}); the vote is invalid", err.utf8_error());
+        return check;
+      }
+    };
+    check.choice = keywords.choice(&decoded);
+    check.counted = check.choice.is_some();
+    check.reason = match check.choice {
+      Some(VoteChoice::Yes) => "Counts as a yes vote".to_string(),
+      Some(VoteChoice::No) => "Counts as a no vote".to_string(),
+      None => format!(
+        "'{}' matches none of the vote keywords (yes: {:?}, no: {:?}); matching ignores case and extra whitespace",
+        decoded, keywords.yes, keywords.no
+      ),
+    };
+    check.decoded = Some(decoded);
+    check
   }
 }
 
 impl From<FetchTransactionResult> for Vote {
   fn from(res: FetchTransactionResult) -> Self {
     Vote::new(res.account, res.hash, res.memo, res.height, res.status, res.timestamp, res.nonce)
+      .with_sequence_no(res.sequence_no)
   }
 }
 
+/// Votes paired with their account's stake, before weighting.
+pub type StakedVotes = Vec<(Vote, Decimal)>;
+
 impl Wrapper<Vec<Vote>> {
-  pub fn process(self, key: impl Into<String>, tip: i64) -> Wrapper<HashMap<String, Vote>> {
+  /// Deduplicates the votes matching `key` per account, keeping the newest.
+  /// Votes at least `confirmation_depth` blocks below `tip` are marked
+  /// canonical.
+  pub fn process(self, key: impl Into<String>, tip: i64, confirmation_depth: i64) -> Wrapper<HashMap<String, Vote>> {
+    self.process_keywords(&VoteKeywords::from_key(&key.into()), tip, confirmation_depth)
+  }
+
+  /// Like `process`, for votes matching `keywords`.
+  pub fn process_keywords(
+    self,
+    keywords: &VoteKeywords,
+    tip: i64,
+    confirmation_depth: i64,
+  ) -> Wrapper<HashMap<String, Vote>> {
     let mut map = HashMap::new();
-    let key = key.into();
 
     for mut vote in self.0 {
-      if let Some(memo) = vote.match_decoded_memo(&key) {
+      if let Some(memo) = vote.match_decoded_keywords(keywords) {
         vote.update_memo(memo);
 
-        if tip - vote.height >= 10 {
+        if tip - vote.height >= confirmation_depth {
           vote.update_status(BlockStatus::Canonical);
         }
 
-        match map.entry(vote.account.clone()) {
-          Entry::Vacant(e) => {
-            e.insert(vote);
-          }
-          Entry::Occupied(mut e) => {
-            let current_vote = e.get_mut();
-            if vote.is_newer_than(current_vote) {
-              *current_vote = vote;
-            }
-          }
-        }
+        keep_newest(&mut map, vote);
       }
     }
 
     Wrapper(map)
   }
 
-  pub fn process_mep(self, round_id: usize, proposal_id: usize, tip: i64) -> Wrapper<HashMap<String, Vote>> {
+  /// Like `process`, for MEF votes on `proposal_id` of round `round_id`.
+  pub fn process_mep(
+    self,
+    round_id: usize,
+    proposal_id: usize,
+    tip: i64,
+    confirmation_depth: i64,
+  ) -> Wrapper<HashMap<String, Vote>> {
     let mut map = HashMap::new();
     let proposal_id_str = proposal_id.to_string();
     let round_id_str = round_id.to_string();
@@ -159,61 +286,106 @@ impl Wrapper<Vec<Vote>> {
       if let Some(memo) = vote.match_decoded_mef_memo(&round_id_str, &proposal_id_str) {
         vote.update_memo(memo);
 
-        if tip - vote.height >= 10 {
+        if tip - vote.height >= confirmation_depth {
           vote.update_status(BlockStatus::Canonical);
         }
 
-        match map.entry(vote.account.clone()) {
-          Entry::Vacant(e) => {
-            e.insert(vote);
-          }
-          Entry::Occupied(mut e) => {
-            let current_vote = e.get_mut();
-            if vote.is_newer_than(current_vote) {
-              *current_vote = vote;
-            }
-          }
-        }
+        keep_newest(&mut map, vote);
       }
     }
 
     Wrapper(map)
   }
 
-  pub fn into_weighted(self, proposal: &Proposal, ledger: &Ledger, tip: i64) -> Wrapper<Vec<VoteWithWeight>> {
+  pub fn into_weighted(
+    self,
+    proposal: &Proposal,
+    ledger: &Ledger,
+    tip: i64,
+    confirmation_depth: i64,
+  ) -> Wrapper<Vec<VoteWithWeight>> {
+    self.into_weighted_by(proposal, |_| ledger, tip, confirmation_depth)
+  }
+
+  /// Like `into_weighted`, weighting each counted vote by the ledger
+  /// `ledger_for` picks for it.
+  pub fn into_weighted_by<'a>(
+    self,
+    proposal: &Proposal,
+    ledger_for: impl Fn(&Vote) -> &'a Ledger,
+    tip: i64,
+    confirmation_depth: i64,
+  ) -> Wrapper<Vec<VoteWithWeight>> {
+    self.into_weighted_above(proposal, ledger_for, tip, confirmation_depth, None).0
+  }
+
+  /// Like `into_weighted_by`, but sets apart the votes of accounts whose
+  /// stake is below `min_stake`: returns the counted votes, then those.
+  pub fn into_weighted_above<'a>(
+    self,
+    proposal: &Proposal,
+    ledger_for: impl Fn(&Vote) -> &'a Ledger,
+    tip: i64,
+    confirmation_depth: i64,
+    min_stake: Option<Decimal>,
+  ) -> (Wrapper<Vec<VoteWithWeight>>, Vec<VoteWithWeight>) {
+    let weigh = |votes: StakedVotes| -> Vec<VoteWithWeight> {
+      votes.iter().map(|(vote, stake)| vote.to_weighted(proposal.weighting.apply(*stake))).collect()
+    };
+    let (counted, below_threshold) = self.into_staked_above(proposal, ledger_for, tip, confirmation_depth, min_stake);
+    (Wrapper(weigh(counted)), weigh(below_threshold))
+  }
+
+  /// Like `into_weighted_above`, pairing each vote with its account's stake
+  /// before weighting.
+  pub fn into_staked_above<'a>(
+    self,
+    proposal: &Proposal,
+    ledger_for: impl Fn(&Vote) -> &'a Ledger,
+    tip: i64,
+    confirmation_depth: i64,
+    min_stake: Option<Decimal>,
+  ) -> (StakedVotes, StakedVotes) {
     tracing::info!("Processing votes for proposal: {}", proposal.key);
-    let votes = self.process(&proposal.key, tip);
+    let votes = self.process_keywords(&proposal.vote_keywords(), tip, confirmation_depth);
     tracing::info!("Processed {} votes for proposal: {}", votes.0.len(), proposal.key);
 
-    let votes_with_stake: Vec<VoteWithWeight> = votes
-      .0
-      .iter()
-      .map(|(account, vote)| {
-        // Get stake or default to 0 if not found
-        let stake = ledger.get_stake_weight(&votes, &proposal.version, account).unwrap_or(Decimal::ZERO);
-
-        vote.to_weighted(stake)
-      })
-      .collect();
+    let mut votes_with_stake = Vec::with_capacity(votes.0.len());
+    let mut below_threshold = Vec::new();
+    for (account, vote) in &votes.0 {
+      // Get stake or default to 0 if not found
+      let stake = ledger_for(vote).get_stake_weight(&votes, &proposal.version, account).unwrap_or(Decimal::ZERO);
+      match min_stake {
+        Some(min_stake) if stake < min_stake => below_threshold.push((vote.clone(), stake)),
+        _ => votes_with_stake.push((vote.clone(), stake)),
+      }
+    }
 
-    Wrapper(votes_with_stake)
+    (votes_with_stake, below_threshold)
   }
 
+  /// Weights MEF votes by stake, leaving out accounts whose stake is below
+  /// `min_stake`.
   pub fn into_weighted_mep(
     self,
     round_id: usize,
     proposal_id: usize,
     ledger: &Ledger,
     tip: i64,
+    confirmation_depth: i64,
+    min_stake: Option<Decimal>,
   ) -> Wrapper<Vec<VoteWithWeight>> {
-    let votes = self.process_mep(round_id, proposal_id, tip);
+    let votes = self.process_mep(round_id, proposal_id, tip, confirmation_depth);
 
     let votes_with_stake: Vec<VoteWithWeight> = votes
       .0
       .iter()
       .filter_map(|(account, vote)| {
         let stake = ledger.get_stake_weight_mep(&votes, account).ok()?;
-        Some(vote.to_weighted(stake))
+        match min_stake {
+          Some(min_stake) if stake < min_stake => None,
+          _ => Some(vote.to_weighted(stake)),
+        }
       })
       .collect();
 
@@ -221,6 +393,38 @@ impl Wrapper<Vec<Vote>> {
   }
 }
 
+/// Keeps `vote` in `map` if it is its account's newest so far. Votes of one
+/// account at the same block height are logged, as only the tie-break of
+/// `Vote::is_newer_than` decides between them.
+fn keep_newest(map: &mut HashMap<String, Vote>, vote: Vote) {
+  match map.entry(vote.account.to_string()) {
+    Entry::Vacant(e) => {
+      e.insert(vote);
+    }
+    Entry::Occupied(mut e) => {
+      let current_vote = e.get_mut();
+      let newer = vote.is_newer_than(current_vote);
+      if vote.height == current_vote.height && vote.hash != current_vote.hash {
+        let (kept, dropped) = if newer { (&vote, &*current_vote) } else { (&*current_vote, &vote) };
+        tracing::warn!(
+          "Account {} voted twice at height {}: keeping {} (nonce {}, seq {}) over {} (nonce {}, seq {})",
+          kept.account,
+          kept.height,
+          kept.hash,
+          kept.nonce,
+          kept.sequence_no,
+          dropped.hash,
+          dropped.nonce,
+          dropped.sequence_no
+        );
+      }
+      if newer {
+        *current_vote = vote;
+      }
+    }
+  }
+}
+
 impl Wrapper<HashMap<String, Vote>> {
   pub fn to_vec(&self) -> Wrapper<Vec<Vote>> {
     Wrapper(self.0.values().cloned().collect())
@@ -238,6 +442,125 @@ impl Wrapper<Vec<VoteWithWeight>> {
     self.0.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
     self
   }
+
+  /// Buckets the votes by timestamp and returns cumulative yes/no counts and
+  /// stake per bucket, from the first bucket containing a vote to the last.
+  pub fn cumulative_timeseries(&self, bucket: TimeseriesBucket, keywords: &VoteKeywords) -> Vec<VoteTimeseriesPoint> {
+    let bucket_ms = bucket.duration_ms();
+    let mut votes: Vec<&VoteWithWeight> = self.0.iter().collect();
+    votes.sort_by_key(|vote| vote.timestamp);
+
+    let (Some(first), Some(last)) = (votes.first(), votes.last()) else {
+      return Vec::new();
+    };
+    let first_bucket = first.timestamp - first.timestamp.rem_euclid(bucket_ms);
+    let last_bucket = last.timestamp - last.timestamp.rem_euclid(bucket_ms);
+
+    let mut points = Vec::new();
+    let mut current = VoteTimeseriesPoint { bucket_start: first_bucket, ..Default::default() };
+    let mut votes = votes.into_iter().peekable();
+    while current.bucket_start <= last_bucket {
+      while let Some(vote) = votes.next_if(|vote| vote.timestamp < current.bucket_start + bucket_ms) {
+        if keywords.choice(&vote.memo) == Some(VoteChoice::No) {
+          current.no_votes += 1;
+          current.no_stake += vote.weight;
+        } else {
+          current.yes_votes += 1;
+          current.yes_stake += vote.weight;
+        }
+      }
+      points.push(current.clone());
+      current.bucket_start += bucket_ms;
+    }
+    points
+  }
+}
+
+/// Memos counted as a yes or a no vote for a proposal. Memos are compared
+/// ignoring case and runs of whitespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteKeywords {
+  yes: Vec<String>,
+  no: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteChoice {
+  Yes,
+  No,
+}
+
+impl VoteKeywords {
+  pub fn new(yes: &[String], no: &[String]) -> Self {
+    VoteKeywords {
+      yes: yes.iter().map(|keyword| normalize_memo(keyword)).collect(),
+      no: no.iter().map(|keyword| normalize_memo(keyword)).collect(),
+    }
+  }
+
+  /// The default scheme: `<key>` votes yes and `no <key>` votes no.
+  pub fn from_key(key: &str) -> Self {
+    Self::new(&[key.to_string()], &[format!("no {key}")])
+  }
+
+  pub fn choice(&self, memo: &str) -> Option<VoteChoice> {
+    let memo = normalize_memo(memo);
+    if self.no.contains(&memo) {
+      Some(VoteChoice::No)
+    } else if self.yes.contains(&memo) {
+      Some(VoteChoice::Yes)
+    } else {
+      None
+    }
+  }
+
+  /// Keywords listed as both yes and no.
+  pub fn overlapping(&self) -> Vec<&str> {
+    self.yes.iter().filter(|keyword| self.no.contains(keyword)).map(String::as_str).collect()
+  }
+}
+
+pub(crate) fn normalize_memo(memo: &str) -> String {
+  memo.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeseriesBucket {
+  Hour,
+  Day,
+}
+
+impl TimeseriesBucket {
+  pub fn duration_ms(&self) -> i64 {
+    match self {
+      TimeseriesBucket::Hour => 60 * 60 * 1000,
+      TimeseriesBucket::Day => 24 * 60 * 60 * 1000,
+    }
+  }
+}
+
+impl std::str::FromStr for TimeseriesBucket {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "hour" => Ok(TimeseriesBucket::Hour),
+      "day" => Ok(TimeseriesBucket::Day),
+      _ => Err(anyhow::anyhow!("Unsupported bucket '{s}'. Supported buckets: hour, day")),
+    }
+  }
+}
+
+/// Cumulative vote totals at the end of a time bucket.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct VoteTimeseriesPoint {
+  pub bucket_start: i64,
+  pub yes_votes: usize,
+  pub no_votes: usize,
+  pub yes_stake: Decimal,
+  pub no_stake: Decimal,
 }
 
 #[cfg(test)]
@@ -282,7 +605,7 @@ mod tests {
   #[test]
   fn test_process_votes() {
     let votes = get_test_votes();
-    let binding = Wrapper(votes).process("cftest-2", 129);
+    let binding = Wrapper(votes).process("cftest-2", 129, 10);
     let processed = binding.to_vec().0;
 
     assert_eq!(processed.len(), 2);
@@ -305,6 +628,135 @@ mod tests {
     assert_eq!(a2.nonce, 2);
   }
 
+  #[test]
+  fn test_process_same_block_votes() {
+    let no_memo = "E4YjFkHVUXbEAkQcUrAEcS1fqvbncnn9Tuz2Jtb1Uu79zY9UAJRpd";
+    let yes_memo = "E4YdLeukpqzqyBAxujeELx9SZWoUW9MhcUfnGHF9PhQmxTJcpmj7j";
+    // One account voting no, then yes further into the same block.
+    let no = Vote::new("1", "CkpNo", no_memo, 110, BlockStatus::Pending, 110, 3).with_sequence_no(2);
+    let yes = Vote::new("1", "CkpYes", yes_memo, 110, BlockStatus::Pending, 110, 4).with_sequence_no(5);
+    for votes in [vec![no.clone(), yes.clone()], vec![yes.clone(), no.clone()]] {
+      let processed = Wrapper(votes).process("cftest-2", 129, 10);
+      assert_eq!(processed.0["1"].hash, "CkpYes");
+      assert_eq!(processed.0["1"].memo, "cftest-2");
+    }
+
+    // Without a nonce to tell them apart, the position in the block decides,
+    // then the hash.
+    let first = Vote::new("1", "CkpB", yes_memo, 110, BlockStatus::Pending, 110, 4).with_sequence_no(1);
+    let second = Vote::new("1", "CkpA", no_memo, 110, BlockStatus::Pending, 110, 4).with_sequence_no(2);
+    assert!(second.is_newer_than(&first) && !first.is_newer_than(&second));
+    let tied = second.clone().with_sequence_no(1);
+    assert!(first.is_newer_than(&tied) && !tied.is_newer_than(&first));
+  }
+
+  #[test]
+  fn test_cumulative_timeseries() {
+    const HOUR: i64 = 60 * 60 * 1000;
+    let votes = Wrapper(vec![
+      Vote::new("1", "1", "cftest-2", 100, BlockStatus::Canonical, HOUR + 10, 1).to_weighted(Decimal::from(5)),
+      Vote::new("2", "2", "no cftest-2", 101, BlockStatus::Canonical, HOUR + 20, 1).to_weighted(Decimal::from(3)),
+      Vote::new("3", "3", "cftest-2", 102, BlockStatus::Canonical, 3 * HOUR + 5, 1).to_weighted(Decimal::from(2)),
+    ]);
+
+    let points = votes.cumulative_timeseries(TimeseriesBucket::Hour, &VoteKeywords::from_key("cftest-2"));
+    assert_eq!(points.len(), 3);
+
+    assert_eq!(points[0].bucket_start, HOUR);
+    assert_eq!((points[0].yes_votes, points[0].no_votes), (1, 1));
+    assert_eq!((points[0].yes_stake, points[0].no_stake), (Decimal::from(5), Decimal::from(3)));
+
+    assert_eq!(points[1].bucket_start, 2 * HOUR);
+    assert_eq!((points[1].yes_votes, points[1].no_votes), (1, 1));
+
+    assert_eq!(points[2].bucket_start, 3 * HOUR);
+    assert_eq!((points[2].yes_votes, points[2].no_votes), (2, 1));
+    assert_eq!(points[2].yes_stake, Decimal::from(7));
+
+    let keywords = VoteKeywords::from_key("cftest-2");
+    assert!(Wrapper(Vec::<VoteWithWeight>::new()).cumulative_timeseries(TimeseriesBucket::Day, &keywords).is_empty());
+  }
+
+  #[test]
+  fn test_vote_keywords() {
+    let default = VoteKeywords::from_key("MIP1");
+    assert_eq!(default.choice("mip1"), Some(VoteChoice::Yes));
+    assert_eq!(default.choice("  No   MIP1 "), Some(VoteChoice::No));
+    assert_eq!(default.choice("mip10"), None);
+    assert_eq!(default.choice("no mip10"), None);
+    assert_eq!(default.choice("not mip1"), None);
+
+    let custom = VoteKeywords::new(&["approve mip7".to_string(), "mip7".to_string()], &["reject MIP7".to_string()]);
+    assert_eq!(custom.choice("Approve  MIP7"), Some(VoteChoice::Yes));
+    assert_eq!(custom.choice("MIP7"), Some(VoteChoice::Yes));
+    assert_eq!(custom.choice("reject mip7"), Some(VoteChoice::No));
+    assert_eq!(custom.choice("no mip7"), None);
+    assert!(custom.overlapping().is_empty());
+
+    let overlapping = VoteKeywords::new(&["mip7".to_string()], &["MIP7".to_string()]);
+    assert_eq!(overlapping.overlapping(), ["mip7"]);
+  }
+
+  #[test]
+  fn test_memo_check() {
+    let keywords = VoteKeywords::from_key("cftest-2");
+
+    let no = MemoCheck::new("E4YjFkHVUXbEAkQcUrAEcS1fqvbncnn9Tuz2Jtb1Uu79zY9UAJRpd", &keywords);
+    assert_eq!(no.decoded.as_deref(), Some("no cftest-2"));
+    assert_eq!((no.choice, no.counted), (Some(VoteChoice::No), true));
+
+    let other = MemoCheck::new("E4Yf92G48v8FApR4EWQq3iKb2vZkHHxZHPaZ73NQNBXmHeXNzHHSp", &keywords);
+    assert_eq!(other.decoded.as_deref(), Some("Payment#0"));
+    assert!(!other.counted);
+    assert!(other.reason.contains("cftest-2"));
+
+    // Not base58, and valid base58 too short to hold a memo.
+    for memo in ["not-a-memo!", "1"] {
+      let invalid = MemoCheck::new(memo, &keywords);
+      assert_eq!((invalid.decoded, invalid.counted), (None, false));
+      assert!(invalid.reason.starts_with("Not a valid base58 transaction memo"));
+    }
+  }
+
+  /// Base58 memo holding `payload`, with the version and length bytes real
+  /// memos start with.
+  fn encode_memo(payload: &[u8]) -> String {
+    let mut bytes = vec![0x14, 0x01, payload.len() as u8];
+    bytes.extend(payload);
+    bytes.resize(34, 0);
+    bs58::encode(bytes).into_string()
+  }
+
+  #[test]
+  fn test_non_utf8_memo() {
+    let keywords = VoteKeywords::from_key("cftest-2");
+    let memo = encode_memo(b"no cftest-2\xff");
+    assert_eq!(decode_memo_bytes(&memo).unwrap(), b"no cftest-2\xff");
+    let err = decode_memo_text(&memo).unwrap_err().to_string();
+    assert!(err.contains("not valid UTF-8") && err.contains("no cftest-2\u{fffd}"), "{err}");
+
+    let check = MemoCheck::new(&memo, &keywords);
+    assert_eq!(check.raw_hex.as_deref(), Some("6e6f206366746573742d32ff"));
+    assert_eq!(check.decoded_lossy.as_deref(), Some("no cftest-2\u{fffd}"));
+    assert_eq!((check.decoded, check.choice, check.counted), (None, None, false));
+    assert!(check.reason.starts_with("Memo text is not valid UTF-8"));
+
+    let valid = MemoCheck::new(&encode_memo(b"cftest-2"), &keywords);
+    assert_eq!(valid.raw_hex.as_deref(), Some("6366746573742d32"));
+    assert_eq!(valid.decoded_lossy, valid.decoded);
+    assert!(valid.counted);
+
+    // The invalid memo is not counted, even though it is the account's newest.
+    let votes = vec![
+      Vote::new("1", "1", encode_memo(b"cftest-2"), 100, BlockStatus::Pending, 100, 1),
+      Vote::new("1", "2", &memo, 110, BlockStatus::Pending, 110, 2),
+      Vote::new("2", "3", encode_memo(&[0xc3, 0x28]), 110, BlockStatus::Pending, 110, 1),
+    ];
+    let processed = Wrapper(votes).process_keywords(&keywords, 120, 10);
+    assert_eq!(processed.0.len(), 1);
+    assert_eq!(processed.0["1"].hash, "1");
+  }
+
   fn get_test_votes() -> Vec<Vote> {
     vec![
       Vote::new("1", "1", "E4YjFkHVUXbEAkQcUrAEcS1fqvbncnn9Tuz2Jtb1Uu79zY9UAJRpd", 100, BlockStatus::Pending, 100, 1),
@@ -363,7 +815,7 @@ mod tests {
   #[test]
   fn test_process_mep_votes() {
     let votes = get_test_mep_votes();
-    let binding = Wrapper(votes).process_mep(1, 1, 130);
+    let binding = Wrapper(votes).process_mep(1, 1, 130, 10);
     let processed = binding.to_vec().0;
 
     assert_eq!(processed.len(), 11);
@@ -384,6 +836,14 @@ mod tests {
     assert_eq!(a2.height, 341719);
     assert_eq!(a2.status, BlockStatus::Pending);
     assert_eq!(a2.nonce, 2);
+
+    // Votes are canonical once `confirmation_depth` blocks deep.
+    let status_at = |tip, confirmation_depth| {
+      let processed = Wrapper(get_test_mep_votes()).process_mep(1, 1, tip, confirmation_depth);
+      processed.0["2"].status
+    };
+    assert_eq!(status_at(341729, 10), BlockStatus::Canonical);
+    assert_eq!(status_at(341729, 11), BlockStatus::Pending);
   }
 
   fn get_test_mep_votes() -> Vec<Vote> {